@@ -0,0 +1,387 @@
+// Background analysis of the file that was just opened: full probe, keyframe index, thumbnail strip
+// and waveform, run one after another on a worker thread so later preflights and snapping are instant.
+//
+// Every run gets a generation number. Opening another file (or `cancel`) bumps the generation; the
+// old run notices between stages and inside long scans, kills its ffmpeg/ffprobe child and stops.
+// Finished stages stay in the probe, keyframe and disk caches, so reopening a file only redoes what
+// was interrupted.
+
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use tauri::Emitter;
+
+const THUMBNAIL_NAMESPACE: &str = "thumbnails";
+const WAVEFORM_NAMESPACE: &str = "waveform";
+pub const THUMBNAIL_COUNT: usize = 24;
+const THUMBNAIL_WIDTH: u32 = 160;
+const WAVEFORM_SAMPLE_RATE: u32 = 8000;
+const WAVEFORM_BUCKETS: u64 = 2000;
+
+static GENERATION: AtomicU64 = AtomicU64::new(0);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AnalysisStage {
+  Probe,
+  Keyframes,
+  Thumbnails,
+  Waveform,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StageStatus {
+  Running,
+  Done,
+  // Nothing to do for this file (e.g. waveform without an audio stream).
+  Skipped,
+  Failed,
+  Cancelled,
+}
+
+/// Payload of the `analysis_progress` event.
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct AnalysisProgress<'a> {
+  generation: u64,
+  input_path: &'a str,
+  stage: AnalysisStage,
+  status: StageStatus,
+  percent: f64,
+  from_cache: bool,
+  error: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ThumbnailStrip {
+  pub width: u32,
+  // Timestamp of each thumbnail; `images` has one JPEG per entry.
+  pub times: Vec<f64>,
+  pub images: Vec<Vec<u8>>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Waveform {
+  pub seconds_per_bucket: f64,
+  // Peak absolute amplitude per bucket of the first audio stream, scaled to 0-255.
+  pub peaks: Vec<u8>,
+}
+
+// What the stages after the probe need to know about the file.
+#[derive(Clone, Copy, Default)]
+struct FileFacts {
+  duration_seconds: Option<f64>,
+  has_video: bool,
+  has_audio: bool,
+}
+
+fn is_current(generation: u64) -> bool {
+  GENERATION.load(Ordering::SeqCst) == generation
+}
+
+/// Stop whatever analysis is running.
+pub fn cancel() {
+  GENERATION.fetch_add(1, Ordering::SeqCst);
+}
+
+/// Cancel the previous run and start analyzing `input_path` in the background. Returns the run's
+/// generation, which tags its `analysis_progress` events.
+/// `input_path` must already be normalized and validated.
+pub fn start(
+  window: tauri::Window,
+  input_path: String,
+  ffmpeg_path: PathBuf,
+  ffprobe_path: PathBuf,
+  ffmpeg_bin_dir_used: String,
+) -> u64 {
+  let generation = GENERATION.fetch_add(1, Ordering::SeqCst) + 1;
+  std::thread::spawn(move || {
+    run(&window, generation, &input_path, &ffmpeg_path, &ffprobe_path, &ffmpeg_bin_dir_used)
+  });
+  generation
+}
+
+fn run(
+  window: &tauri::Window,
+  generation: u64,
+  input_path: &str,
+  ffmpeg_path: &Path,
+  ffprobe_path: &Path,
+  ffmpeg_bin_dir_used: &str,
+) {
+  let key = crate::probe_cache_key_best_effort(input_path);
+  let emit = |stage, status, percent, from_cache, error: Option<String>| {
+    let _ = window.emit(
+      "analysis_progress",
+      AnalysisProgress { generation, input_path, stage, status, percent, from_cache, error },
+    );
+  };
+
+  let mut facts = FileFacts::default();
+  for stage in [
+    AnalysisStage::Probe,
+    AnalysisStage::Keyframes,
+    AnalysisStage::Thumbnails,
+    AnalysisStage::Waveform,
+  ] {
+    if !is_current(generation) {
+      emit(stage, StageStatus::Cancelled, 0.0, false, None);
+      continue;
+    }
+    emit(stage, StageStatus::Running, 0.0, false, None);
+
+    let mut on_progress = |percent: f64| {
+      emit(stage, StageStatus::Running, percent, false, None);
+      is_current(generation)
+    };
+    // Ok(None) means the stage does not apply to this file; Ok(Some(hit)) reports a cache hit.
+    let result = match stage {
+      AnalysisStage::Probe => crate::probe_full(input_path, ffprobe_path, ffmpeg_bin_dir_used).map(|probe| {
+        facts = FileFacts {
+          duration_seconds: probe.data.duration_seconds,
+          has_video: !probe.data.video_streams.is_empty(),
+          has_audio: !probe.data.audio_streams.is_empty(),
+        };
+        Some(probe.cache_hit)
+      }),
+      AnalysisStage::Keyframes if facts.has_video => {
+        crate::keyframes::get_or_build(ffprobe_path, input_path, &key, facts.duration_seconds, on_progress)
+          .map(|(_, from_cache)| Some(from_cache))
+      }
+      AnalysisStage::Thumbnails if facts.has_video => {
+        build_thumbnails(ffmpeg_path, input_path, &key, facts.duration_seconds, &mut on_progress).map(Some)
+      }
+      AnalysisStage::Waveform if facts.has_audio => {
+        build_waveform(ffmpeg_path, input_path, &key, facts.duration_seconds, &mut on_progress).map(Some)
+      }
+      _ => Ok(None),
+    };
+
+    match result {
+      Ok(Some(from_cache)) => emit(stage, StageStatus::Done, 100.0, from_cache, None),
+      Ok(None) => emit(stage, StageStatus::Skipped, 100.0, false, None),
+      Err(_) if !is_current(generation) => emit(stage, StageStatus::Cancelled, 0.0, false, None),
+      Err(e) => {
+        eprintln!("[ANALYSIS] {stage:?} failed for {input_path}: {e}");
+        emit(stage, StageStatus::Failed, 0.0, false, Some(e));
+      }
+    }
+  }
+}
+
+fn thumbnail_key(key: &str, index: usize) -> String {
+  format!("{key}|{index}/{THUMBNAIL_COUNT}|{THUMBNAIL_WIDTH}")
+}
+
+fn thumbnail_times(duration_seconds: f64) -> Vec<f64> {
+  let step = duration_seconds / THUMBNAIL_COUNT as f64;
+  (0..THUMBNAIL_COUNT).map(|i| (i as f64 + 0.5) * step).collect()
+}
+
+fn usable_duration(duration_seconds: Option<f64>) -> Result<f64, String> {
+  duration_seconds
+    .filter(|d| d.is_finite() && *d > 0.0)
+    .ok_or_else(|| "Duration is unknown".to_string())
+}
+
+// Evenly spaced JPEG frames. Each one is a separate input-seeking ffmpeg run, which only decodes
+// from the nearest keyframe, so the cost does not grow with file length.
+fn build_thumbnails(
+  ffmpeg_path: &Path,
+  input_path: &str,
+  key: &str,
+  duration_seconds: Option<f64>,
+  on_progress: &mut impl FnMut(f64) -> bool,
+) -> Result<bool, String> {
+  let duration = usable_duration(duration_seconds)?;
+  let mut all_cached = true;
+  for (i, t) in thumbnail_times(duration).into_iter().enumerate() {
+    if !on_progress(i as f64 * 100.0 / THUMBNAIL_COUNT as f64) {
+      return Err("Thumbnail extraction cancelled".to_string());
+    }
+    let entry_key = thumbnail_key(key, i);
+    if crate::disk_cache::read(THUMBNAIL_NAMESPACE, &entry_key).is_some() {
+      continue;
+    }
+    all_cached = false;
+
+    let jpeg = crate::media_backend::current().grab_frame(ffmpeg_path, input_path, t, THUMBNAIL_WIDTH)?;
+    crate::disk_cache::write(THUMBNAIL_NAMESPACE, &entry_key, &jpeg);
+  }
+  Ok(all_cached)
+}
+
+/// Thumbnails produced by a finished analysis of the file with cache key `key`.
+pub fn cached_thumbnails(key: &str, duration_seconds: Option<f64>) -> Option<ThumbnailStrip> {
+  let times = thumbnail_times(usable_duration(duration_seconds).ok()?);
+  let images = (0..THUMBNAIL_COUNT)
+    .map(|i| crate::disk_cache::read(THUMBNAIL_NAMESPACE, &thumbnail_key(key, i)))
+    .collect::<Option<Vec<_>>>()?;
+  Some(ThumbnailStrip { width: THUMBNAIL_WIDTH, times, images })
+}
+
+/// Waveform produced by a finished analysis of the file with cache key `key`.
+pub fn cached_waveform(key: &str) -> Option<Waveform> {
+  let payload = crate::disk_cache::read(WAVEFORM_NAMESPACE, key)?;
+  serde_json::from_slice(&payload).ok()
+}
+
+// Running peak of the current bucket; a bucket is flushed as soon as it holds its last sample.
+struct PeakBuckets {
+  samples_per_bucket: u64,
+  peaks: Vec<u8>,
+  peak: u16,
+  len: u64,
+  samples_read: u64,
+}
+
+impl PeakBuckets {
+  fn push(&mut self, lo: u8, hi: u8) {
+    self.peak = self.peak.max(i16::from_le_bytes([lo, hi]).unsigned_abs());
+    self.len += 1;
+    if self.len >= self.samples_per_bucket {
+      self.flush();
+    }
+  }
+
+  fn flush(&mut self) {
+    self.peaks.push((self.peak >> 7).min(255) as u8);
+    self.samples_read += self.len;
+    self.peak = 0;
+    self.len = 0;
+  }
+}
+
+// Decodes the first audio stream to low-rate mono PCM and keeps one peak per bucket.
+fn build_waveform(
+  ffmpeg_path: &Path,
+  input_path: &str,
+  key: &str,
+  duration_seconds: Option<f64>,
+  on_progress: &mut impl FnMut(f64) -> bool,
+) -> Result<bool, String> {
+  if cached_waveform(key).is_some() {
+    return Ok(true);
+  }
+  let waveform = extract_waveform(ffmpeg_path, input_path, duration_seconds, on_progress)?;
+  if let Ok(payload) = serde_json::to_vec(&waveform) {
+    crate::disk_cache::write(WAVEFORM_NAMESPACE, key, &payload);
+  }
+  Ok(false)
+}
+
+fn extract_waveform(
+  ffmpeg_path: &Path,
+  input_path: &str,
+  duration_seconds: Option<f64>,
+  on_progress: &mut impl FnMut(f64) -> bool,
+) -> Result<Waveform, String> {
+  let duration = usable_duration(duration_seconds)?;
+  let total_samples = (duration * WAVEFORM_SAMPLE_RATE as f64).ceil() as u64;
+  let samples_per_bucket = total_samples.div_ceil(WAVEFORM_BUCKETS).max(1);
+
+  let args = crate::media_backend::args(&[
+    "-v",
+    "error",
+    "-nostdin",
+    "-i",
+    input_path,
+    "-map",
+    "0:a:0",
+    "-vn",
+    "-sn",
+    "-dn",
+    "-ac",
+    "1",
+    "-ar",
+    &WAVEFORM_SAMPLE_RATE.to_string(),
+    "-f",
+    "s16le",
+    "-",
+  ]);
+
+  let mut buckets = PeakBuckets {
+    samples_per_bucket,
+    peaks: Vec::with_capacity(WAVEFORM_BUCKETS as usize + 1),
+    peak: 0,
+    len: 0,
+    samples_read: 0,
+  };
+  // A sample can straddle two reads.
+  let mut carry: Option<u8> = None;
+  let mut last_percent = -1.0;
+  let output = crate::media_backend::current().run_encode(ffmpeg_path, &args, &mut |mut bytes| {
+    if let (Some(lo), Some((&hi, rest))) = (carry, bytes.split_first()) {
+      buckets.push(lo, hi);
+      carry = None;
+      bytes = rest;
+    }
+    let mut chunks = bytes.chunks_exact(2);
+    for pair in chunks.by_ref() {
+      buckets.push(pair[0], pair[1]);
+    }
+    if let Some(&lo) = chunks.remainder().first() {
+      carry = Some(lo);
+    }
+
+    let percent = (buckets.samples_read as f64 / total_samples as f64 * 100.0).clamp(0.0, 99.0).floor();
+    if percent > last_percent {
+      last_percent = percent;
+      return on_progress(percent);
+    }
+    true
+  })?;
+  output.into_stdout("ffmpeg waveform extraction failed")?;
+  if buckets.len > 0 {
+    buckets.flush();
+  }
+
+  Ok(Waveform {
+    seconds_per_bucket: samples_per_bucket as f64 / WAVEFORM_SAMPLE_RATE as f64,
+    peaks: buckets.peaks,
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::media_backend::{install_for_test, FakeBackend};
+  use std::sync::Arc;
+
+  // One second of 8 kHz PCM whose amplitude steps up every 100 samples.
+  fn stepped_pcm() -> Vec<u8> {
+    (0..WAVEFORM_SAMPLE_RATE as usize)
+      .flat_map(|i| (((i / 100) as i16 + 1) * 400).to_le_bytes())
+      .collect()
+  }
+
+  fn waveform_from_chunks(chunk_sizes: &[usize]) -> Waveform {
+    let pcm = stepped_pcm();
+    let mut chunks = Vec::new();
+    let mut rest = pcm.as_slice();
+    for size in chunk_sizes.iter().cycle() {
+      if rest.is_empty() {
+        break;
+      }
+      let (chunk, tail) = rest.split_at((*size).min(rest.len()));
+      chunks.push(chunk.to_vec());
+      rest = tail;
+    }
+    let backend = Arc::new(FakeBackend::default());
+    backend.on_encode_stdout(chunks);
+    install_for_test(backend);
+    extract_waveform(Path::new("ffmpeg"), "clip.mp4", Some(1.0), &mut |_| true).unwrap()
+  }
+
+  #[test]
+  fn waveform_buckets_survive_samples_split_across_reads() {
+    let whole = waveform_from_chunks(&[usize::MAX]);
+    assert_eq!(whole.peaks.len(), WAVEFORM_BUCKETS as usize);
+    assert!(whole.peaks.windows(2).all(|w| w[0] <= w[1]));
+
+    for sizes in [&[1][..], &[3, 5, 7], &[4095, 1, 2]] {
+      assert_eq!(waveform_from_chunks(sizes).peaks, whole.peaks, "chunks of {sizes:?}");
+    }
+  }
+}
@@ -0,0 +1,271 @@
+// Persistent cache for data that is slow to recompute: probe results, keyframe indexes, thumbnails.
+//
+// Entries live in the app cache directory as `<namespace>/<hash>.cache`. Each file starts with a
+// header line and the full key, so hash collisions and stale formats read as misses. Keys are the
+// same path|size|mtime strings as the in-memory probe cache, so an edited file never hits. Reads
+// refresh the file's mtime, which makes eviction least-recently-used across all namespaces.
+
+use serde::Serialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock, PoisonError};
+use std::time::SystemTime;
+
+const ENTRY_HEADER: &str = "clipwave-cache v1";
+const ENTRY_EXTENSION: &str = "cache";
+// Total size of all namespaces before least-recently-used entries are evicted.
+pub const MAX_CACHE_BYTES: u64 = 512 * 1024 * 1024;
+
+#[derive(Debug, Serialize)]
+pub struct NamespaceStats {
+  pub name: String,
+  pub entries: usize,
+  pub bytes: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CacheStats {
+  pub dir: String,
+  pub total_bytes: u64,
+  pub max_bytes: u64,
+  pub namespaces: Vec<NamespaceStats>,
+}
+
+// Total entry size under `root`, counted once and then kept current by writes, so a write only walks
+// the folder when the total crosses the cap.
+struct CacheSize {
+  root: PathBuf,
+  bytes: u64,
+}
+
+// Serializes writes and eviction so two exports cannot both decide to evict the same files.
+fn write_lock() -> &'static Mutex<Option<CacheSize>> {
+  static LOCK: OnceLock<Mutex<Option<CacheSize>>> = OnceLock::new();
+  LOCK.get_or_init(|| Mutex::new(None))
+}
+
+// FNV-1a: stable across builds, unlike std's DefaultHasher.
+fn fnv1a64(text: &str) -> u64 {
+  let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+  for b in text.as_bytes() {
+    hash ^= *b as u64;
+    hash = hash.wrapping_mul(0x0100_0000_01b3);
+  }
+  hash
+}
+
+fn cache_root() -> Option<PathBuf> {
+  crate::app_cache_dir()
+}
+
+fn entry_path(namespace: &str, key: &str) -> Option<PathBuf> {
+  cache_root().map(|d| d.join(namespace).join(format!("{:016x}.{ENTRY_EXTENSION}", fnv1a64(key))))
+}
+
+/// Payload stored for `key`, or None on a miss. A hit counts as a use for LRU eviction.
+pub fn read(namespace: &str, key: &str) -> Option<Vec<u8>> {
+  let path = entry_path(namespace, key)?;
+  let bytes = fs::read(&path).ok()?;
+  let header = format!("{ENTRY_HEADER}\n{key}\n");
+  let payload = bytes.strip_prefix(header.as_bytes())?.to_vec();
+  if let Ok(file) = fs::File::options().write(true).open(&path) {
+    let _ = file.set_modified(SystemTime::now());
+  }
+  Some(payload)
+}
+
+/// Store `payload` for `key`, then evict old entries if the cache is over its size cap.
+pub fn write(namespace: &str, key: &str, payload: &[u8]) {
+  write_capped(namespace, key, payload, MAX_CACHE_BYTES);
+}
+
+fn write_capped(namespace: &str, key: &str, payload: &[u8], max_bytes: u64) {
+  let (Some(root), Some(path)) = (cache_root(), entry_path(namespace, key)) else {
+    return;
+  };
+  let mut size = write_lock().lock().unwrap_or_else(PoisonError::into_inner);
+  if let Some(dir) = path.parent() {
+    let _ = fs::create_dir_all(dir);
+  }
+  let mut bytes = format!("{ENTRY_HEADER}\n{key}\n").into_bytes();
+  bytes.extend_from_slice(payload);
+  let written = bytes.len() as u64;
+  let replaced = fs::metadata(&path).map(|m| m.len()).unwrap_or(0);
+  // Write then rename so an interrupted write never leaves a truncated entry behind.
+  let tmp = path.with_extension("tmp");
+  let stored = fs::write(&tmp, bytes).is_ok() && fs::rename(&tmp, &path).is_ok();
+  if !stored {
+    let _ = fs::remove_file(&tmp);
+  }
+
+  let mut total = match size.as_ref().filter(|s| s.root == root) {
+    Some(known) if stored => known.bytes.saturating_sub(replaced) + written,
+    Some(known) => known.bytes,
+    None => list_entries(&root).iter().map(|e| e.2).sum(),
+  };
+  if total > max_bytes {
+    total = evict_to(&root, max_bytes);
+  }
+  *size = Some(CacheSize { root, bytes: total });
+}
+
+// (path, namespace, size, last use) for every entry.
+fn list_entries(root: &Path) -> Vec<(PathBuf, String, u64, SystemTime)> {
+  let mut entries = Vec::new();
+  let Ok(namespaces) = fs::read_dir(root) else {
+    return entries;
+  };
+  for ns in namespaces.flatten() {
+    let ns_path = ns.path();
+    if !ns_path.is_dir() {
+      continue;
+    }
+    let name = ns.file_name().to_string_lossy().to_string();
+    let Ok(files) = fs::read_dir(&ns_path) else {
+      continue;
+    };
+    for file in files.flatten() {
+      let path = file.path();
+      if path.extension().and_then(|e| e.to_str()) != Some(ENTRY_EXTENSION) {
+        continue;
+      }
+      if let Ok(meta) = file.metadata() {
+        let used = meta.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+        entries.push((path, name.clone(), meta.len(), used));
+      }
+    }
+  }
+  entries
+}
+
+// Remove least-recently-used entries until the cache fits in `max_bytes`. Returns the size left.
+fn evict_to(root: &Path, max_bytes: u64) -> u64 {
+  let mut entries = list_entries(root);
+  let mut total: u64 = entries.iter().map(|e| e.2).sum();
+  if total <= max_bytes {
+    return total;
+  }
+  entries.sort_by_key(|e| e.3);
+  for (path, _, size, _) in entries {
+    if total <= max_bytes {
+      break;
+    }
+    if fs::remove_file(&path).is_ok() {
+      total = total.saturating_sub(size);
+    }
+  }
+  total
+}
+
+/// Size and entry count per namespace.
+pub fn stats() -> CacheStats {
+  let root = cache_root();
+  let mut namespaces: Vec<NamespaceStats> = Vec::new();
+  for (_, name, size, _) in root.as_deref().map(list_entries).unwrap_or_default() {
+    match namespaces.iter_mut().find(|n| n.name == name) {
+      Some(ns) => {
+        ns.entries += 1;
+        ns.bytes += size;
+      }
+      None => namespaces.push(NamespaceStats {
+        name,
+        entries: 1,
+        bytes: size,
+      }),
+    }
+  }
+  namespaces.sort_by(|a, b| a.name.cmp(&b.name));
+
+  CacheStats {
+    dir: root.map(|d| d.to_string_lossy().to_string()).unwrap_or_default(),
+    total_bytes: namespaces.iter().map(|n| n.bytes).sum(),
+    max_bytes: MAX_CACHE_BYTES,
+    namespaces,
+  }
+}
+
+/// Delete every entry in `namespace`, or in all namespaces when None. Returns the bytes freed.
+pub fn clear(namespace: Option<&str>) -> Result<u64, String> {
+  let root = cache_root().ok_or_else(|| "Cache folder is not available".to_string())?;
+  let mut size = write_lock().lock().unwrap_or_else(PoisonError::into_inner);
+  // Counted again on the next write, including when a removal below fails.
+  *size = None;
+  let mut freed = 0;
+  for (path, name, size, _) in list_entries(&root) {
+    if namespace.is_some_and(|ns| ns != name) {
+      continue;
+    }
+    fs::remove_file(&path).map_err(|e| format!("Failed to remove {}: {e}", path.display()))?;
+    freed += size;
+  }
+  Ok(freed)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::time::Duration;
+
+  // A cache folder of its own, used as the app cache folder on the current test thread.
+  struct TempCache(PathBuf);
+
+  impl TempCache {
+    fn new(name: &str) -> TempCache {
+      let dir = std::env::temp_dir().join(format!("clipwave-cache-test-{name}-{}", std::process::id()));
+      let _ = fs::remove_dir_all(&dir);
+      crate::set_app_cache_dir_for_test(dir.clone());
+      TempCache(dir)
+    }
+  }
+
+  impl Drop for TempCache {
+    fn drop(&mut self) {
+      let _ = fs::remove_dir_all(&self.0);
+    }
+  }
+
+  fn set_last_use(namespace: &str, key: &str, seconds_ago: u64) {
+    let file = fs::File::options().write(true).open(entry_path(namespace, key).unwrap()).unwrap();
+    file.set_modified(SystemTime::now() - Duration::from_secs(seconds_ago)).unwrap();
+  }
+
+  #[test]
+  fn entries_with_another_header_or_key_read_as_misses() {
+    let _cache = TempCache::new("header");
+    write("probe", "a.mp4|10|1", b"payload");
+    assert_eq!(read("probe", "a.mp4|10|1").as_deref(), Some(&b"payload"[..]));
+    assert_eq!(read("keyframes", "a.mp4|10|1"), None);
+
+    let path = entry_path("probe", "a.mp4|10|1").unwrap();
+    fs::write(&path, b"clipwave-cache v0\na.mp4|10|1\npayload").unwrap();
+    assert_eq!(read("probe", "a.mp4|10|1"), None);
+    // What a hash collision looks like: the file holds a different key.
+    fs::write(&path, b"clipwave-cache v1\nb.mp4|10|1\npayload").unwrap();
+    assert_eq!(read("probe", "a.mp4|10|1"), None);
+  }
+
+  #[test]
+  fn eviction_removes_the_least_recently_used_entries() {
+    let _cache = TempCache::new("lru");
+    let payload = [0u8; 100];
+    for (key, seconds_ago) in [("a", 300), ("b", 200), ("c", 100)] {
+      write_capped("probe", key, &payload, 1000);
+      set_last_use("probe", key, seconds_ago);
+    }
+    let entry_bytes = fs::metadata(entry_path("probe", "a").unwrap()).unwrap().len();
+    // A read is a use: `a` is now the newest.
+    assert!(read("probe", "a").is_some());
+
+    write_capped("thumbs", "d", &payload, 3 * entry_bytes);
+    assert!(read("probe", "b").is_none());
+    for (namespace, key) in [("probe", "a"), ("probe", "c"), ("thumbs", "d")] {
+      assert!(read(namespace, key).is_some(), "{namespace}/{key}");
+    }
+    assert_eq!(stats().total_bytes, 3 * entry_bytes);
+
+    // The running total follows a clear, so the next write neither over- nor under-evicts.
+    assert_eq!(clear(Some("probe")).unwrap(), 2 * entry_bytes);
+    write_capped("probe", "e", &payload, 2 * entry_bytes);
+    assert!(read("thumbs", "d").is_some() && read("probe", "e").is_some());
+  }
+}
@@ -0,0 +1,146 @@
+// Keyframe index: every video keyframe PTS of a file, scanned once and kept in memory and in the disk cache.
+//
+// MP4 and Matroska files usually carry this list in their own index, which `native_probe` reads
+// without spawning anything; Matroska files with incomplete Cues get their block headers scanned
+// on the index build only. Other files are scanned by the media backend, which reads packet
+// keyframe flags: what `-ss` with stream copy seeks to, so the index matches where a lossless cut
+// can actually start.
+
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::path::Path;
+use std::sync::{Arc, Mutex, OnceLock};
+
+const CACHE_NAMESPACE: &str = "keyframes";
+// Slack when comparing a target against keyframe timestamps.
+const KEYFRAME_EPSILON_SECONDS: f64 = 1e-6;
+
+fn memory_cache() -> &'static Mutex<HashMap<String, Arc<Vec<f64>>>> {
+  static CACHE: OnceLock<Mutex<HashMap<String, Arc<Vec<f64>>>>> = OnceLock::new();
+  CACHE.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+// One lock per file being scanned, so concurrent callers wait for the first scan instead of repeating it.
+fn build_locks() -> &'static Mutex<HashMap<String, Arc<Mutex<()>>>> {
+  static LOCKS: OnceLock<Mutex<HashMap<String, Arc<Mutex<()>>>>> = OnceLock::new();
+  LOCKS.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+fn load_from_disk(key: &str) -> Option<Vec<f64>> {
+  let payload = crate::disk_cache::read(CACHE_NAMESPACE, key)?;
+  String::from_utf8_lossy(&payload)
+    .lines()
+    .map(|l| l.trim().parse::<f64>().ok())
+    .collect()
+}
+
+fn save_to_disk(key: &str, keyframes: &[f64]) {
+  let mut text = String::with_capacity(keyframes.len() * 12);
+  for kf in keyframes {
+    let _ = writeln!(text, "{kf}");
+  }
+  crate::disk_cache::write(CACHE_NAMESPACE, key, text.as_bytes());
+}
+
+/// Index for `key` from memory or disk, without scanning.
+pub fn cached(key: &str) -> Option<Arc<Vec<f64>>> {
+  if let Some(hit) = memory_cache().lock().ok()?.get(key).cloned() {
+    return Some(hit);
+  }
+  let loaded = Arc::new(load_from_disk(key)?);
+  if let Ok(mut cache) = memory_cache().lock() {
+    cache.insert(key.to_string(), loaded.clone());
+  }
+  Some(loaded)
+}
+
+/// Index for `key` from memory, disk or the container's own index, without running ffprobe.
+pub fn lookup(input_path: &str, key: &str) -> Option<Arc<Vec<f64>>> {
+  if let Some(hit) = cached(key) {
+    return Some(hit);
+  }
+  let keyframes = Arc::new(crate::native_probe::keyframes(Path::new(input_path))?);
+  save_to_disk(key, &keyframes);
+  if let Ok(mut cache) = memory_cache().lock() {
+    cache.insert(key.to_string(), keyframes.clone());
+  }
+  Some(keyframes)
+}
+
+/// Drop in-memory indexes (after the disk cache was cleared).
+pub fn clear_memory() {
+  if let Ok(mut cache) = memory_cache().lock() {
+    cache.clear();
+  }
+}
+
+/// Index for `input_path`, scanning the file if it is not cached yet. `on_progress` gets 0-100 and
+/// returns false to abort the scan. Returns the sorted keyframe times and whether they came from the cache.
+pub fn get_or_build(
+  ffprobe_path: &Path,
+  input_path: &str,
+  key: &str,
+  duration_seconds: Option<f64>,
+  mut on_progress: impl FnMut(f64) -> bool,
+) -> Result<(Arc<Vec<f64>>, bool), String> {
+  if let Some(hit) = cached(key) {
+    return Ok((hit, true));
+  }
+  if let Some(native) = lookup(input_path, key) {
+    let _ = on_progress(100.0);
+    return Ok((native, false));
+  }
+
+  let lock = build_locks()
+    .lock()
+    .map_err(|_| "Keyframe index lock poisoned".to_string())?
+    .entry(key.to_string())
+    .or_default()
+    .clone();
+  let _guard = lock.lock().map_err(|_| "Keyframe index lock poisoned".to_string())?;
+  // Another caller may have finished the scan while we waited.
+  if let Some(hit) = cached(key) {
+    return Ok((hit, true));
+  }
+
+  let result = scan(ffprobe_path, input_path, duration_seconds, &mut on_progress).map(Arc::new);
+  // Cache first: a caller arriving after the lock is gone must find the index, not scan again.
+  if let Ok(keyframes) = &result {
+    save_to_disk(key, keyframes);
+    if let Ok(mut cache) = memory_cache().lock() {
+      cache.insert(key.to_string(), keyframes.clone());
+    }
+  }
+  if let Ok(mut locks) = build_locks().lock() {
+    locks.remove(key);
+  }
+  Ok((result?, false))
+}
+
+fn scan(
+  ffprobe_path: &Path,
+  input_path: &str,
+  duration_seconds: Option<f64>,
+  on_progress: &mut dyn FnMut(f64) -> bool,
+) -> Result<Vec<f64>, String> {
+  // Matroska files without complete Cues: reading block headers beats decoding packets in ffprobe.
+  if let Some(keyframes) = crate::native_probe::scanned_keyframes(Path::new(input_path)) {
+    let _ = on_progress(100.0);
+    return Ok(keyframes);
+  }
+  let mut keyframes =
+    crate::media_backend::current().keyframes(ffprobe_path, input_path, duration_seconds, on_progress)?;
+
+  // Packets arrive in decode order; B-frame reordering means PTS is not monotonic.
+  keyframes.sort_by(f64::total_cmp);
+  keyframes.dedup();
+  let _ = on_progress(100.0);
+  Ok(keyframes)
+}
+
+/// Last keyframe at or before `target` and first keyframe at or after it.
+pub fn surrounding(keyframes: &[f64], target: f64) -> (Option<f64>, Option<f64>) {
+  let upto = keyframes.partition_point(|k| *k <= target + KEYFRAME_EPSILON_SECONDS);
+  let from = keyframes.partition_point(|k| *k + KEYFRAME_EPSILON_SECONDS < target);
+  (upto.checked_sub(1).map(|i| keyframes[i]), keyframes.get(from).copied())
+}
@@ -1,16 +1,27 @@
 // Prevents additional console window on Windows in release, DO NOT REMOVE!!
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use tauri::{Emitter, Manager};
 use std::collections::HashMap;
 use std::io::{Read, Write};
 use std::path::{Path, PathBuf};
 use std::io::ErrorKind;
 use std::process::{Command, Stdio};
-use std::sync::{Mutex, OnceLock};
+use std::sync::{Arc, Condvar, Mutex, OnceLock, PoisonError};
+use media_backend::ProcessTiming;
 use std::{env, fs};
 
+mod analysis;
+mod disk_cache;
+mod keyframes;
+mod media_backend;
+// Stream probing from this module is only used off Windows (Media Foundation covers it there).
+#[cfg_attr(windows, allow(dead_code))]
+mod native_probe;
+mod presets;
+mod timecode;
+
 #[cfg(windows)]
 fn apply_no_window(cmd: &mut Command) {
   use std::os::windows::process::CommandExt;
@@ -21,11 +32,11 @@ fn apply_no_window(cmd: &mut Command) {
 #[cfg(not(windows))]
 fn apply_no_window(_cmd: &mut Command) {}
 
-#[derive(Clone, Debug, Serialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 struct AudioStreamInfo {
   // 0-based order within audio streams (used for ffmpeg mapping: `0:a:{order}`).
   order: i32,
-  // Original ffprobe stream index (global), when available; -1 for in-process probes.
+  // Global stream index as ffprobe numbers it (`0:{index}`), also for in-process probes.
   index: i32,
   codec_name: String,
   channels: Option<i32>,
@@ -33,7 +44,43 @@ struct AudioStreamInfo {
   title: String,
 }
 
-#[derive(Clone, Debug, Serialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct VideoStreamInfo {
+  // 0-based order within video streams (`0:v:{order}`).
+  order: i32,
+  index: i32,
+  codec_name: String,
+  profile: Option<String>,
+  level: Option<i32>,
+  width: Option<i32>,
+  height: Option<i32>,
+  sample_aspect_ratio: Option<String>,
+  display_aspect_ratio: Option<String>,
+  frame_rate: Option<f64>,
+  bit_depth: Option<i32>,
+  pix_fmt: Option<String>,
+  color_range: Option<String>,
+  color_primaries: Option<String>,
+  color_transfer: Option<String>,
+  // Matrix coefficients (ffprobe calls this `color_space`).
+  color_matrix: Option<String>,
+  hdr: Option<HdrInfo>,
+  bit_rate: Option<u64>,
+  rotation_degrees: i32,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct HdrInfo {
+  // "HDR10", "HLG" or "Dolby Vision".
+  format: String,
+  // Mastering display luminance in cd/m².
+  mastering_min_luminance: Option<f64>,
+  mastering_max_luminance: Option<f64>,
+  max_content_light_level: Option<i64>,
+  max_frame_average_light_level: Option<i64>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
 struct SubtitleStreamInfo {
   // 0-based order within subtitle streams (future: used for `0:s:{order}`).
   order: i32,
@@ -43,12 +90,29 @@ struct SubtitleStreamInfo {
   title: String,
 }
 
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct ChapterInfo {
+  // 0-based position in the source's chapter list.
+  index: i32,
+  title: String,
+  start_seconds: f64,
+  end_seconds: f64,
+}
+
 #[derive(Debug, Serialize)]
 struct ProbeResult {
   input_path: String,
   duration_seconds: Option<f64>,
+  video_streams: Vec<VideoStreamInfo>,
   audio_streams: Vec<AudioStreamInfo>,
   subtitle_streams: Vec<SubtitleStreamInfo>,
+  chapters: Vec<ChapterInfo>,
+  // Recording start as tagged by the source (`creation_time`, falling back to QuickTime `creationdate`).
+  creation_time: Option<String>,
+  // First video stream's rates and how likely it is to be variable frame rate.
+  frame_rate: Option<FrameRateInfo>,
+  // Container-level details for a media info panel.
+  format: Option<FormatInfo>,
   ffmpeg_bin_dir_used: String,
   ffprobe_path: String,
   ffprobe_args: Vec<String>,
@@ -57,6 +121,51 @@ struct ProbeResult {
   timing_ms: ProbeTimingInfo,
 }
 
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct FrameRateInfo {
+  // ffprobe's r_frame_rate: the lowest rate that represents all timestamps (the "real" base rate).
+  real_frame_rate: Option<f64>,
+  avg_frame_rate: Option<f64>,
+  // 0 = constant frame rate, 1 = certainly variable.
+  vfr_confidence: f64,
+  // Rate to use for constant frame rate output, as an ffmpeg rational (e.g. `30000/1001`).
+  suggested_cfr_rate: Option<String>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct FormatInfo {
+  // Demuxer name(s), e.g. `mov,mp4,m4a,3gp,3g2,mj2`.
+  format_name: String,
+  format_long_name: String,
+  bit_rate: Option<u64>,
+  start_time_seconds: Option<f64>,
+  size_bytes: Option<u64>,
+  stream_count: Option<i32>,
+  // All global tags, sorted by key.
+  tags: std::collections::BTreeMap<String, String>,
+  // Embedded files (MKV fonts, cover images stored as attachments).
+  attachments: Vec<AttachmentInfo>,
+  // Non-media streams such as timecode tracks or GoPro telemetry.
+  data_streams: Vec<DataStreamInfo>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct AttachmentInfo {
+  index: i32,
+  filename: String,
+  mimetype: String,
+  codec_name: String,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct DataStreamInfo {
+  index: i32,
+  codec_name: String,
+  // FourCC as reported by the container (`tmcd`, `gpmd`, ...).
+  codec_tag: String,
+  handler_name: String,
+}
+
 #[derive(Debug, Serialize)]
 struct ProbeTimingInfo {
   validation_ms: f64,
@@ -67,16 +176,129 @@ struct ProbeTimingInfo {
   ffprobe_execution_ms: f64,
   ffprobe_wait_ms: f64,
   json_parsing_ms: f64,
+  // The short second ffprobe that samples frame intervals for the VFR check.
+  frame_sampling_ms: f64,
   total_ms: f64,
   cache_hit: bool,
 }
 
+#[derive(Clone, Debug, Deserialize)]
+struct ChapterMarker {
+  // Source-timeline position in hh:mm:ss[.mmm].
+  time: String,
+  #[serde(default)]
+  title: String,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+struct StreamMetadataEdit {
+  // Output stream specifier: `v:0`, `a:0`, `s:0`, ...
+  stream: String,
+  language: Option<String>,
+  title: Option<String>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+struct MetadataOptions {
+  title: Option<String>,
+  comment: Option<String>,
+  artist: Option<String>,
+  date: Option<String>,
+  // "none" (default), "all", or "privacy" (location and device tags only).
+  strip: String,
+  streams: Vec<StreamMetadataEdit>,
+}
+
+// Optional extras for `trim_media`; every field may be omitted by the frontend.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+struct TrimOptions {
+  // User chapter markers; when present they replace the source's chapters in the output.
+  chapter_markers: Option<Vec<ChapterMarker>>,
+  metadata: Option<MetadataOptions>,
+  // Write `source creation_time + IN` so clips keep their real wall-clock start.
+  shift_creation_time: bool,
+  // Also set the output file's modification time to the shifted creation time.
+  set_file_mtime: bool,
+  // Decode the whole output after export to catch corruption (slow on long clips).
+  verify_decode: bool,
+  // Destination folder; defaults to the source's folder.
+  output_dir: Option<String>,
+  // Filename template, e.g. `{stem}_{in}-{out}.{ext}` (see `OUTPUT_TEMPLATE_TOKENS`).
+  name_template: Option<String>,
+  // What to do when the output exists: "overwrite" (default), "number" or "fail".
+  collision: Option<String>,
+  // Value for the `{index}` token (batch position); defaults to 1.
+  index: Option<u32>,
+  // Exact mode only: output constant frame rate at this rate ("auto" = detected, or e.g. "30000/1001").
+  constant_frame_rate: Option<String>,
+  // Extra ffmpeg arguments, checked by `validate_extra_ffmpeg_args`. Pre-input args go right before
+  // `-ss`/`-i` (input options such as `-hwaccel`); post-input args follow the inputs and come before
+  // the stream maps; output args come last, just before the output file, so they override ours.
+  pre_input_args: Vec<String>,
+  post_input_args: Vec<String>,
+  output_args: Vec<String>,
+  // Export preset name (see `presets`); supplies the mode and every setting below left unset.
+  preset: Option<String>,
+  // Output extension, e.g. "mp4"; defaults to the source's.
+  container: Option<String>,
+  // Exact mode only: encoder, downscale height and audio encoding (defaults: libx264 CRF 18, no scaling, audio copy).
+  video: Option<presets::VideoSettings>,
+  max_height: Option<u32>,
+  audio: Option<presets::AudioSettings>,
+}
+
 #[derive(Debug, Serialize)]
 struct TrimResult {
   output_path: String,
   requested_duration_seconds: f64,
   actual_duration_seconds: Option<f64>,
   duration_warning: Option<String>,
+  // Source tags that were not carried into the output (`key` for global tags, `v:0:key` for stream tags).
+  removed_metadata_tags: Vec<String>,
+  output_creation_time: Option<String>,
+  verification: VerificationReport,
+  warnings: Vec<TrimWarning>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum TrimWarningCategory {
+  Preflight,
+  Duration,
+  MissingStream,
+  AvSync,
+  Timestamps,
+  Decode,
+}
+
+#[derive(Debug, Serialize)]
+struct TrimWarning {
+  category: TrimWarningCategory,
+  message: String,
+}
+
+#[derive(Debug, Serialize)]
+struct VerifiedStreamInfo {
+  index: i32,
+  codec_type: String,
+  codec_name: String,
+  first_pts_seconds: Option<f64>,
+  last_pts_seconds: Option<f64>,
+  // End of the last packet (last PTS + packet duration).
+  end_seconds: Option<f64>,
+}
+
+#[derive(Debug, Serialize)]
+struct VerificationReport {
+  output_size_bytes: u64,
+  container_duration_seconds: Option<f64>,
+  streams: Vec<VerifiedStreamInfo>,
+  // Audio start minus video start; positive means audio begins later.
+  av_start_offset_seconds: Option<f64>,
+  decode_checked: bool,
+  decode_errors: Vec<String>,
 }
 
 #[derive(Debug, Serialize)]
@@ -112,7 +334,7 @@ struct WarmupResult {
   ms: f64,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Clone, Debug, Serialize)]
 struct SpawnDebugInfo {
   phase: String,
   program: String,
@@ -183,45 +405,6 @@ struct SubtitlesProbeResult {
   debug: Option<SpawnDebugInfo>,
 }
 
-#[allow(dead_code)]
-fn parse_hh_mm_ss(input: &str) -> Result<u64, String> {
-  // Parse with milliseconds support and round down to whole seconds
-  let seconds_f64 = parse_hh_mm_ss_with_millis(input)?;
-  Ok(seconds_f64.floor() as u64)
-}
-
-// Parse time in format hh:mm:ss or hh:mm:ss.milliseconds
-fn parse_hh_mm_ss_with_millis(input: &str) -> Result<f64, String> {
-  let parts: Vec<&str> = input.split(':').collect();
-  if parts.len() != 3 {
-    return Err("Time must be in format hh:mm:ss or hh:mm:ss.milliseconds".to_string());
-  }
-
-  let (h, m, s_with_millis) = (parts[0], parts[1], parts[2]);
-
-  // Validate hours (any number of digits)
-  if h.is_empty() || !h.chars().all(|c| c.is_ascii_digit()) {
-    return Err("Invalid hours".to_string());
-  }
-
-  // Validate minutes (must be exactly 2 digits)
-  if m.len() != 2 || !m.chars().all(|c| c.is_ascii_digit()) {
-    return Err("Invalid minutes (must be 2 digits)".to_string());
-  }
-
-  // Parse seconds part (may include decimal point for milliseconds)
-  let seconds_f64: f64 = s_with_millis.parse().map_err(|_| "Invalid seconds".to_string())?;
-
-  let hours: u64 = h.parse().map_err(|_| "Invalid hours".to_string())?;
-  let minutes: u64 = m.parse().map_err(|_| "Invalid minutes".to_string())?;
-
-  if minutes >= 60 || seconds_f64 >= 60.0 || seconds_f64 < 0.0 {
-    return Err("Minutes and seconds must be < 60".to_string());
-  }
-
-  Ok(hours as f64 * 3600.0 + minutes as f64 * 60.0 + seconds_f64)
-}
-
 fn time_for_filename(input: &str) -> String {
   input.replace(':', "h")
 }
@@ -377,11 +560,28 @@ fn rotation_filter_for_degrees(deg: i32) -> Option<&'static str> {
   }
 }
 
-fn probe_video_rotation_degrees_best_effort(ffprobe_path: &Path, input_path: &str) -> i32 {
-  let mut cmd = Command::new(ffprobe_path);
-  apply_no_window(&mut cmd);
-  let output = cmd
-    .args([
+fn probe_video_rotation_degrees_best_effort(ffprobe_path: &Path, ffmpeg_bin_dir_used: &str, input_path: &str) -> i32 {
+  // Answer from the probe cache when any probe of this file version has run.
+  let cache_key = probe_cache_key_best_effort(input_path);
+  let cached = probe_cache().lock().ok().and_then(|guard| {
+    let entry = guard.get(&cache_key)?;
+    entry
+      .rotation_degrees
+      .or_else(|| entry.is_full.then(|| entry.video_streams.first().map(|v| v.rotation_degrees).unwrap_or(0)))
+  });
+  if let Some(degrees) = cached {
+    return degrees;
+  }
+  #[cfg(not(windows))]
+  if let Some((data, _)) = in_process_probe_into_cache(input_path, &cache_key, ffprobe_path, ffmpeg_bin_dir_used) {
+    return data.rotation_degrees.unwrap_or(0);
+  }
+  #[cfg(windows)]
+  let _ = ffmpeg_bin_dir_used;
+
+  let output = media_backend::current().probe(
+    ffprobe_path,
+    &media_backend::args(&[
       "-v",
       "quiet",
       "-print_format",
@@ -389,17 +589,14 @@ fn probe_video_rotation_degrees_best_effort(ffprobe_path: &Path, input_path: &st
       "-select_streams",
       "v:0",
       "-show_streams",
-    ])
-    .arg(input_path)
-    .stdin(Stdio::null())
-    .stdout(Stdio::piped())
-    .stderr(Stdio::null())
-    .output();
+      input_path,
+    ]),
+  );
 
   let Ok(output) = output else {
     return 0;
   };
-  if !output.status.success() {
+  if !output.success {
     return 0;
   }
 
@@ -407,14 +604,16 @@ fn probe_video_rotation_degrees_best_effort(ffprobe_path: &Path, input_path: &st
     return 0;
   };
 
-  let Some(video) = json
+  json
     .get("streams")
     .and_then(|s| s.as_array())
     .and_then(|arr| arr.first())
-  else {
-    return 0;
-  };
+    .map(rotation_degrees_from_stream_json)
+    .unwrap_or(0)
+}
 
+// Rotation from a stream's `rotate` tag (older muxers) or its display matrix side data.
+fn rotation_degrees_from_stream_json(video: &serde_json::Value) -> i32 {
   if let Some(tags) = video.get("tags").and_then(|t| t.as_object()) {
     if let Some(deg) = tags
       .get("rotate")
@@ -703,15 +902,28 @@ mod win_mf {
 
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
 struct CachedProbeResult {
   input_path: String,
   has_duration: bool,
   has_tracks: bool,
   has_subtitles: bool,
+  // Set only by the full ffprobe run, so every field (chapters, creation time, format, ...) is filled in.
+  // Entries cached before this field existed read as partial and are probed again once.
+  #[serde(default)]
+  is_full: bool,
   duration_seconds: Option<f64>,
+  video_streams: Vec<VideoStreamInfo>,
   audio_streams: Vec<AudioStreamInfo>,
   subtitle_streams: Vec<SubtitleStreamInfo>,
+  chapters: Vec<ChapterInfo>,
+  creation_time: Option<String>,
+  frame_rate: Option<FrameRateInfo>,
+  format: Option<FormatInfo>,
+  // First video track's rotation; the in-process probe sets it without listing video streams.
+  // Full entries cached before this field existed carry it in `video_streams` instead.
+  #[serde(default)]
+  rotation_degrees: Option<i32>,
   ffmpeg_bin_dir_used: String,
   ffprobe_path: String,
   ffprobe_args: Vec<String>,
@@ -741,20 +953,189 @@ fn probe_cache_key_best_effort(input_path: &str) -> String {
   }
 }
 
-fn parse_duration_from_ffprobe_text(stdout: &[u8]) -> Option<f64> {
-  let s = String::from_utf8_lossy(stdout);
-  let t = s.trim();
-  if t.is_empty() {
-    return None;
-  }
-  t.parse::<f64>().ok()
-}
-
 fn stderr_head_text(stderr: &[u8]) -> String {
   let n = std::cmp::min(stderr.len(), 200);
   String::from_utf8_lossy(&stderr[..n]).to_string()
 }
 
+/// Video streams from a `-show_entries stream=...:stream_side_data` probe, in stream order.
+fn parse_video_streams_from_ffprobe_json(json: &serde_json::Value) -> Vec<VideoStreamInfo> {
+  let Some(streams) = json.get("streams").and_then(|s| s.as_array()) else {
+    return Vec::new();
+  };
+
+  let mut video_streams: Vec<VideoStreamInfo> = streams
+    .iter()
+    .filter(|s| s.get("codec_type").and_then(|t| t.as_str()) == Some("video"))
+    // Cover art is stored as a one-frame video stream; it is not the video.
+    .filter(|s| {
+      s.get("disposition")
+        .and_then(|d| d.get("attached_pic"))
+        .and_then(|v| v.as_i64())
+        != Some(1)
+    })
+    .map(|stream| {
+      let text = |key: &str| {
+        stream
+          .get(key)
+          .and_then(|v| v.as_str())
+          .filter(|v| !v.is_empty() && *v != "unknown")
+          .map(str::to_string)
+      };
+      let int = |key: &str| stream.get(key).and_then(|v| v.as_i64()).map(|v| v as i32);
+      let rate = |key: &str| {
+        stream
+          .get(key)
+          .and_then(|v| v.as_str())
+          .and_then(parse_ffprobe_rate)
+          .filter(|r| r.is_finite() && *r > 0.0)
+      };
+      let pix_fmt = text("pix_fmt");
+      // bits_per_raw_sample is often missing for HEVC/VP9; the pixel format name carries it too.
+      let bit_depth = text("bits_per_raw_sample")
+        .and_then(|v| v.parse::<i32>().ok())
+        .or_else(|| {
+          pix_fmt.as_deref().map(|f| {
+            if f.contains("12") {
+              12
+            } else if f.contains("10") {
+              10
+            } else {
+              8
+            }
+          })
+        });
+      let color_transfer = text("color_transfer");
+
+      VideoStreamInfo {
+        order: 0,
+        index: int("index").unwrap_or(-1),
+        codec_name: text("codec_name").unwrap_or_default(),
+        profile: text("profile"),
+        level: int("level").filter(|l| *l > 0),
+        width: int("width"),
+        height: int("height"),
+        sample_aspect_ratio: text("sample_aspect_ratio").filter(|v| v != "0:1"),
+        display_aspect_ratio: text("display_aspect_ratio").filter(|v| v != "0:1"),
+        frame_rate: rate("avg_frame_rate").or_else(|| rate("r_frame_rate")),
+        bit_depth,
+        pix_fmt,
+        color_range: text("color_range"),
+        color_primaries: text("color_primaries"),
+        hdr: hdr_info_from_stream_json(stream, color_transfer.as_deref()),
+        color_transfer,
+        color_matrix: text("color_space"),
+        bit_rate: text("bit_rate").and_then(|v| v.parse::<u64>().ok()),
+        rotation_degrees: rotation_degrees_from_stream_json(stream),
+      }
+    })
+    .collect();
+
+  video_streams.sort_by_key(|s| s.index);
+  for (i, s) in video_streams.iter_mut().enumerate() {
+    s.order = i as i32;
+  }
+  video_streams
+}
+
+/// Container details from a probe that requested `format=...:format_tags` and stream tags.
+fn parse_format_info_from_ffprobe_json(json: &serde_json::Value) -> Option<FormatInfo> {
+  let format = json.get("format")?;
+  let text = |v: &serde_json::Value, key: &str| v.get(key).and_then(|x| x.as_str()).unwrap_or("").to_string();
+  let tag = |v: &serde_json::Value, key: &str| {
+    v.get("tags")
+      .and_then(|t| t.get(key))
+      .and_then(|x| x.as_str())
+      .unwrap_or("")
+      .to_string()
+  };
+  let index = |v: &serde_json::Value| v.get("index").and_then(|x| x.as_i64()).unwrap_or(-1) as i32;
+
+  let tags = format
+    .get("tags")
+    .and_then(|t| t.as_object())
+    .map(|obj| {
+      obj
+        .iter()
+        .filter_map(|(k, v)| v.as_str().map(|v| (k.clone(), v.to_string())))
+        .collect()
+    })
+    .unwrap_or_default();
+
+  let mut attachments = Vec::new();
+  let mut data_streams = Vec::new();
+  for stream in json.get("streams").and_then(|s| s.as_array()).into_iter().flatten() {
+    match stream.get("codec_type").and_then(|t| t.as_str()) {
+      Some("attachment") => attachments.push(AttachmentInfo {
+        index: index(stream),
+        filename: tag(stream, "filename"),
+        mimetype: tag(stream, "mimetype"),
+        codec_name: text(stream, "codec_name"),
+      }),
+      Some("data") => data_streams.push(DataStreamInfo {
+        index: index(stream),
+        codec_name: text(stream, "codec_name"),
+        codec_tag: text(stream, "codec_tag_string"),
+        handler_name: tag(stream, "handler_name"),
+      }),
+      _ => {}
+    }
+  }
+
+  Some(FormatInfo {
+    format_name: text(format, "format_name"),
+    format_long_name: text(format, "format_long_name"),
+    bit_rate: format.get("bit_rate").and_then(|v| v.as_str()).and_then(|v| v.parse().ok()),
+    start_time_seconds: format.get("start_time").and_then(|v| v.as_str()).and_then(|v| v.parse().ok()),
+    size_bytes: format.get("size").and_then(|v| v.as_str()).and_then(|v| v.parse().ok()),
+    stream_count: format.get("nb_streams").and_then(|v| v.as_i64()).map(|v| v as i32),
+    tags,
+    attachments,
+    data_streams,
+  })
+}
+
+fn hdr_info_from_stream_json(stream: &serde_json::Value, color_transfer: Option<&str>) -> Option<HdrInfo> {
+  let side_data: &[serde_json::Value] = stream
+    .get("side_data_list")
+    .and_then(|v| v.as_array())
+    .map(|v| v.as_slice())
+    .unwrap_or(&[]);
+  let side = |kind: &str| {
+    side_data
+      .iter()
+      .find(|d| d.get("side_data_type").and_then(|t| t.as_str()) == Some(kind))
+  };
+
+  let format = if side("DOVI configuration record").is_some() {
+    "Dolby Vision"
+  } else {
+    match color_transfer {
+      Some("smpte2084") => "HDR10",
+      Some("arib-std-b67") => "HLG",
+      _ => return None,
+    }
+  };
+
+  let mastering = side("Mastering display metadata");
+  let luminance = |key: &str| {
+    mastering
+      .and_then(|m| m.get(key))
+      .and_then(|v| v.as_str())
+      .and_then(parse_ffprobe_rate)
+  };
+  let light = side("Content light level metadata");
+  let level = |key: &str| light.and_then(|l| l.get(key)).and_then(|v| v.as_i64());
+
+  Some(HdrInfo {
+    format: format.to_string(),
+    mastering_min_luminance: luminance("min_luminance"),
+    mastering_max_luminance: luminance("max_luminance"),
+    max_content_light_level: level("max_content"),
+    max_frame_average_light_level: level("max_average"),
+  })
+}
+
 fn parse_streams_from_ffprobe_json(stdout: &[u8]) -> Result<(Vec<AudioStreamInfo>, Vec<SubtitleStreamInfo>), String> {
   let json: serde_json::Value =
     serde_json::from_slice(stdout).map_err(|e| format!("Invalid ffprobe JSON: {e}"))?;
@@ -834,6 +1215,52 @@ fn parse_streams_from_ffprobe_json(stdout: &[u8]) -> Result<(Vec<AudioStreamInfo
   Ok((audio_streams, subtitle_streams))
 }
 
+fn parse_creation_time_from_ffprobe_json(json: &serde_json::Value) -> Option<String> {
+  let tags = json.get("format")?.get("tags")?.as_object()?;
+  ["creation_time", "com.apple.quicktime.creationdate"]
+    .iter()
+    .find_map(|key| tags.get(*key).and_then(|v| v.as_str()))
+    .map(|s| s.trim().to_string())
+    .filter(|s| !s.is_empty())
+}
+
+fn parse_chapters_from_ffprobe_json(json: &serde_json::Value) -> Vec<ChapterInfo> {
+  let Some(chapters) = json.get("chapters").and_then(|c| c.as_array()) else {
+    return Vec::new();
+  };
+
+  let time_field = |chapter: &serde_json::Value, key: &str| -> Option<f64> {
+    let v = chapter.get(key)?;
+    v.as_f64().or_else(|| v.as_str().and_then(|s| s.parse::<f64>().ok()))
+  };
+
+  let mut out: Vec<ChapterInfo> = chapters
+    .iter()
+    .filter_map(|chapter| {
+      let start_seconds = time_field(chapter, "start_time")?;
+      let end_seconds = time_field(chapter, "end_time")?;
+      let title = chapter
+        .get("tags")
+        .and_then(|t| t.get("title"))
+        .and_then(|v| v.as_str())
+        .unwrap_or("")
+        .to_string();
+      Some(ChapterInfo {
+        index: 0,
+        title,
+        start_seconds,
+        end_seconds,
+      })
+    })
+    .collect();
+
+  out.sort_by(|a, b| a.start_seconds.partial_cmp(&b.start_seconds).unwrap_or(std::cmp::Ordering::Equal));
+  for (i, c) in out.iter_mut().enumerate() {
+    c.index = i as i32;
+  }
+  out
+}
+
 #[tauri::command]
 fn warm_ffprobe(ffmpeg_bin_dir: String) -> Result<WarmupResult, String> {
   use std::time::Instant;
@@ -843,30 +1270,10 @@ fn warm_ffprobe(ffmpeg_bin_dir: String) -> Result<WarmupResult, String> {
   let (_ffmpeg_path, ffprobe_path, _used) = resolve_ffmpeg_binaries_with_fallback(&ffmpeg_bin_dir);
   let ffprobe_path_text = ffprobe_path.to_string_lossy().to_string();
   let runner = "direct".to_string();
-  let workdir = stable_working_dir();
-
-  let args: Vec<String> = vec!["-version".to_string()];
 
-  let mut c = Command::new(&ffprobe_path);
-  apply_no_window(&mut c);
-  c.args(&args);
-  if let Some(dir) = &workdir {
-    c.current_dir(dir);
-  }
-  let output = c
-    .stdin(Stdio::null())
-    .stdout(Stdio::null())
-    .stderr(Stdio::null())
-    .output()
-    .map_err(|e| {
-    if e.kind() == ErrorKind::NotFound {
-      "Failed to run ffprobe: program not found".to_string()
-    } else {
-      format!("Failed to run ffprobe: {e}")
-    }
-    })?;
+  let output = media_backend::current().probe(&ffprobe_path, &media_backend::args(&["-version"]))?;
 
-  if !output.status.success() {
+  if !output.success {
     return Err("ffprobe warmup failed".to_string());
   }
 
@@ -877,755 +1284,532 @@ fn warm_ffprobe(ffmpeg_bin_dir: String) -> Result<WarmupResult, String> {
   })
 }
 
-#[tauri::command]
-fn probe_duration(input_path: String, ffmpeg_bin_dir: String) -> Result<DurationProbeResult, String> {
-  use std::time::Instant;
-  let start_total = Instant::now();
+// What a probe command needs from the file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum ProbeNeed {
+  Duration,
+  Tracks,
+  Subtitles,
+  // Everything `probe_media` reports; only the full ffprobe run provides it.
+  Full,
+}
 
-  let input_path = normalize_input_path_for_cli(&input_path);
+impl ProbeNeed {
+  fn met_by(self, entry: &CachedProbeResult) -> bool {
+    match self {
+      ProbeNeed::Duration => entry.has_duration,
+      ProbeNeed::Tracks => entry.has_tracks,
+      ProbeNeed::Subtitles => entry.has_subtitles,
+      ProbeNeed::Full => entry.is_full,
+    }
+  }
+}
 
-  let cache_key = probe_cache_key_best_effort(&input_path);
-  if let Ok(guard) = probe_cache().lock() {
-    if let Some(cached) = guard.get(&cache_key).cloned() {
-      if cached.has_duration {
-        let timing_ms = ProbeTimingInfo {
-          validation_ms: 0.0,
-          resolve_binaries_ms: 0.0,
-          ffprobe_spawn_ms: 0.0,
-          ffprobe_first_stdout_byte_ms: None,
-          ffprobe_first_stderr_byte_ms: None,
-          ffprobe_execution_ms: 0.0,
-          ffprobe_wait_ms: 0.0,
-          json_parsing_ms: 0.0,
-          total_ms: start_total.elapsed().as_secs_f64() * 1000.0,
-          cache_hit: true,
-        };
+// Needs the in-process probe can answer; Media Foundation does not list subtitles.
+#[cfg(not(windows))]
+const IN_PROCESS_PROBE_NEEDS: &[ProbeNeed] = &[ProbeNeed::Duration, ProbeNeed::Tracks, ProbeNeed::Subtitles];
+#[cfg(windows)]
+const IN_PROCESS_PROBE_NEEDS: &[ProbeNeed] = &[ProbeNeed::Duration, ProbeNeed::Tracks];
 
-        return Ok(DurationProbeResult {
-          input_path: cached.input_path,
-          duration_seconds: cached.duration_seconds,
-          ffmpeg_bin_dir_used: cached.ffmpeg_bin_dir_used,
-          ffprobe_path: cached.ffprobe_path,
-          ffprobe_args: cached.ffprobe_args,
-          ffprobe_runner: cached.ffprobe_runner,
-          cwd: cached.cwd,
-          timing_ms,
-          debug: None,
-        });
-      }
-    }
+// Answer of the probe service; each probe command projects it into its own result type.
+struct ProbeOutcome {
+  data: CachedProbeResult,
+  validation_ms: f64,
+  resolve_binaries_ms: f64,
+  // Time spent in whatever answered: ffprobe, the native probe or Media Foundation.
+  probe_ms: f64,
+  // Set only for the caller that actually ran ffprobe.
+  run: Option<FullProbeRun>,
+  debug: Option<SpawnDebugInfo>,
+  // True when answered from the cache or by waiting on another caller's ffprobe.
+  cache_hit: bool,
+}
+
+/// The probe service behind every probe command: the memory and disk cache first, then the
+/// in-process probe (native on Linux/macOS, Media Foundation on Windows) for anything short of a
+/// full probe, then one shared full ffprobe run.
+fn probe_service(input_path: &str, ffmpeg_bin_dir: &str, need: ProbeNeed) -> Result<ProbeOutcome, String> {
+  use std::time::Instant;
+
+  let cache_key = probe_cache_key_best_effort(input_path);
+  let cached = probe_cache()
+    .lock()
+    .ok()
+    .and_then(|guard| guard.get(&cache_key).filter(|c| need.met_by(c)).cloned());
+  if let Some(data) = cached.or_else(|| cached_full_probe(&cache_key)) {
+    return Ok(ProbeOutcome {
+      data,
+      validation_ms: 0.0,
+      resolve_binaries_ms: 0.0,
+      probe_ms: 0.0,
+      run: None,
+      debug: None,
+      cache_hit: true,
+    });
   }
 
   let start_validation = Instant::now();
-  ensure_input_file_exists(&input_path)?;
-  validate_ffmpeg_bin_dir(&ffmpeg_bin_dir)?;
+  ensure_input_file_exists(input_path)?;
+  validate_ffmpeg_bin_dir(ffmpeg_bin_dir)?;
   let validation_ms = start_validation.elapsed().as_secs_f64() * 1000.0;
 
   let start_resolve = Instant::now();
-  let (_ffmpeg_path, ffprobe_path, ffmpeg_bin_dir_used) =
-    resolve_ffmpeg_binaries_with_fallback(&ffmpeg_bin_dir);
+  let (_ffmpeg_path, ffprobe_path, ffmpeg_bin_dir_used) = resolve_ffmpeg_binaries_with_fallback(ffmpeg_bin_dir);
   let resolve_binaries_ms = start_resolve.elapsed().as_secs_f64() * 1000.0;
 
-  let ffprobe_path_text = ffprobe_path.to_string_lossy().to_string();
-  let workdir = stable_working_dir();
-  let cwd_text = workdir
-    .as_ref()
-    .map(|p| p.to_string_lossy().to_string())
-    .unwrap_or_else(|| String::new());
-
-  let ffprobe_args: Vec<String> = vec![
-    "-v".to_string(),
-    "error".to_string(),
-    "-show_entries".to_string(),
-    "format=duration".to_string(),
-    "-of".to_string(),
-    "default=nw=1:nk=1".to_string(),
-    input_path.clone(),
-  ];
-
-  #[cfg(windows)]
-  {
-    let start_mf = Instant::now();
-    if let Ok(duration_seconds) = win_mf::probe_duration_seconds(&input_path) {
-      if duration_seconds.is_some() {
-        let timing_ms = ProbeTimingInfo {
-          validation_ms,
-          resolve_binaries_ms,
-          ffprobe_spawn_ms: 0.0,
-          ffprobe_first_stdout_byte_ms: None,
-          ffprobe_first_stderr_byte_ms: None,
-          ffprobe_execution_ms: start_mf.elapsed().as_secs_f64() * 1000.0,
-          ffprobe_wait_ms: 0.0,
-          json_parsing_ms: 0.0,
-          total_ms: start_total.elapsed().as_secs_f64() * 1000.0,
-          cache_hit: false,
-        };
-
-        let debug = SpawnDebugInfo {
-          phase: "duration_mf".to_string(),
-          program: "MediaFoundation".to_string(),
-          args: Vec::new(),
-          cwd: cwd_text.clone(),
-          program_exists: true,
-          exit_code: Some(0),
-          success: true,
-          stdout_len: 0,
-          stderr_len: 0,
-          stderr_head: String::new(),
-        };
-
-        let result = DurationProbeResult {
-          input_path: input_path.clone(),
-          duration_seconds,
-          ffmpeg_bin_dir_used: ffmpeg_bin_dir_used.clone(),
-          ffprobe_path: ffprobe_path_text.clone(),
-          ffprobe_args: Vec::new(),
-          ffprobe_runner: "mf".to_string(),
-          cwd: cwd_text.clone(),
-          timing_ms,
-          debug: Some(debug),
-        };
-
-        if let Ok(mut guard) = probe_cache().lock() {
-          let entry = guard.entry(cache_key).or_insert(CachedProbeResult {
-            input_path: result.input_path.clone(),
-            has_duration: false,
-            has_tracks: false,
-            has_subtitles: false,
-            duration_seconds: None,
-            audio_streams: Vec::new(),
-            subtitle_streams: Vec::new(),
-            ffmpeg_bin_dir_used: result.ffmpeg_bin_dir_used.clone(),
-            ffprobe_path: result.ffprobe_path.clone(),
-            ffprobe_args: result.ffprobe_args.clone(),
-            ffprobe_runner: result.ffprobe_runner.clone(),
-            cwd: result.cwd.clone(),
-          });
-
-          entry.input_path = result.input_path.clone();
-          entry.has_duration = true;
-          entry.duration_seconds = result.duration_seconds;
-          entry.ffmpeg_bin_dir_used = result.ffmpeg_bin_dir_used.clone();
-          entry.ffprobe_path = result.ffprobe_path.clone();
-          entry.ffprobe_args = result.ffprobe_args.clone();
-          entry.ffprobe_runner = result.ffprobe_runner.clone();
-          entry.cwd = result.cwd.clone();
-        }
-
-        return Ok(result);
-      }
+  if IN_PROCESS_PROBE_NEEDS.contains(&need) {
+    let start_in_process = Instant::now();
+    if let Some((data, debug)) = in_process_probe_into_cache(input_path, &cache_key, &ffprobe_path, &ffmpeg_bin_dir_used)
+      .filter(|(data, _)| need.met_by(data))
+    {
+      return Ok(ProbeOutcome {
+        data,
+        validation_ms,
+        resolve_binaries_ms,
+        probe_ms: start_in_process.elapsed().as_secs_f64() * 1000.0,
+        run: None,
+        debug: Some(debug),
+        cache_hit: false,
+      });
     }
   }
 
-  let start_spawn_total = Instant::now();
-  let mut cmd = Command::new(&ffprobe_path);
-  apply_no_window(&mut cmd);
-  cmd.args(&ffprobe_args);
-  if let Some(dir) = &workdir {
-    cmd.current_dir(dir);
+  let probe = probe_full(input_path, &ffprobe_path, &ffmpeg_bin_dir_used)?;
+  Ok(ProbeOutcome {
+    data: probe.data,
+    validation_ms,
+    resolve_binaries_ms,
+    probe_ms: probe.run.as_ref().map(|r| r.ffprobe_execution_ms).unwrap_or(0.0),
+    debug: probe.run.as_ref().map(|r| r.debug.clone()),
+    run: probe.run,
+    cache_hit: probe.cache_hit,
+  })
+}
+
+fn in_process_debug(program: &str, cwd: &str) -> SpawnDebugInfo {
+  SpawnDebugInfo {
+    phase: "probe_in_process".to_string(),
+    program: program.to_string(),
+    args: Vec::new(),
+    cwd: cwd.to_string(),
+    program_exists: true,
+    exit_code: Some(0),
+    success: true,
+    stdout_len: 0,
+    stderr_len: 0,
+    stderr_head: String::new(),
   }
-  cmd.stdin(Stdio::null())
-    .stdout(Stdio::piped())
-    .stderr(Stdio::piped());
+}
 
-  let start_spawn = Instant::now();
-  let mut child = cmd.spawn().map_err(|e| {
-    if e.kind() == ErrorKind::NotFound {
-      "Failed to run ffprobe: program not found (set FFmpeg bin folder or add ffprobe to PATH)".to_string()
-    } else {
-      format!("Failed to run ffprobe: {e}")
-    }
+// Merge an in-process probe into the file's cache entry. None means the file needs ffprobe.
+fn merge_in_process_probe(
+  input_path: &str,
+  cache_key: &str,
+  ffprobe_path: &Path,
+  ffmpeg_bin_dir_used: &str,
+  runner: &str,
+  fill: impl FnOnce(&mut CachedProbeResult),
+) -> Option<CachedProbeResult> {
+  let mut guard = probe_cache().lock().ok()?;
+  let entry = guard.entry(cache_key.to_string()).or_default();
+  entry.input_path = input_path.to_string();
+  fill(entry);
+  entry.ffmpeg_bin_dir_used = ffmpeg_bin_dir_used.to_string();
+  entry.ffprobe_path = ffprobe_path.to_string_lossy().to_string();
+  entry.ffprobe_args = Vec::new();
+  entry.ffprobe_runner = runner.to_string();
+  entry.cwd = stable_working_dir()
+    .map(|p| p.to_string_lossy().to_string())
+    .unwrap_or_default();
+  Some(entry.clone())
+}
+
+// In-process MP4/Matroska probe: duration, tracks and subtitles in one go.
+#[cfg(not(windows))]
+fn in_process_probe_into_cache(
+  input_path: &str,
+  cache_key: &str,
+  ffprobe_path: &Path,
+  ffmpeg_bin_dir_used: &str,
+) -> Option<(CachedProbeResult, SpawnDebugInfo)> {
+  let native = native_probe::probe(Path::new(input_path))?;
+  eprintln!("[PROBE] Native {} probe: {input_path}", native.container);
+  let data = merge_in_process_probe(input_path, cache_key, ffprobe_path, ffmpeg_bin_dir_used, "native", |entry| {
+    entry.has_duration = true;
+    entry.has_tracks = true;
+    entry.has_subtitles = true;
+    entry.duration_seconds = Some(native.duration_seconds);
+    entry.audio_streams = native.audio_streams;
+    entry.subtitle_streams = native.subtitle_streams;
+    entry.rotation_degrees = Some(normalize_rotation_degrees(native.rotation_degrees));
   })?;
-  let ffprobe_spawn_ms = start_spawn.elapsed().as_secs_f64() * 1000.0;
-
-  let mut stdout = child
-    .stdout
-    .take()
-    .ok_or_else(|| "Failed to capture ffprobe stdout".to_string())?;
-  let mut stderr = child
-    .stderr
-    .take()
-    .ok_or_else(|| "Failed to capture ffprobe stderr".to_string())?;
-
-  let (stdout_tx, stdout_rx) =
-    std::sync::mpsc::channel::<(Option<f64>, Vec<u8>, Result<(), String>)>();
-  let (stderr_tx, stderr_rx) =
-    std::sync::mpsc::channel::<(Option<f64>, Vec<u8>, Result<(), String>)>();
-
-  std::thread::spawn(move || {
-    let mut buf = Vec::new();
-    let mut first_ms: Option<f64> = None;
-    let mut tmp = [0_u8; 8192];
-    loop {
-      match stdout.read(&mut tmp) {
-        Ok(0) => break,
-        Ok(n) => {
-          if first_ms.is_none() {
-            first_ms = Some(start_spawn_total.elapsed().as_secs_f64() * 1000.0);
-          }
-          buf.extend_from_slice(&tmp[..n]);
-        }
-        Err(e) => {
-          let _ = stdout_tx.send((first_ms, buf, Err(format!("Failed reading ffprobe stdout: {e}"))));
-          return;
-        }
-      }
-    }
-    let _ = stdout_tx.send((first_ms, buf, Ok(())));
-  });
+  let debug = in_process_debug("native", &data.cwd);
+  Some((data, debug))
+}
 
-  std::thread::spawn(move || {
-    let mut buf = Vec::new();
-    let mut first_ms: Option<f64> = None;
-    let mut tmp = [0_u8; 8192];
-    loop {
-      match stderr.read(&mut tmp) {
-        Ok(0) => break,
-        Ok(n) => {
-          if first_ms.is_none() {
-            first_ms = Some(start_spawn_total.elapsed().as_secs_f64() * 1000.0);
-          }
-          buf.extend_from_slice(&tmp[..n]);
-        }
-        Err(e) => {
-          let _ = stderr_tx.send((first_ms, buf, Err(format!("Failed reading ffprobe stderr: {e}"))));
-          return;
-        }
-      }
+// Media Foundation: duration and audio tracks. It cannot list subtitles, which stay with ffprobe.
+#[cfg(windows)]
+fn in_process_probe_into_cache(
+  input_path: &str,
+  cache_key: &str,
+  ffprobe_path: &Path,
+  ffmpeg_bin_dir_used: &str,
+) -> Option<(CachedProbeResult, SpawnDebugInfo)> {
+  let duration_seconds = win_mf::probe_duration_seconds(input_path).ok().flatten();
+  let audio_streams = win_mf::probe_audio_streams(input_path).ok();
+  if duration_seconds.is_none() && audio_streams.is_none() {
+    return None;
+  }
+  let data = merge_in_process_probe(input_path, cache_key, ffprobe_path, ffmpeg_bin_dir_used, "mf", |entry| {
+    if duration_seconds.is_some() {
+      entry.has_duration = true;
+      entry.duration_seconds = duration_seconds;
     }
-    let _ = stderr_tx.send((first_ms, buf, Ok(())));
-  });
-
-  let start_wait = Instant::now();
-  let status = child
-    .wait()
-    .map_err(|e| format!("Failed waiting for ffprobe: {e}"))?;
-  let ffprobe_wait_ms = start_wait.elapsed().as_secs_f64() * 1000.0;
+    if let Some(audio_streams) = audio_streams {
+      entry.has_tracks = true;
+      entry.audio_streams = audio_streams;
+    }
+  })?;
+  let debug = in_process_debug("MediaFoundation", &data.cwd);
+  Some((data, debug))
+}
 
-  let (ffprobe_first_stdout_byte_ms, stdout_buf, stdout_ok) =
-    stdout_rx.recv().unwrap_or((None, Vec::new(), Err("Failed to receive ffprobe stdout".to_string())));
-  let (ffprobe_first_stderr_byte_ms, stderr_buf, stderr_ok) =
-    stderr_rx.recv().unwrap_or((None, Vec::new(), Err("Failed to receive ffprobe stderr".to_string())));
+#[tauri::command]
+fn probe_duration(input_path: String, ffmpeg_bin_dir: String) -> Result<DurationProbeResult, String> {
+  let start_total = std::time::Instant::now();
+  let input_path = normalize_input_path_for_cli(&input_path);
+  let probe = probe_service(&input_path, &ffmpeg_bin_dir, ProbeNeed::Duration)?;
+  let timing_ms = probe_timing_info(&probe, start_total.elapsed().as_secs_f64() * 1000.0);
+  let data = probe.data;
+
+  Ok(DurationProbeResult {
+    input_path: data.input_path,
+    duration_seconds: data.duration_seconds,
+    ffmpeg_bin_dir_used: data.ffmpeg_bin_dir_used,
+    ffprobe_path: data.ffprobe_path,
+    ffprobe_args: data.ffprobe_args,
+    ffprobe_runner: data.ffprobe_runner,
+    cwd: data.cwd,
+    timing_ms,
+    debug: probe.debug,
+  })
+}
 
-  stdout_ok?;
-  stderr_ok?;
+#[tauri::command]
+fn probe_tracks(input_path: String, ffmpeg_bin_dir: String) -> Result<TracksProbeResult, String> {
+  let start_total = std::time::Instant::now();
+  let input_path = normalize_input_path_for_cli(&input_path);
+  let probe = probe_service(&input_path, &ffmpeg_bin_dir, ProbeNeed::Tracks)?;
+  let data = probe.data;
 
-  if !status.success() {
-    let stderr = String::from_utf8_lossy(&stderr_buf).trim().to_string();
-    return Err(if stderr.is_empty() {
-      "ffprobe failed".to_string()
-    } else {
-      format!("ffprobe failed: {stderr}")
-    });
-  }
-
-  let duration_seconds = parse_duration_from_ffprobe_text(&stdout_buf);
-
-  let timing_ms = ProbeTimingInfo {
-    validation_ms,
-    resolve_binaries_ms,
-    ffprobe_spawn_ms,
-    ffprobe_first_stdout_byte_ms,
-    ffprobe_first_stderr_byte_ms,
-    ffprobe_execution_ms: start_spawn_total.elapsed().as_secs_f64() * 1000.0,
-    ffprobe_wait_ms,
-    json_parsing_ms: 0.0,
-    total_ms: start_total.elapsed().as_secs_f64() * 1000.0,
-    cache_hit: false,
-  };
-
-  let debug = SpawnDebugInfo {
-    phase: "duration".to_string(),
-    program: ffprobe_path_text.clone(),
-    args: ffprobe_args.clone(),
-    cwd: cwd_text.clone(),
-    program_exists: Path::new(&ffprobe_path_text).exists(),
-    exit_code: status.code(),
-    success: status.success(),
-    stdout_len: stdout_buf.len(),
-    stderr_len: stderr_buf.len(),
-    stderr_head: stderr_head_text(&stderr_buf),
-  };
-
-  let result = DurationProbeResult {
-    input_path,
-    duration_seconds,
-    ffmpeg_bin_dir_used,
-    ffprobe_path: ffprobe_path_text,
-    ffprobe_args,
-    ffprobe_runner: "direct".to_string(),
-    cwd: cwd_text,
-    timing_ms,
-    debug: Some(debug),
-  };
-
-  if let Ok(mut guard) = probe_cache().lock() {
-    let entry = guard.entry(cache_key).or_insert(CachedProbeResult {
-      input_path: result.input_path.clone(),
-      has_duration: false,
-      has_tracks: false,
-      has_subtitles: false,
-      duration_seconds: None,
-      audio_streams: Vec::new(),
-      subtitle_streams: Vec::new(),
-      ffmpeg_bin_dir_used: result.ffmpeg_bin_dir_used.clone(),
-      ffprobe_path: result.ffprobe_path.clone(),
-      ffprobe_args: result.ffprobe_args.clone(),
-      ffprobe_runner: result.ffprobe_runner.clone(),
-      cwd: result.cwd.clone(),
-    });
-
-    entry.input_path = result.input_path.clone();
-    entry.has_duration = result.duration_seconds.is_some();
-    entry.duration_seconds = result.duration_seconds;
-    entry.ffmpeg_bin_dir_used = result.ffmpeg_bin_dir_used.clone();
-    entry.ffprobe_path = result.ffprobe_path.clone();
-    entry.ffprobe_args = result.ffprobe_args.clone();
-    entry.ffprobe_runner = result.ffprobe_runner.clone();
-    entry.cwd = result.cwd.clone();
-  }
-
-  Ok(result)
+  Ok(TracksProbeResult {
+    input_path: data.input_path,
+    audio_streams: data.audio_streams,
+    subtitle_streams: data.subtitle_streams,
+    ffmpeg_bin_dir_used: data.ffmpeg_bin_dir_used,
+    ffprobe_path: data.ffprobe_path,
+    ffprobe_runner: data.ffprobe_runner,
+    cwd: data.cwd,
+    timing_ms: TracksProbeTimingInfo {
+      validation_ms: probe.validation_ms,
+      resolve_binaries_ms: probe.resolve_binaries_ms,
+      // One probe covers both audio and subtitles.
+      audio_ffprobe_ms: probe.probe_ms,
+      subs_ffprobe_ms: 0.0,
+      total_ms: start_total.elapsed().as_secs_f64() * 1000.0,
+      cache_hit: probe.cache_hit,
+    },
+    debug: probe.debug.into_iter().collect(),
+  })
 }
 
 #[tauri::command]
-fn probe_tracks(input_path: String, ffmpeg_bin_dir: String) -> Result<TracksProbeResult, String> {
-  use std::time::Instant;
-  let start_total = Instant::now();
-
+fn probe_subtitles(input_path: String, ffmpeg_bin_dir: String) -> Result<SubtitlesProbeResult, String> {
+  let start_total = std::time::Instant::now();
   let input_path = normalize_input_path_for_cli(&input_path);
+  let probe = probe_service(&input_path, &ffmpeg_bin_dir, ProbeNeed::Subtitles)?;
+  let data = probe.data;
 
-  let cache_key = probe_cache_key_best_effort(&input_path);
-  if let Ok(guard) = probe_cache().lock() {
-    if let Some(cached) = guard.get(&cache_key).cloned() {
-      if cached.has_tracks {
-        let timing_ms = TracksProbeTimingInfo {
-          validation_ms: 0.0,
-          resolve_binaries_ms: 0.0,
-          audio_ffprobe_ms: 0.0,
-          subs_ffprobe_ms: 0.0,
-          total_ms: start_total.elapsed().as_secs_f64() * 1000.0,
-          cache_hit: true,
-        };
-        return Ok(TracksProbeResult {
-          input_path: cached.input_path,
-          audio_streams: cached.audio_streams,
-          subtitle_streams: cached.subtitle_streams,
-          ffmpeg_bin_dir_used: cached.ffmpeg_bin_dir_used,
-          ffprobe_path: cached.ffprobe_path,
-          ffprobe_runner: cached.ffprobe_runner,
-          cwd: cached.cwd,
-          timing_ms,
-          debug: Vec::new(),
-        });
-      }
-    }
-  }
-
-  let start_validation = Instant::now();
-  ensure_input_file_exists(&input_path)?;
-  validate_ffmpeg_bin_dir(&ffmpeg_bin_dir)?;
-  let validation_ms = start_validation.elapsed().as_secs_f64() * 1000.0;
-
-  let start_resolve = Instant::now();
-  let (_ffmpeg_path, ffprobe_path, ffmpeg_bin_dir_used) =
-    resolve_ffmpeg_binaries_with_fallback(&ffmpeg_bin_dir);
-  let resolve_binaries_ms = start_resolve.elapsed().as_secs_f64() * 1000.0;
+  Ok(SubtitlesProbeResult {
+    input_path: data.input_path,
+    subtitle_streams: data.subtitle_streams,
+    ffmpeg_bin_dir_used: data.ffmpeg_bin_dir_used,
+    ffprobe_path: data.ffprobe_path,
+    ffprobe_runner: data.ffprobe_runner,
+    cwd: data.cwd,
+    timing_ms: SubtitlesProbeTimingInfo {
+      validation_ms: probe.validation_ms,
+      resolve_binaries_ms: probe.resolve_binaries_ms,
+      ffprobe_ms: probe.probe_ms,
+      total_ms: start_total.elapsed().as_secs_f64() * 1000.0,
+      cache_hit: probe.cache_hit,
+    },
+    debug: probe.debug,
+  })
+}
 
-  let ffprobe_path_text = ffprobe_path.to_string_lossy().to_string();
-  let workdir = stable_working_dir();
-  let cwd_text = workdir
-    .as_ref()
-    .map(|p| p.to_string_lossy().to_string())
-    .unwrap_or_else(|| String::new());
+const DEFAULT_OUTPUT_TEMPLATE: &str = "{stem}_clip_{mode}_{in}_{out}.{ext}";
+const OUTPUT_TEMPLATE_TOKENS: [&str; 8] = ["stem", "in", "out", "mode", "date", "index", "audio_lang", "ext"];
+
+struct OutputNameContext<'a> {
+  stem: &'a str,
+  mode: &'a str,
+  in_time: &'a str,
+  out_time: &'a str,
+  ext: &'a str,
+  index: u32,
+  audio_lang: &'a str,
+}
 
-  #[cfg(windows)]
-  {
-    let start_mf = Instant::now();
-    if let Ok(audio_streams) = win_mf::probe_audio_streams(&input_path) {
-      let subtitle_streams = if let Ok(guard) = probe_cache().lock() {
-        guard
-          .get(&cache_key)
-          .filter(|c| c.has_subtitles)
-          .map(|c| c.subtitle_streams.clone())
-          .unwrap_or_default()
-      } else {
-        Vec::new()
-      };
+/// Check a naming template: braces must be balanced and every `{token}` must be known.
+fn validate_output_template(template: &str) -> Result<(), String> {
+  if template.trim().is_empty() {
+    return Err("Output name template is empty".to_string());
+  }
+  if template.contains(['/', '\\']) {
+    return Err("Output name template must not contain path separators (use the output folder setting)".to_string());
+  }
+  let mut rest = template;
+  while let Some(open) = rest.find(['{', '}']) {
+    if rest[open..].starts_with('}') {
+      return Err("Output name template has an unmatched '}'".to_string());
+    }
+    let after = &rest[open + 1..];
+    let close = after
+      .find('}')
+      .ok_or_else(|| "Output name template has an unmatched '{'".to_string())?;
+    let token = &after[..close];
+    if !OUTPUT_TEMPLATE_TOKENS.contains(&token) {
+      return Err(format!(
+        "Unknown token '{{{token}}}' in output name template (allowed: {})",
+        OUTPUT_TEMPLATE_TOKENS.map(|t| format!("{{{t}}}")).join(", ")
+      ));
+    }
+    rest = &after[close + 1..];
+  }
+  Ok(())
+}
 
-      let timing_ms = TracksProbeTimingInfo {
-        validation_ms,
-        resolve_binaries_ms,
-        audio_ffprobe_ms: start_mf.elapsed().as_secs_f64() * 1000.0,
-        subs_ffprobe_ms: 0.0,
-        total_ms: start_total.elapsed().as_secs_f64() * 1000.0,
-        cache_hit: false,
-      };
+// Today's date (UTC) as YYYY-MM-DD for the `{date}` token.
+fn today_for_filename() -> String {
+  let secs = std::time::SystemTime::now()
+    .duration_since(std::time::UNIX_EPOCH)
+    .map(|d| d.as_secs() as i64)
+    .unwrap_or(0);
+  let (y, m, d) = civil_from_days(secs.div_euclid(86_400));
+  format!("{y:04}-{m:02}-{d:02}")
+}
 
-      let debug = SpawnDebugInfo {
-        phase: "tracks_audio_mf".to_string(),
-        program: "MediaFoundation".to_string(),
-        args: Vec::new(),
-        cwd: cwd_text.clone(),
-        program_exists: true,
-        exit_code: Some(0),
-        success: true,
-        stdout_len: 0,
-        stderr_len: 0,
-        stderr_head: String::new(),
-      };
+fn render_output_template(template: &str, ctx: &OutputNameContext) -> Result<String, String> {
+  validate_output_template(template)?;
+  let date = today_for_filename();
+  let mut out = template.to_string();
+  for (token, value) in [
+    ("{stem}", ctx.stem.to_string()),
+    ("{in}", time_for_filename(ctx.in_time)),
+    ("{out}", time_for_filename(ctx.out_time)),
+    ("{mode}", ctx.mode.to_string()),
+    ("{date}", date),
+    ("{index}", ctx.index.to_string()),
+    ("{audio_lang}", ctx.audio_lang.to_string()),
+    ("{ext}", ctx.ext.to_string()),
+  ] {
+    out = out.replace(token, &value);
+  }
+  if !template.contains("{ext}") {
+    out = format!("{out}.{}", ctx.ext);
+  }
+  Ok(sanitize_filename(&out))
+}
 
-      if let Ok(mut guard) = probe_cache().lock() {
-        let entry = guard.entry(cache_key).or_insert(CachedProbeResult {
-          input_path: input_path.clone(),
-          has_duration: false,
-          has_tracks: false,
-          has_subtitles: false,
-          duration_seconds: None,
-          audio_streams: Vec::new(),
-          subtitle_streams: Vec::new(),
-          ffmpeg_bin_dir_used: ffmpeg_bin_dir_used.clone(),
-          ffprobe_path: ffprobe_path_text.clone(),
-          ffprobe_args: Vec::new(),
-          ffprobe_runner: "mf".to_string(),
-          cwd: cwd_text.clone(),
-        });
+/// Replace characters that are illegal in Windows/Unix filenames and avoid reserved device names.
+fn sanitize_filename(name: &str) -> String {
+  let mut out: String = name
+    .chars()
+    .map(|c| match c {
+      '<' | '>' | ':' | '"' | '/' | '\\' | '|' | '?' | '*' => '_',
+      c if c.is_control() => '_',
+      c => c,
+    })
+    .collect();
 
-        entry.input_path = input_path.clone();
-        entry.audio_streams = audio_streams.clone();
-        entry.has_tracks = true;
-        entry.ffmpeg_bin_dir_used = ffmpeg_bin_dir_used.clone();
-        entry.ffprobe_path = ffprobe_path_text.clone();
-        entry.ffprobe_runner = "mf".to_string();
-        entry.cwd = cwd_text.clone();
-      }
+  // Windows silently drops trailing dots and spaces.
+  while out.ends_with('.') || out.ends_with(' ') {
+    out.pop();
+  }
+  if out.is_empty() {
+    out = "clip".to_string();
+  }
 
-      return Ok(TracksProbeResult {
-        input_path,
-        audio_streams,
-        subtitle_streams,
-        ffmpeg_bin_dir_used,
-        ffprobe_path: ffprobe_path_text,
-        ffprobe_runner: "mf".to_string(),
-        cwd: cwd_text,
-        timing_ms,
-        debug: vec![debug],
-      });
-    }
+  let base = out.split('.').next().unwrap_or("").to_ascii_uppercase();
+  let reserved = matches!(base.as_str(), "CON" | "PRN" | "AUX" | "NUL")
+    || ((base.starts_with("COM") || base.starts_with("LPT"))
+      && base.len() == 4
+      && base.chars().last().is_some_and(|c| c.is_ascii_digit()));
+  if reserved {
+    out = format!("_{out}");
   }
+  out
+}
 
-  let run_json = |phase: &str, args: Vec<String>| -> Result<(Vec<u8>, f64, SpawnDebugInfo), String> {
-    let start = Instant::now();
-    let mut c = Command::new(&ffprobe_path);
-    apply_no_window(&mut c);
-    c.args(&args);
-    if let Some(dir) = &workdir {
-      c.current_dir(dir);
-    }
-    let out = c
-      .stdin(Stdio::null())
-      .stdout(Stdio::piped())
-      .stderr(Stdio::piped())
-      .output()
-      .map_err(|e| {
-        if e.kind() == ErrorKind::NotFound {
-          "Failed to run ffprobe: program not found (set FFmpeg bin folder or add ffprobe to PATH)".to_string()
-        } else {
-          format!("Failed to run ffprobe: {e}")
-        }
-      })?;
+fn build_output_path(
+  input_path: &str,
+  mode: &str,
+  in_time: &str,
+  out_time: &str,
+  options: &TrimOptions,
+  audio_lang: &str,
+) -> Result<PathBuf, String> {
+  let input = Path::new(input_path);
+  let stem = input
+    .file_stem()
+    .ok_or_else(|| "Could not determine input filename".to_string())?
+    .to_string_lossy();
+  let extension = match options.container.as_deref().map(str::trim).filter(|c| !c.is_empty()) {
+    Some(container) => container.to_lowercase(),
+    None => input
+      .extension()
+      .map(|e| e.to_string_lossy().to_string())
+      .unwrap_or_else(|| "mp4".to_string()),
+  };
 
-    if !out.status.success() {
-      let stderr = String::from_utf8_lossy(&out.stderr).trim().to_string();
-      return Err(if stderr.is_empty() {
-        "ffprobe failed".to_string()
-      } else {
-        format!("ffprobe failed: {stderr}")
-      });
+  let parent = match options.output_dir.as_deref().map(str::trim).filter(|d| !d.is_empty()) {
+    Some(dir) => {
+      // Created by the export itself, so previews and dry runs leave nothing behind.
+      let dir = PathBuf::from(dir);
+      if dir.exists() && !dir.is_dir() {
+        return Err("Output folder is not a directory".to_string());
+      }
+      dir
     }
-
-    let debug = SpawnDebugInfo {
-      phase: phase.to_string(),
-      program: ffprobe_path_text.clone(),
-      args: args.clone(),
-      cwd: cwd_text.clone(),
-      program_exists: Path::new(&ffprobe_path_text).exists(),
-      exit_code: out.status.code(),
-      success: out.status.success(),
-      stdout_len: out.stdout.len(),
-      stderr_len: out.stderr.len(),
-      stderr_head: stderr_head_text(&out.stderr),
-    };
-
-    Ok((out.stdout, start.elapsed().as_secs_f64() * 1000.0, debug))
+    None => input
+      .parent()
+      .ok_or_else(|| "Could not determine input folder".to_string())?
+      .to_path_buf(),
   };
 
-  let audio_args: Vec<String> = vec![
-    "-v".to_string(),
-    "error".to_string(),
-    "-print_format".to_string(),
-    "json".to_string(),
-    "-select_streams".to_string(),
-    "a".to_string(),
-    "-probesize".to_string(),
-    "10M".to_string(),
-    "-analyzeduration".to_string(),
-    "10M".to_string(),
-    "-show_entries".to_string(),
-    "stream=index,codec_type,codec_name,channels:stream_tags=language,title".to_string(),
-    input_path.clone(),
-  ];
-
-  let subs_args: Vec<String> = vec![
-    "-v".to_string(),
-    "error".to_string(),
-    "-print_format".to_string(),
-    "json".to_string(),
-    "-select_streams".to_string(),
-    "s".to_string(),
-    "-probesize".to_string(),
-    "10M".to_string(),
-    "-analyzeduration".to_string(),
-    "10M".to_string(),
-    "-show_entries".to_string(),
-    "stream=index,codec_type,codec_name:stream_tags=language,title".to_string(),
-    input_path.clone(),
-  ];
-
-  let (audio_stdout, audio_ms, audio_debug) = run_json("tracks_audio", audio_args)?;
-  let (mut audio_streams, _subtitle_ignored) = parse_streams_from_ffprobe_json(&audio_stdout)?;
-
-  let (subs_stdout, subs_ms, subs_debug) = run_json("tracks_subs", subs_args)?;
-  let (_audio_ignored, subtitle_streams) = parse_streams_from_ffprobe_json(&subs_stdout)?;
-
-  // Ensure audio streams are in stable order by index.
-  audio_streams.sort_by(|a, b| a.index.cmp(&b.index));
+  let template = options
+    .name_template
+    .as_deref()
+    .filter(|t| !t.trim().is_empty())
+    .unwrap_or(DEFAULT_OUTPUT_TEMPLATE);
+  let filename = render_output_template(
+    template,
+    &OutputNameContext {
+      stem: &stem,
+      mode,
+      in_time,
+      out_time,
+      ext: &extension,
+      index: options.index.unwrap_or(1),
+      audio_lang,
+    },
+  )?;
+  Ok(parent.join(filename))
+}
 
-  let timing_ms = TracksProbeTimingInfo {
-    validation_ms,
-    resolve_binaries_ms,
-    audio_ffprobe_ms: audio_ms,
-    subs_ffprobe_ms: subs_ms,
-    total_ms: start_total.elapsed().as_secs_f64() * 1000.0,
-    cache_hit: false,
+/// Refuse an output that resolves to the source file itself (e.g. `{stem}.{ext}` next to the source),
+/// which the final rename would otherwise replace with the clip.
+fn ensure_output_is_not_input(output_path: &Path, input_path: &str) -> Result<(), String> {
+  let same = match (fs::canonicalize(output_path), fs::canonicalize(input_path)) {
+    (Ok(output), Ok(input)) => output == input,
+    _ => false,
   };
-
-  // Update cache with tracks; duration is left as-is.
-  if let Ok(mut guard) = probe_cache().lock() {
-    let entry = guard.entry(cache_key).or_insert(CachedProbeResult {
-      input_path: input_path.clone(),
-      has_duration: false,
-      has_tracks: false,
-      has_subtitles: false,
-      duration_seconds: None,
-      audio_streams: Vec::new(),
-      subtitle_streams: Vec::new(),
-      ffmpeg_bin_dir_used: ffmpeg_bin_dir_used.clone(),
-      ffprobe_path: ffprobe_path_text.clone(),
-      ffprobe_args: Vec::new(),
-      ffprobe_runner: "direct".to_string(),
-      cwd: cwd_text.clone(),
-    });
-    entry.audio_streams = audio_streams.clone();
-    entry.subtitle_streams = subtitle_streams.clone();
-    entry.has_tracks = true;
-    entry.has_subtitles = true;
-    entry.ffmpeg_bin_dir_used = ffmpeg_bin_dir_used.clone();
-    entry.ffprobe_path = ffprobe_path_text.clone();
-    entry.ffprobe_runner = "direct".to_string();
-    entry.cwd = cwd_text.clone();
+  if same {
+    return Err(format!(
+      "The output would replace the source file ({}); change the name template or output folder",
+      output_path.to_string_lossy()
+    ));
   }
-
-  Ok(TracksProbeResult {
-    input_path,
-    audio_streams,
-    subtitle_streams,
-    ffmpeg_bin_dir_used,
-    ffprobe_path: ffprobe_path_text,
-    ffprobe_runner: "direct".to_string(),
-    cwd: cwd_text,
-    timing_ms,
-    debug: vec![audio_debug, subs_debug],
-  })
+  Ok(())
 }
 
-#[tauri::command]
-fn probe_subtitles(input_path: String, ffmpeg_bin_dir: String) -> Result<SubtitlesProbeResult, String> {
-  use std::time::Instant;
-  let start_total = Instant::now();
-
-  let input_path = normalize_input_path_for_cli(&input_path);
-  let cache_key = probe_cache_key_best_effort(&input_path);
-
-  if let Ok(guard) = probe_cache().lock() {
-    if let Some(cached) = guard.get(&cache_key).cloned() {
-      if cached.has_subtitles {
-        return Ok(SubtitlesProbeResult {
-          input_path: cached.input_path,
-          subtitle_streams: cached.subtitle_streams,
-          ffmpeg_bin_dir_used: cached.ffmpeg_bin_dir_used,
-          ffprobe_path: cached.ffprobe_path,
-          ffprobe_runner: cached.ffprobe_runner,
-          cwd: cached.cwd,
-          timing_ms: SubtitlesProbeTimingInfo {
-            validation_ms: 0.0,
-            resolve_binaries_ms: 0.0,
-            ffprobe_ms: 0.0,
-            total_ms: start_total.elapsed().as_secs_f64() * 1000.0,
-            cache_hit: true,
-          },
-          debug: None,
-        });
+/// Resolve a name clash according to the collision policy: "overwrite" (default), "number" or "fail".
+fn apply_output_collision_policy(base: PathBuf, policy: Option<&str>) -> Result<PathBuf, String> {
+  let policy = policy.map(|p| p.trim().to_lowercase()).unwrap_or_default();
+  match policy.as_str() {
+    "number" => {}
+    "" | "overwrite" => return Ok(base),
+    "fail" => {
+      if base.exists() {
+        return Err(format!("Output file already exists: {}", base.to_string_lossy()));
       }
+      return Ok(base);
     }
+    other => return Err(format!("Unknown collision policy '{other}' (expected number, overwrite or fail)")),
   }
 
-  let start_validation = Instant::now();
-  ensure_input_file_exists(&input_path)?;
-  validate_ffmpeg_bin_dir(&ffmpeg_bin_dir)?;
-  let validation_ms = start_validation.elapsed().as_secs_f64() * 1000.0;
-
-  let start_resolve = Instant::now();
-  let (_ffmpeg_path, ffprobe_path, ffmpeg_bin_dir_used) =
-    resolve_ffmpeg_binaries_with_fallback(&ffmpeg_bin_dir);
-  let resolve_binaries_ms = start_resolve.elapsed().as_secs_f64() * 1000.0;
-
-  let ffprobe_path_text = ffprobe_path.to_string_lossy().to_string();
-  let workdir = stable_working_dir();
-  let cwd_text = workdir
-    .as_ref()
-    .map(|p| p.to_string_lossy().to_string())
-    .unwrap_or_else(|| String::new());
-
-  let args: Vec<String> = vec![
-    "-v".to_string(),
-    "error".to_string(),
-    "-print_format".to_string(),
-    "json".to_string(),
-    "-select_streams".to_string(),
-    "s".to_string(),
-    "-probesize".to_string(),
-    "10M".to_string(),
-    "-analyzeduration".to_string(),
-    "10M".to_string(),
-    "-show_entries".to_string(),
-    "stream=index,codec_type,codec_name:stream_tags=language,title".to_string(),
-    input_path.clone(),
-  ];
-
-  let start_ffprobe = Instant::now();
-  let mut c = Command::new(&ffprobe_path);
-  apply_no_window(&mut c);
-  c.args(&args);
-  if let Some(dir) = &workdir {
-    c.current_dir(dir);
+  if !base.exists() {
+    return Ok(base);
   }
-  let out = c
-    .stdin(Stdio::null())
-    .stdout(Stdio::piped())
-    .stderr(Stdio::piped())
-    .output()
-    .map_err(|e| {
-      if e.kind() == ErrorKind::NotFound {
-        "Failed to run ffprobe: program not found (set FFmpeg bin folder or add ffprobe to PATH)".to_string()
-      } else {
-        format!("Failed to run ffprobe: {e}")
-      }
-    })?;
-
-  let ffprobe_ms = start_ffprobe.elapsed().as_secs_f64() * 1000.0;
-
-  let debug = SpawnDebugInfo {
-    phase: "subs".to_string(),
-    program: ffprobe_path_text.clone(),
-    args: args.clone(),
-    cwd: cwd_text.clone(),
-    program_exists: Path::new(&ffprobe_path_text).exists(),
-    exit_code: out.status.code(),
-    success: out.status.success(),
-    stdout_len: out.stdout.len(),
-    stderr_len: out.stderr.len(),
-    stderr_head: stderr_head_text(&out.stderr),
-  };
-
-  if !out.status.success() {
-    let stderr = String::from_utf8_lossy(&out.stderr).trim().to_string();
-    return Err(if stderr.is_empty() {
-      "ffprobe failed".to_string()
-    } else {
-      format!("ffprobe failed: {stderr}")
-    });
+  // Auto-number: file (1), file (2), etc.
+  let stem = base.file_stem().unwrap_or_default().to_string_lossy().to_string();
+  let ext = base.extension().map(|e| e.to_string_lossy().to_string()).unwrap_or_default();
+  let parent = base.parent().unwrap_or_else(|| Path::new("."));
+  for i in 1..=999 {
+    let numbered = parent.join(format!("{stem} ({i}).{ext}"));
+    if !numbered.exists() {
+      return Ok(numbered);
+    }
   }
+  Err(format!("Output names up to \"{stem} (999).{ext}\" are all taken in {}", parent.to_string_lossy()))
+}
 
-  let (_audio_ignored, subtitle_streams) = parse_streams_from_ffprobe_json(&out.stdout)?;
-
-  if let Ok(mut guard) = probe_cache().lock() {
-    let entry = guard.entry(cache_key).or_insert(CachedProbeResult {
-      input_path: input_path.clone(),
-      has_duration: false,
-      has_tracks: false,
-      has_subtitles: false,
-      duration_seconds: None,
-      audio_streams: Vec::new(),
-      subtitle_streams: Vec::new(),
-      ffmpeg_bin_dir_used: ffmpeg_bin_dir_used.clone(),
-      ffprobe_path: ffprobe_path_text.clone(),
-      ffprobe_args: Vec::new(),
-      ffprobe_runner: "direct".to_string(),
-      cwd: cwd_text.clone(),
-    });
-    entry.input_path = input_path.clone();
-    entry.subtitle_streams = subtitle_streams.clone();
-    entry.has_subtitles = true;
-    entry.ffmpeg_bin_dir_used = ffmpeg_bin_dir_used.clone();
-    entry.ffprobe_path = ffprobe_path_text.clone();
-    entry.ffprobe_runner = "direct".to_string();
-    entry.cwd = cwd_text.clone();
+fn audio_language_best_effort(ffprobe_path: &Path, input_path: &str, audio_order: i32) -> String {
+  if audio_order < 0 {
+    return "none".to_string();
   }
-
-  Ok(SubtitlesProbeResult {
-    input_path,
-    subtitle_streams,
-    ffmpeg_bin_dir_used,
-    ffprobe_path: ffprobe_path_text,
-    ffprobe_runner: "direct".to_string(),
-    cwd: cwd_text,
-    timing_ms: SubtitlesProbeTimingInfo {
-      validation_ms,
-      resolve_binaries_ms,
-      ffprobe_ms,
-      total_ms: start_total.elapsed().as_secs_f64() * 1000.0,
-      cache_hit: false,
-    },
-    debug: Some(debug),
-  })
+  let cache_key = probe_cache_key_best_effort(input_path);
+  let cached = probe_cache().lock().ok().and_then(|guard| {
+    guard
+      .get(&cache_key)
+      .filter(|c| c.has_tracks)
+      .map(|c| c.audio_streams.clone())
+  });
+  let streams = cached.unwrap_or_else(|| {
+    media_backend::current()
+      .probe(
+        ffprobe_path,
+        &media_backend::args(&[
+          "-v",
+          "error",
+          "-print_format",
+          "json",
+          "-select_streams",
+          "a",
+          "-show_entries",
+          "stream=index,codec_type,codec_name,channels:stream_tags=language,title",
+          input_path,
+        ]),
+      )
+      .ok()
+      .filter(|o| o.success)
+      .and_then(|o| parse_streams_from_ffprobe_json(&o.stdout).ok())
+      .map(|(audio, _subs)| audio)
+      .unwrap_or_default()
+  });
+  streams
+    .into_iter()
+    .find(|a| a.order == audio_order)
+    .map(|a| a.language)
+    .unwrap_or_else(|| "und".to_string())
 }
 
-fn build_output_path(input_path: &str, mode: &str, in_time: &str, out_time: &str) -> Result<PathBuf, String> {
-  let input = Path::new(input_path);
-  let parent = input
-    .parent()
-    .ok_or_else(|| "Could not determine input folder".to_string())?;
-  let stem = input
-    .file_stem()
-    .ok_or_else(|| "Could not determine input filename".to_string())?
-    .to_string_lossy();
-  let extension = input
-    .extension()
-    .map(|e| e.to_string_lossy().to_string())
-    .unwrap_or_else(|| "mp4".to_string());
-
-  let suffix_in = time_for_filename(in_time);
-  let suffix_out = time_for_filename(out_time);
-  let filename = format!(
-    "{}_clip_{}_{}_{}.{}",
-    stem,
-    mode,
-    suffix_in,
-    suffix_out,
-    extension
-  );
-  Ok(parent.join(filename))
+#[tauri::command]
+fn check_output_template(template: String) -> Result<String, String> {
+  // Render a sample so the UI can show what the template produces.
+  render_output_template(
+    template.trim(),
+    &OutputNameContext {
+      stem: "video",
+      mode: "lossless",
+      in_time: "00:01:05.250",
+      out_time: "00:02:10.000",
+      ext: "mp4",
+      index: 1,
+      audio_lang: "eng",
+    },
+  )
 }
 
 #[tauri::command]
@@ -1717,24 +1901,23 @@ fn check_ffmpeg(ffmpeg_bin_dir: String) -> Result<FfmpegCheckResult, String> {
   let (ffmpeg_path, ffprobe_path, ffmpeg_bin_dir_used) =
     resolve_ffmpeg_binaries_with_fallback(&ffmpeg_bin_dir);
 
+  let backend = media_backend::current();
   let run = |exe: &Path, name: &str| -> Result<(), String> {
-    let mut cmd = Command::new(exe);
-    apply_no_window(&mut cmd);
-    let output = cmd
-      .arg("-version")
-      .stdin(Stdio::null())
-      .stdout(Stdio::piped())
-      .stderr(Stdio::piped())
-      .output()
-      .map_err(|e| {
-        if e.kind() == ErrorKind::NotFound {
-          format!("{name} not found")
-        } else {
-          format!("Failed to run {name}: {e}")
-        }
-      })?;
+    let version = media_backend::args(&["-version"]);
+    let output = if name == "ffprobe" {
+      backend.probe(exe, &version)
+    } else {
+      backend.run_encode(exe, &version, &mut |_| true)
+    }
+    .map_err(|e| {
+      if e.contains("program not found") {
+        format!("{name} not found")
+      } else {
+        e
+      }
+    })?;
 
-    if !output.status.success() {
+    if !output.success {
       let stderr = String::from_utf8_lossy(&output.stderr).trim().to_string();
       return Err(if stderr.is_empty() {
         format!("{name} failed")
@@ -1809,83 +1992,102 @@ fn install_ffmpeg_winget() -> Result<(), String> {
 
 /// Probe the duration of a media file using ffprobe (returns seconds).
 fn probe_duration_ffprobe(ffprobe_path: &Path, file_path: &Path) -> Option<f64> {
-  let mut cmd = Command::new(ffprobe_path);
-  apply_no_window(&mut cmd);
-  let output = cmd
-    .args([
-      "-v", "error",
-      "-show_entries", "format=duration",
-      "-of", "default=noprint_wrappers=1:nokey=1",
-    ])
-    .arg(file_path)
-    .stdin(Stdio::null())
-    .stdout(Stdio::piped())
-    .stderr(Stdio::piped())
-    .output()
+  let file_path = file_path.to_string_lossy();
+  let output = media_backend::current()
+    .probe(
+      ffprobe_path,
+      &media_backend::args(&[
+        "-v", "error",
+        "-show_entries", "format=duration",
+        "-of", "default=noprint_wrappers=1:nokey=1",
+        &file_path,
+      ]),
+    )
     .ok()?;
   let stdout = String::from_utf8_lossy(&output.stdout);
   stdout.trim().parse::<f64>().ok()
 }
 
-fn run_ffprobe_keyframes(
-  ffprobe_path: &Path,
-  input_path: &str,
-  read_intervals: &str,
-) -> Result<Vec<f64>, String> {
-  let mut cmd = Command::new(ffprobe_path);
-  apply_no_window(&mut cmd);
-  let output = cmd
-    .args([
-      "-v",
-      "quiet",
-      "-select_streams",
-      "v:0",
-      "-skip_frame",
-      "nokey",
-      "-read_intervals",
-      read_intervals,
-      "-print_format",
-      "json",
-      "-show_frames",
-      "-show_entries",
-      "frame=best_effort_timestamp_time",
-    ])
-    .arg(input_path)
-    .stdin(Stdio::null())
-    .stdout(Stdio::piped())
-    .stderr(Stdio::piped())
-    .output()
-    .map_err(|e| {
-      if e.kind() == ErrorKind::NotFound {
-        "Failed to run ffprobe: program not found (set FFmpeg bin folder or add ffprobe to PATH)".to_string()
-      } else {
-        format!("Failed to run ffprobe: {e}")
-      }
-    })?;
+#[derive(Debug, Serialize)]
+struct KeyframeIndexInfo {
+  keyframe_count: usize,
+  first_keyframe_seconds: Option<f64>,
+  last_keyframe_seconds: Option<f64>,
+  from_cache: bool,
+}
 
-  if !output.status.success() {
-    let stderr = String::from_utf8_lossy(&output.stderr).trim().to_string();
-    return Err(if stderr.is_empty() {
-      "ffprobe failed".to_string()
-    } else {
-      format!("ffprobe failed: {stderr}")
-    });
-  }
+/// Scan the whole video stream for keyframes once and keep the result, so later preflights are instant.
+/// Emits `keyframe_index_progress` while scanning.
+#[tauri::command]
+async fn build_keyframe_index(window: tauri::Window, input_path: String, ffmpeg_bin_dir: String) -> Result<KeyframeIndexInfo, String> {
+  tauri::async_runtime::spawn_blocking(move || build_keyframe_index_sync(window, input_path, ffmpeg_bin_dir))
+    .await
+    .map_err(|e| format!("build_keyframe_index failed: {e}"))?
+}
 
-  let json: serde_json::Value =
-    serde_json::from_slice(&output.stdout).map_err(|e| format!("Invalid ffprobe JSON: {e}"))?;
-
-  let mut times = Vec::new();
-  if let Some(frames) = json.get("frames").and_then(|f| f.as_array()) {
-    for frame in frames {
-      if let Some(ts) = frame.get("best_effort_timestamp_time").and_then(|v| v.as_str()) {
-        if let Ok(v) = ts.parse::<f64>() {
-          times.push(v);
-        }
-      }
-    }
-  }
-  Ok(times)
+fn build_keyframe_index_sync(window: tauri::Window, input_path: String, ffmpeg_bin_dir: String) -> Result<KeyframeIndexInfo, String> {
+  let input_path = normalize_input_path_for_cli(&input_path);
+  ensure_input_file_exists(&input_path)?;
+  validate_ffmpeg_bin_dir(&ffmpeg_bin_dir)?;
+
+  let (_ffmpeg_path, ffprobe_path, _ffmpeg_bin_dir_used) =
+    resolve_ffmpeg_binaries_with_fallback(&ffmpeg_bin_dir);
+  let key = probe_cache_key_best_effort(&input_path);
+  let duration = if keyframes::lookup(&input_path, &key).is_some() {
+    None
+  } else {
+    probe_duration_ffprobe(&ffprobe_path, Path::new(&input_path))
+  };
+
+  let (index, from_cache) = keyframes::get_or_build(&ffprobe_path, &input_path, &key, duration, |percent| {
+    let _ = window.emit(
+      "keyframe_index_progress",
+      serde_json::json!({ "inputPath": &input_path, "percent": percent }),
+    );
+    true
+  })?;
+
+  Ok(KeyframeIndexInfo {
+    keyframe_count: index.len(),
+    first_keyframe_seconds: index.first().copied(),
+    last_keyframe_seconds: index.last().copied(),
+    from_cache,
+  })
+}
+
+/// Start background analysis of a newly opened file (probe, keyframe index, thumbnails, waveform),
+/// cancelling the analysis of the previous file. Returns at once with the run's generation;
+/// progress arrives as `analysis_progress` events.
+#[tauri::command]
+fn analyze_file(window: tauri::Window, input_path: String, ffmpeg_bin_dir: String) -> Result<u64, String> {
+  let input_path = normalize_input_path_for_cli(&input_path);
+  ensure_input_file_exists(&input_path)?;
+  validate_ffmpeg_bin_dir(&ffmpeg_bin_dir)?;
+
+  let (ffmpeg_path, ffprobe_path, ffmpeg_bin_dir_used) =
+    resolve_ffmpeg_binaries_with_fallback(&ffmpeg_bin_dir);
+  Ok(analysis::start(window, input_path, ffmpeg_path, ffprobe_path, ffmpeg_bin_dir_used))
+}
+
+#[tauri::command]
+fn cancel_analysis() {
+  analysis::cancel();
+}
+
+/// Thumbnails from a finished `analyze_file` run, or None if that stage has not completed.
+#[tauri::command]
+fn thumbnail_strip(input_path: String) -> Option<analysis::ThumbnailStrip> {
+  let input_path = normalize_input_path_for_cli(&input_path);
+  let key = probe_cache_key_best_effort(&input_path);
+  let duration = cached_full_probe(&key)?.duration_seconds;
+  analysis::cached_thumbnails(&key, duration)
+}
+
+/// Waveform peaks from a finished `analyze_file` run, or None if that stage has not completed.
+#[tauri::command]
+fn waveform_peaks(input_path: String) -> Option<analysis::Waveform> {
+  let input_path = normalize_input_path_for_cli(&input_path);
+  analysis::cached_waveform(&probe_cache_key_best_effort(&input_path))
 }
 
 #[tauri::command]
@@ -1895,37 +2097,14 @@ async fn lossless_preflight(input_path: String, in_time: String, out_time: Strin
     .map_err(|e| format!("lossless_preflight failed: {e}"))?
 }
 
-/// Find the last keyframe at or before `target` and the first keyframe at or after `target`.
+/// Find the last keyframe at or before `target` and the first keyframe at or after `target`, from the
+/// file's keyframe index (built on first use, then answered from the cache).
 fn find_surrounding_keyframes(ffprobe_path: &Path, input_path: &str, target: f64) -> (Option<f64>, Option<f64>) {
-  let windows = [60.0_f64, 600.0_f64, 3600.0_f64];
-
-  // Keyframe before (or at) target
-  let mut prev: Option<f64> = None;
-  for w in windows {
-    let start = (target - w).max(0.0);
-    let read_intervals = format!("{start}%{target}");
-    if let Ok(mut times) = run_ffprobe_keyframes(ffprobe_path, input_path, &read_intervals) {
-      times.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
-      if let Some(last) = times.last().copied() {
-        prev = Some(last);
-        break;
-      }
-    }
-  }
-
-  // Keyframe after (or at) target
-  let mut next: Option<f64> = None;
-  for w in windows {
-    let end = target + w;
-    let read_intervals = format!("{target}%{end}");
-    if let Ok(mut times) = run_ffprobe_keyframes(ffprobe_path, input_path, &read_intervals) {
-      times.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
-      if let Some(first) = times.into_iter().find(|t| *t + 1e-6 >= target) {
-        next = Some(first);
-        break;
-      }
-    }
-  }
+  let key = probe_cache_key_best_effort(&normalize_input_path_for_cli(input_path));
+  let Ok((index, _)) = keyframes::get_or_build(ffprobe_path, input_path, &key, None, |_| true) else {
+    return (None, None);
+  };
+  let (prev, next) = keyframes::surrounding(&index, target);
 
   // Round to millisecond precision
   let prev = prev.map(|v| (v * 1000.0).round() / 1000.0);
@@ -1933,16 +2112,90 @@ fn find_surrounding_keyframes(ffprobe_path: &Path, input_path: &str, target: f64
   (prev, next)
 }
 
-fn lossless_preflight_sync(input_path: String, in_time: String, out_time: String, ffmpeg_bin_dir: String) -> Result<LosslessPreflightResult, String> {
+/// Video frame rate for timecode math: r_frame_rate (the stream's base rate), else avg_frame_rate.
+fn probe_frame_rate_best_effort(ffprobe_path: &Path, input_path: &str) -> Option<f64> {
+  let output = media_backend::current()
+    .probe(
+      ffprobe_path,
+      &media_backend::args(&[
+        "-v",
+        "error",
+        "-select_streams",
+        "v:0",
+        "-show_entries",
+        "stream=r_frame_rate,avg_frame_rate",
+        "-of",
+        "json",
+        input_path,
+      ]),
+    )
+    .ok()?;
+  if !output.success {
+    return None;
+  }
+  let json: serde_json::Value = serde_json::from_slice(&output.stdout).ok()?;
+  let stream = json.get("streams")?.as_array()?.first()?;
+  ["r_frame_rate", "avg_frame_rate"]
+    .iter()
+    .filter_map(|key| stream.get(*key).and_then(|v| v.as_str()).and_then(parse_ffprobe_rate))
+    .find(|fps| fps.is_finite() && *fps > 0.0)
+}
+
+#[derive(Debug, Serialize)]
+struct TimecodeConversion {
+  seconds: f64,
+  text: String,
+  frame_rate: Option<f64>,
+}
+
+/// Convert a time in any accepted form to `format` (clock, smpte, smpte_drop_frame, frames, seconds)
+/// using the file's frame rate.
+#[tauri::command]
+fn convert_timecode(
+  input_path: String,
+  time: String,
+  format: timecode::TimecodeFormat,
+  ffmpeg_bin_dir: String,
+) -> Result<TimecodeConversion, String> {
+  let input_path = normalize_input_path_for_cli(&input_path);
   ensure_input_file_exists(&input_path)?;
   validate_ffmpeg_bin_dir(&ffmpeg_bin_dir)?;
 
-  let in_seconds = parse_hh_mm_ss_with_millis(&in_time)?;
-  let out_seconds = parse_hh_mm_ss_with_millis(&out_time)?;
+  let (_ffmpeg_path, ffprobe_path, _ffmpeg_bin_dir_used) =
+    resolve_ffmpeg_binaries_with_fallback(&ffmpeg_bin_dir);
+  let needs_rate = timecode::needs_frame_rate(&time)
+    || !matches!(format, timecode::TimecodeFormat::Clock | timecode::TimecodeFormat::Seconds);
+  let frame_rate = if needs_rate {
+    probe_frame_rate_best_effort(&ffprobe_path, &input_path)
+  } else {
+    None
+  };
+
+  let seconds = timecode::parse(&time, frame_rate)?;
+  let text = timecode::format(seconds, format, frame_rate)?;
+  Ok(TimecodeConversion { seconds, text, frame_rate })
+}
+
+/// Probe the frame rate only if one of `times` is a SMPTE timecode or frame number.
+fn frame_rate_for_times<'a>(ffprobe_path: &Path, input_path: &str, times: impl IntoIterator<Item = &'a str>) -> Option<f64> {
+  if times.into_iter().any(timecode::needs_frame_rate) {
+    probe_frame_rate_best_effort(ffprobe_path, input_path)
+  } else {
+    None
+  }
+}
+
+fn lossless_preflight_sync(input_path: String, in_time: String, out_time: String, ffmpeg_bin_dir: String) -> Result<LosslessPreflightResult, String> {
+  ensure_input_file_exists(&input_path)?;
+  validate_ffmpeg_bin_dir(&ffmpeg_bin_dir)?;
 
   let (_ffmpeg_path, ffprobe_path, _ffmpeg_bin_dir_used) =
     resolve_ffmpeg_binaries_with_fallback(&ffmpeg_bin_dir);
 
+  let frame_rate = frame_rate_for_times(&ffprobe_path, &input_path, [in_time.as_str(), out_time.as_str()]);
+  let in_seconds = timecode::parse(&in_time, frame_rate)?;
+  let out_seconds = timecode::parse(&out_time, frame_rate)?;
+
   // --- IN point analysis ---
   let (nearest, next) = if in_seconds <= 0.0 {
     (Some(0.0), Some(0.0))
@@ -1973,204 +2226,466 @@ fn lossless_preflight_sync(input_path: String, in_time: String, out_time: String
   })
 }
 
-#[tauri::command]
-fn probe_media(input_path: String, ffmpeg_bin_dir: String) -> Result<ProbeResult, String> {
-  use std::time::Instant;
-  let start_total = Instant::now();
+#[derive(Debug, Serialize)]
+struct SnapToKeyframeResult {
+  // Snapped position in hh:mm:ss.mmm, ready to use as IN/OUT.
+  time: String,
+  seconds: f64,
+  // Signed: negative when the snap moved earlier.
+  distance_seconds: f64,
+}
 
-  let input_path = normalize_input_path_for_cli(&input_path);
+/// Move `time` onto a keyframe (`previous`, `next` or `nearest`) so a lossless cut starts or ends cleanly.
+#[tauri::command]
+async fn snap_to_keyframe(
+  input_path: String,
+  time: String,
+  direction: String,
+  ffmpeg_bin_dir: String,
+) -> Result<SnapToKeyframeResult, String> {
+  tauri::async_runtime::spawn_blocking(move || snap_to_keyframe_sync(input_path, time, direction, ffmpeg_bin_dir))
+    .await
+    .map_err(|e| format!("snap_to_keyframe failed: {e}"))?
+}
 
-  let start_validation = Instant::now();
+fn snap_to_keyframe_sync(
+  input_path: String,
+  time: String,
+  direction: String,
+  ffmpeg_bin_dir: String,
+) -> Result<SnapToKeyframeResult, String> {
   ensure_input_file_exists(&input_path)?;
   validate_ffmpeg_bin_dir(&ffmpeg_bin_dir)?;
-  let validation_ms = start_validation.elapsed().as_secs_f64() * 1000.0;
-  eprintln!("[PERF] Validation took: {:?}", start_validation.elapsed());
 
-  let start_resolve = Instant::now();
-  let (_ffmpeg_path, ffprobe_path, ffmpeg_bin_dir_used) =
+  let direction = direction.trim().to_lowercase();
+  if !matches!(direction.as_str(), "previous" | "next" | "nearest") {
+    return Err("Direction must be 'previous', 'next' or 'nearest'".to_string());
+  }
+
+  let (_ffmpeg_path, ffprobe_path, _ffmpeg_bin_dir_used) =
     resolve_ffmpeg_binaries_with_fallback(&ffmpeg_bin_dir);
-  let resolve_binaries_ms = start_resolve.elapsed().as_secs_f64() * 1000.0;
-  eprintln!("[PERF] Resolve binaries took: {:?}", start_resolve.elapsed());
+  let frame_rate = frame_rate_for_times(&ffprobe_path, &input_path, [time.as_str()]);
+  let seconds = timecode::parse(&time, frame_rate)?;
 
-  let ffprobe_path_text = ffprobe_path.to_string_lossy().to_string();
-  let workdir = stable_working_dir();
-  let cwd_text = workdir
-    .as_ref()
-    .map(|p| p.to_string_lossy().to_string())
-    .unwrap_or_else(|| String::new());
+  let (prev, next) = if seconds <= 0.0 {
+    (Some(0.0), Some(0.0))
+  } else {
+    find_surrounding_keyframes(&ffprobe_path, &input_path, seconds)
+  };
+  let snapped = match direction.as_str() {
+    "previous" => prev,
+    "next" => next,
+    _ => match (prev, next) {
+      (Some(p), Some(n)) => Some(if seconds - p <= n - seconds { p } else { n }),
+      (p, n) => p.or(n),
+    },
+  }
+  .ok_or_else(|| format!("No {direction} keyframe found around {}", timecode::format_clock(seconds)))?;
 
-  let cache_key = probe_cache_key_best_effort(&input_path);
-  if let Ok(guard) = probe_cache().lock() {
-    if let Some(cached) = guard.get(&cache_key).cloned() {
-      let timing_ms = ProbeTimingInfo {
-        validation_ms,
-        resolve_binaries_ms,
-        ffprobe_spawn_ms: 0.0,
-        ffprobe_first_stdout_byte_ms: None,
-        ffprobe_first_stderr_byte_ms: None,
-        ffprobe_execution_ms: 0.0,
-        ffprobe_wait_ms: 0.0,
-        json_parsing_ms: 0.0,
-        total_ms: start_total.elapsed().as_secs_f64() * 1000.0,
-        cache_hit: true,
-      };
+  Ok(SnapToKeyframeResult {
+    time: timecode::format_clock(snapped),
+    seconds: snapped,
+    distance_seconds: ((snapped - seconds) * 1000.0).round() / 1000.0,
+  })
+}
+
+#[derive(Clone, Debug, Serialize)]
+struct FrameTimestamp {
+  seconds: f64,
+  time: String,
+  key_frame: bool,
+  // I, P or B (empty if ffprobe did not report it).
+  pict_type: String,
+}
+
+#[derive(Debug, Serialize)]
+struct NeighborFramesResult {
+  // Frame shown at the requested time (last frame starting at or before it).
+  current: Option<FrameTimestamp>,
+  // Up to `count` frames before `current`, oldest first.
+  before: Vec<FrameTimestamp>,
+  // Up to `count` frames after `current`.
+  after: Vec<FrameTimestamp>,
+}
 
-      return Ok(ProbeResult {
-        input_path: cached.input_path,
-        duration_seconds: cached.duration_seconds,
-        audio_streams: cached.audio_streams,
-        subtitle_streams: cached.subtitle_streams,
-        ffmpeg_bin_dir_used: cached.ffmpeg_bin_dir_used,
-        ffprobe_path: cached.ffprobe_path,
-        ffprobe_args: cached.ffprobe_args,
-        ffprobe_runner: cached.ffprobe_runner,
-        cwd: cached.cwd,
-        timing_ms,
+const MAX_NEIGHBOR_FRAMES: usize = 50;
+// Initial read window around the target; doubled until enough frames are found.
+const NEIGHBOR_FRAMES_MIN_SPAN_SECONDS: f64 = 1.0;
+const NEIGHBOR_FRAMES_MAX_ATTEMPTS: usize = 4;
+
+fn run_ffprobe_frames_in_interval(ffprobe_path: &Path, input_path: &str, read_intervals: &str) -> Result<Vec<FrameTimestamp>, String> {
+  let stdout = media_backend::current()
+    .probe(
+      ffprobe_path,
+      &media_backend::args(&[
+        "-v",
+        "error",
+        "-select_streams",
+        "v:0",
+        "-read_intervals",
+        read_intervals,
+        "-show_entries",
+        "frame=best_effort_timestamp_time,key_frame,pict_type",
+        "-of",
+        "compact=p=0",
+        input_path,
+      ]),
+    )?
+    .into_stdout("ffprobe failed")?;
+
+  let mut frames = Vec::new();
+  for line in String::from_utf8_lossy(&stdout).lines() {
+    let (mut seconds, mut key_frame, mut pict_type) = (None, false, String::new());
+    for field in line.trim().split('|') {
+      match field.split_once('=') {
+        Some(("best_effort_timestamp_time", v)) => seconds = v.parse::<f64>().ok(),
+        Some(("key_frame", v)) => key_frame = v == "1",
+        Some(("pict_type", v)) if v != "?" => pict_type = v.to_string(),
+        _ => {}
+      }
+    }
+    if let Some(seconds) = seconds {
+      frames.push(FrameTimestamp {
+        seconds,
+        time: timecode::format_clock(seconds),
+        key_frame,
+        pict_type,
       });
     }
   }
+  frames.sort_by(|a, b| a.seconds.total_cmp(&b.seconds));
+  frames.dedup_by(|a, b| (a.seconds - b.seconds).abs() < 1e-9);
+  Ok(frames)
+}
 
-  let start_spawn_total = Instant::now();
-  let ffprobe_args: Vec<String> = vec![
-    // Keep this probe intentionally lightweight: only request the fields we actually use.
-    // Full `-show_streams -show_format` can be much slower on some systems/files.
-    "-v".to_string(),
-    "error".to_string(),
-    "-print_format".to_string(),
-    "json".to_string(),
-    "-show_entries".to_string(),
-    "format=duration:stream=index,codec_type,codec_name,channels:stream_tags=language,title".to_string(),
-    input_path.clone(),
-  ];
+/// Real timestamps of the `count` frames before and after `time`, read from a small window around it.
+/// Works for variable frame rate footage, where stepping by 1/fps lands between frames.
+#[tauri::command]
+async fn neighbor_frames(input_path: String, time: String, count: u32, ffmpeg_bin_dir: String) -> Result<NeighborFramesResult, String> {
+  tauri::async_runtime::spawn_blocking(move || neighbor_frames_sync(input_path, time, count, ffmpeg_bin_dir))
+    .await
+    .map_err(|e| format!("neighbor_frames failed: {e}"))?
+}
 
-  let mut cmd = Command::new(&ffprobe_path);
-  apply_no_window(&mut cmd);
-  cmd.args(&ffprobe_args);
-  if let Some(dir) = &workdir {
-    cmd.current_dir(dir);
+fn neighbor_frames_sync(input_path: String, time: String, count: u32, ffmpeg_bin_dir: String) -> Result<NeighborFramesResult, String> {
+  let input_path = normalize_input_path_for_cli(&input_path);
+  ensure_input_file_exists(&input_path)?;
+  validate_ffmpeg_bin_dir(&ffmpeg_bin_dir)?;
+  let count = (count as usize).clamp(1, MAX_NEIGHBOR_FRAMES);
+
+  let (_ffmpeg_path, ffprobe_path, _ffmpeg_bin_dir_used) =
+    resolve_ffmpeg_binaries_with_fallback(&ffmpeg_bin_dir);
+  let frame_rate = probe_frame_rate_best_effort(&ffprobe_path, &input_path);
+  let target = timecode::parse(&time, frame_rate)?;
+
+  // Enough room for `count` frames on each side at the nominal rate, with slack for VFR gaps.
+  let mut span = frame_rate
+    .map(|fps| (count as f64 + 1.0) * 2.0 / fps)
+    .unwrap_or(0.0)
+    .max(NEIGHBOR_FRAMES_MIN_SPAN_SECONDS);
+  let mut frames = Vec::new();
+  for _ in 0..NEIGHBOR_FRAMES_MAX_ATTEMPTS {
+    let start = (target - span).max(0.0);
+    frames = run_ffprobe_frames_in_interval(&ffprobe_path, &input_path, &format!("{start}%{}", target + span))?;
+    let at_or_before = frames.iter().filter(|f| f.seconds <= target + 1e-6).count();
+    let enough_before = at_or_before > count || start <= 0.0;
+    let enough_after = frames.len() - at_or_before >= count;
+    // A short tail means the window reached the end of the file, so widening would not help.
+    let reached_end = !frames.last().is_some_and(|f| f.seconds >= target + span * 0.5);
+    if enough_before && (enough_after || reached_end) {
+      break;
+    }
+    span *= 2.0;
   }
-  let ffprobe_runner = "direct".to_string();
 
-  cmd.stdin(Stdio::null())
-    .stdout(Stdio::piped())
-    .stderr(Stdio::piped());
+  let split = frames.partition_point(|f| f.seconds <= target + 1e-6);
+  let after: Vec<FrameTimestamp> = frames.iter().skip(split).take(count).cloned().collect();
+  let (current, before) = match split.checked_sub(1) {
+    Some(i) => (Some(frames[i].clone()), frames[i.saturating_sub(count)..i].to_vec()),
+    None => (None, Vec::new()),
+  };
 
-  let start_spawn = Instant::now();
-  let mut child = cmd.spawn().map_err(|e| {
-    if e.kind() == ErrorKind::NotFound {
-      "Failed to run ffprobe: program not found (set FFmpeg bin folder or add ffprobe to PATH)".to_string()
-    } else {
-      format!("Failed to run ffprobe: {e}")
-    }
-  })?;
-  let ffprobe_spawn_ms = start_spawn.elapsed().as_secs_f64() * 1000.0;
-
-  let mut stdout = child
-    .stdout
-    .take()
-    .ok_or_else(|| "Failed to capture ffprobe stdout".to_string())?;
-  let mut stderr = child
-    .stderr
-    .take()
-    .ok_or_else(|| "Failed to capture ffprobe stderr".to_string())?;
-
-  let (stdout_tx, stdout_rx) =
-    std::sync::mpsc::channel::<(Option<f64>, Vec<u8>, Result<(), String>)>();
-  let (stderr_tx, stderr_rx) =
-    std::sync::mpsc::channel::<(Option<f64>, Vec<u8>, Result<(), String>)>();
-
-  std::thread::spawn(move || {
-    let mut buf = Vec::new();
-    let mut first_ms: Option<f64> = None;
-    let mut tmp = [0_u8; 8192];
-    loop {
-      match stdout.read(&mut tmp) {
-        Ok(0) => break,
-        Ok(n) => {
-          if first_ms.is_none() {
-            first_ms = Some(start_spawn_total.elapsed().as_secs_f64() * 1000.0);
-          }
-          buf.extend_from_slice(&tmp[..n]);
-        }
-        Err(e) => {
-          let _ = stdout_tx.send((first_ms, buf, Err(format!("Failed reading ffprobe stdout: {e}"))));
-          return;
-        }
-      }
-    }
-    let _ = stdout_tx.send((first_ms, buf, Ok(())));
-  });
+  Ok(NeighborFramesResult { current, before, after })
+}
 
-  std::thread::spawn(move || {
-    let mut buf = Vec::new();
-    let mut first_ms: Option<f64> = None;
-    let mut tmp = [0_u8; 8192];
-    loop {
-      match stderr.read(&mut tmp) {
-        Ok(0) => break,
-        Ok(n) => {
-          if first_ms.is_none() {
-            first_ms = Some(start_spawn_total.elapsed().as_secs_f64() * 1000.0);
-          }
-          buf.extend_from_slice(&tmp[..n]);
-        }
-        Err(e) => {
-          let _ = stderr_tx.send((first_ms, buf, Err(format!("Failed reading ffprobe stderr: {e}"))));
-          return;
-        }
-      }
-    }
-    let _ = stderr_tx.send((first_ms, buf, Ok(())));
-  });
+#[tauri::command]
+fn probe_media(input_path: String, ffmpeg_bin_dir: String) -> Result<ProbeResult, String> {
+  use std::time::Instant;
+  let start_total = Instant::now();
+
+  let input_path = normalize_input_path_for_cli(&input_path);
+  let probe = probe_service(&input_path, &ffmpeg_bin_dir, ProbeNeed::Full)?;
+  let timing_ms = probe_timing_info(&probe, start_total.elapsed().as_secs_f64() * 1000.0);
+  eprintln!("[PERF] TOTAL probe_media took: {:?}", start_total.elapsed());
+
+  let data = probe.data;
+  Ok(ProbeResult {
+    input_path: data.input_path,
+    duration_seconds: data.duration_seconds,
+    video_streams: data.video_streams,
+    audio_streams: data.audio_streams,
+    subtitle_streams: data.subtitle_streams,
+    chapters: data.chapters,
+    creation_time: data.creation_time,
+    frame_rate: data.frame_rate,
+    format: data.format,
+    ffmpeg_bin_dir_used: data.ffmpeg_bin_dir_used,
+    ffprobe_path: data.ffprobe_path,
+    ffprobe_args: data.ffprobe_args,
+    ffprobe_runner: data.ffprobe_runner,
+    cwd: data.cwd,
+    timing_ms,
+  })
+}
 
-  let start_wait = Instant::now();
-  let status = child
-    .wait()
-    .map_err(|e| format!("Failed waiting for ffprobe: {e}"))?;
-  let ffprobe_wait_ms = start_wait.elapsed().as_secs_f64() * 1000.0;
+// Timings of the ffprobe run behind a full probe, so each probe command can report them.
+#[derive(Clone, Debug)]
+struct FullProbeRun {
+  ffprobe_spawn_ms: f64,
+  ffprobe_first_stdout_byte_ms: Option<f64>,
+  ffprobe_first_stderr_byte_ms: Option<f64>,
+  ffprobe_execution_ms: f64,
+  ffprobe_wait_ms: f64,
+  json_parsing_ms: f64,
+  frame_sampling_ms: f64,
+  debug: SpawnDebugInfo,
+}
 
-  let (ffprobe_first_stdout_byte_ms, stdout_buf, stdout_ok) =
-    stdout_rx.recv().unwrap_or((None, Vec::new(), Err("Failed to receive ffprobe stdout".to_string())));
-  let (ffprobe_first_stderr_byte_ms, stderr_buf, stderr_ok) =
-    stderr_rx.recv().unwrap_or((None, Vec::new(), Err("Failed to receive ffprobe stderr".to_string())));
+struct FullProbe {
+  data: CachedProbeResult,
+  // Set only for the caller that actually ran ffprobe.
+  run: Option<FullProbeRun>,
+  // True when answered from the cache or by waiting on another caller's ffprobe.
+  cache_hit: bool,
+}
 
-  stdout_ok?;
-  stderr_ok?;
+struct InflightProbe {
+  outcome: Mutex<Option<Result<CachedProbeResult, String>>>,
+  done: Condvar,
+}
 
-  eprintln!("[PERF] FFprobe execution took: {:?}", start_spawn_total.elapsed());
+// Held by the caller running ffprobe. However it leaves `probe_full` (result, early return or
+// panic), the slot gets an outcome, the waiters are woken and the slot is unregistered.
+struct InflightLeader<'a> {
+  cache_key: &'a str,
+  slot: &'a InflightProbe,
+}
 
-  if !status.success() {
-    let stderr = String::from_utf8_lossy(&stderr_buf).trim().to_string();
-    return Err(if stderr.is_empty() {
-      "ffprobe failed".to_string()
-    } else {
-      format!("ffprobe failed: {stderr}")
-    });
+impl Drop for InflightLeader<'_> {
+  fn drop(&mut self) {
+    let mut outcome = self.slot.outcome.lock().unwrap_or_else(PoisonError::into_inner);
+    if outcome.is_none() {
+      *outcome = Some(Err("Probe ended without a result".to_string()));
+    }
+    drop(outcome);
+    self.slot.done.notify_all();
+    if let Ok(mut inflight) = inflight_probes().lock() {
+      inflight.remove(self.cache_key);
+    }
   }
+}
 
-  let start_parse = Instant::now();
-  let json: serde_json::Value =
-    serde_json::from_slice(&stdout_buf).map_err(|e| format!("Invalid ffprobe JSON: {e}"))?;
-  eprintln!("[PERF] JSON parsing took: {:?}", start_parse.elapsed());
+// Probes currently running, keyed like `probe_cache`.
+fn inflight_probes() -> &'static Mutex<HashMap<String, Arc<InflightProbe>>> {
+  static INFLIGHT: OnceLock<Mutex<HashMap<String, Arc<InflightProbe>>>> = OnceLock::new();
+  INFLIGHT.get_or_init(|| Mutex::new(HashMap::new()))
+}
 
-  let duration_seconds = json
-    .get("format")
-    .and_then(|f| f.get("duration"))
-    .and_then(|d| d.as_str())
-    .and_then(|s| s.parse::<f64>().ok());
+const PROBE_CACHE_NAMESPACE: &str = "probe";
 
-  let mut audio_streams = Vec::new();
-  let mut subtitle_streams = Vec::new();
-  if let Some(streams) = json.get("streams").and_then(|s| s.as_array()) {
-    for stream in streams {
-      let codec_type = stream.get("codec_type").and_then(|t| t.as_str()).unwrap_or("");
-      if codec_type != "audio" && codec_type != "subtitle" {
-        continue;
+// Memory first, then the disk cache (which survives restarts).
+fn cached_full_probe(cache_key: &str) -> Option<CachedProbeResult> {
+  if let Some(hit) = probe_cache()
+    .lock()
+    .ok()?
+    .get(cache_key)
+    .filter(|c| c.is_full)
+    .cloned()
+  {
+    return Some(hit);
+  }
+
+  let payload = disk_cache::read(PROBE_CACHE_NAMESPACE, cache_key)?;
+  let data = serde_json::from_slice::<CachedProbeResult>(&payload)
+    .ok()
+    .filter(|c| c.is_full)?;
+  if let Ok(mut guard) = probe_cache().lock() {
+    guard.insert(cache_key.to_string(), data.clone());
+  }
+  Some(data)
+}
+
+/// The ffprobe step of `probe_service`: one full ffprobe per file, shared by every probe command.
+/// Concurrent callers for the same file wait on the in-flight run instead of spawning their own.
+/// `input_path` must already be normalized and validated.
+fn probe_full(input_path: &str, ffprobe_path: &Path, ffmpeg_bin_dir_used: &str) -> Result<FullProbe, String> {
+  let cache_key = probe_cache_key_best_effort(input_path);
+  if let Some(data) = cached_full_probe(&cache_key) {
+    return Ok(FullProbe { data, run: None, cache_hit: true });
+  }
+
+  let (slot, leader) = {
+    let mut inflight = inflight_probes()
+      .lock()
+      .map_err(|_| "Probe registry lock poisoned".to_string())?;
+    match inflight.get(&cache_key) {
+      Some(slot) => (slot.clone(), false),
+      None => {
+        let slot = Arc::new(InflightProbe {
+          outcome: Mutex::new(None),
+          done: Condvar::new(),
+        });
+        inflight.insert(cache_key.clone(), slot.clone());
+        (slot, true)
       }
+    }
+  };
 
-      let index = stream
-        .get("index")
-        .and_then(|v| v.as_i64())
+  if !leader {
+    let mut outcome = slot.outcome.lock().map_err(|_| "Probe lock poisoned".to_string())?;
+    while outcome.is_none() {
+      outcome = slot.done.wait(outcome).map_err(|_| "Probe lock poisoned".to_string())?;
+    }
+    return match outcome.as_ref() {
+      Some(Ok(data)) => Ok(FullProbe {
+        data: data.clone(),
+        run: None,
+        cache_hit: true,
+      }),
+      Some(Err(e)) => Err(e.clone()),
+      None => Err("Probe finished without a result".to_string()),
+    };
+  }
+
+  let leader = InflightLeader {
+    cache_key: &cache_key,
+    slot: &slot,
+  };
+  let result = run_full_probe(input_path, ffprobe_path, ffmpeg_bin_dir_used);
+  if let Ok((data, _)) = &result {
+    if let Ok(mut guard) = probe_cache().lock() {
+      guard.insert(cache_key.clone(), data.clone());
+    }
+    if let Ok(payload) = serde_json::to_vec(data) {
+      disk_cache::write(PROBE_CACHE_NAMESPACE, &cache_key, &payload);
+    }
+  }
+  *slot.outcome.lock().unwrap_or_else(PoisonError::into_inner) = Some(match &result {
+    Ok((data, _)) => Ok(data.clone()),
+    Err(e) => Err(e.clone()),
+  });
+  drop(leader);
+
+  let (data, run) = result?;
+  Ok(FullProbe {
+    data,
+    run: Some(run),
+    cache_hit: false,
+  })
+}
+
+/// Location, size and per-kind entry counts of the on-disk cache.
+#[tauri::command]
+fn cache_info() -> disk_cache::CacheStats {
+  disk_cache::stats()
+}
+
+/// Clear the on-disk cache (`probe`, `keyframes`, ... or everything when `namespace` is omitted)
+/// along with the matching in-memory data. Returns the cache state afterwards.
+#[tauri::command]
+fn clear_cache(namespace: Option<String>) -> Result<disk_cache::CacheStats, String> {
+  let namespace = namespace.filter(|n| !n.trim().is_empty());
+  let freed = disk_cache::clear(namespace.as_deref())?;
+  let all = namespace.is_none();
+  if all || namespace.as_deref() == Some(PROBE_CACHE_NAMESPACE) {
+    if let Ok(mut guard) = probe_cache().lock() {
+      guard.clear();
+    }
+  }
+  if all || namespace.as_deref() == Some("keyframes") {
+    keyframes::clear_memory();
+  }
+  eprintln!("[CACHE] Cleared {freed} bytes");
+  Ok(disk_cache::stats())
+}
+
+fn probe_timing_info(probe: &ProbeOutcome, total_ms: f64) -> ProbeTimingInfo {
+  let run = probe.run.as_ref();
+  ProbeTimingInfo {
+    validation_ms: probe.validation_ms,
+    resolve_binaries_ms: probe.resolve_binaries_ms,
+    ffprobe_spawn_ms: run.map(|r| r.ffprobe_spawn_ms).unwrap_or(0.0),
+    ffprobe_first_stdout_byte_ms: run.and_then(|r| r.ffprobe_first_stdout_byte_ms),
+    ffprobe_first_stderr_byte_ms: run.and_then(|r| r.ffprobe_first_stderr_byte_ms),
+    ffprobe_execution_ms: probe.probe_ms,
+    ffprobe_wait_ms: run.map(|r| r.ffprobe_wait_ms).unwrap_or(0.0),
+    json_parsing_ms: run.map(|r| r.json_parsing_ms).unwrap_or(0.0),
+    frame_sampling_ms: run.map(|r| r.frame_sampling_ms).unwrap_or(0.0),
+    total_ms,
+    cache_hit: probe.cache_hit,
+  }
+}
+
+fn run_full_probe(
+  input_path: &str,
+  ffprobe_path: &Path,
+  ffmpeg_bin_dir_used: &str,
+) -> Result<(CachedProbeResult, FullProbeRun), String> {
+  use std::time::Instant;
+
+  let ffprobe_path_text = ffprobe_path.to_string_lossy().to_string();
+  let cwd_text = stable_working_dir()
+    .map(|p| p.to_string_lossy().to_string())
+    .unwrap_or_default();
+
+  let start_spawn_total = Instant::now();
+  let ffprobe_args: Vec<String> = vec![
+    // Keep this probe intentionally lightweight: only request the fields we actually use.
+    // Full `-show_streams -show_format` can be much slower on some systems/files.
+    "-v".to_string(),
+    "error".to_string(),
+    "-print_format".to_string(),
+    "json".to_string(),
+    "-show_entries".to_string(),
+    "format=duration,format_name,format_long_name,bit_rate,start_time,size,nb_streams:stream=index,codec_type,codec_name,codec_tag_string,channels,r_frame_rate,avg_frame_rate,profile,level,width,height,sample_aspect_ratio,display_aspect_ratio,bits_per_raw_sample,pix_fmt,color_range,color_space,color_transfer,color_primaries,bit_rate:stream_side_data:stream_disposition=attached_pic:stream_tags=language,title,rotate,filename,mimetype,handler_name:chapter=id,start_time,end_time:chapter_tags=title:format_tags".to_string(),
+    input_path.to_string(),
+  ];
+
+  let output = media_backend::current().probe(ffprobe_path, &ffprobe_args)?;
+  let ProcessTiming {
+    spawn_ms: ffprobe_spawn_ms,
+    first_stdout_byte_ms: ffprobe_first_stdout_byte_ms,
+    first_stderr_byte_ms: ffprobe_first_stderr_byte_ms,
+    wait_ms: ffprobe_wait_ms,
+    total_ms: ffprobe_execution_ms,
+  } = output.timing;
+  eprintln!("[PERF] FFprobe execution took: {:?}", start_spawn_total.elapsed());
+  let (exit_code, success, stderr_buf) = (output.exit_code, output.success, output.stderr.clone());
+  let stdout_buf = output.into_stdout("ffprobe failed")?;
+
+  let start_parse = Instant::now();
+  let json: serde_json::Value =
+    serde_json::from_slice(&stdout_buf).map_err(|e| format!("Invalid ffprobe JSON: {e}"))?;
+  eprintln!("[PERF] JSON parsing took: {:?}", start_parse.elapsed());
+
+  let duration_seconds = json
+    .get("format")
+    .and_then(|f| f.get("duration"))
+    .and_then(|d| d.as_str())
+    .and_then(|s| s.parse::<f64>().ok());
+
+  let mut audio_streams = Vec::new();
+  let mut subtitle_streams = Vec::new();
+  if let Some(streams) = json.get("streams").and_then(|s| s.as_array()) {
+    for stream in streams {
+      let codec_type = stream.get("codec_type").and_then(|t| t.as_str()).unwrap_or("");
+      if codec_type != "audio" && codec_type != "subtitle" {
+        continue;
+      }
+
+      let index = stream
+        .get("index")
+        .and_then(|v| v.as_i64())
         .ok_or_else(|| "ffprobe stream missing index".to_string())? as i32;
       let codec_name = stream
         .get("codec_name")
@@ -2230,307 +2745,2362 @@ fn probe_media(input_path: String, ffmpeg_bin_dir: String) -> Result<ProbeResult
     s.order = i as i32;
   }
 
-  let timing_ms = ProbeTimingInfo {
-    validation_ms,
-    resolve_binaries_ms,
-    ffprobe_spawn_ms,
-    ffprobe_first_stdout_byte_ms,
-    ffprobe_first_stderr_byte_ms,
-    ffprobe_execution_ms: start_spawn_total.elapsed().as_secs_f64() * 1000.0,
-    ffprobe_wait_ms,
-    json_parsing_ms: start_parse.elapsed().as_secs_f64() * 1000.0,
-    total_ms: start_total.elapsed().as_secs_f64() * 1000.0,
-    cache_hit: false,
-  };
+  let chapters = parse_chapters_from_ffprobe_json(&json);
+  let creation_time = parse_creation_time_from_ffprobe_json(&json);
+  let video_streams = parse_video_streams_from_ffprobe_json(&json);
+  let format = parse_format_info_from_ffprobe_json(&json);
+  let video_rates = json
+    .get("streams")
+    .and_then(|s| s.as_array())
+    .and_then(|arr| arr.iter().find(|s| s.get("codec_type").and_then(|t| t.as_str()) == Some("video")))
+    .map(|video| {
+      let rate = |key: &str| video.get(key).and_then(|v| v.as_str()).and_then(parse_ffprobe_rate);
+      (rate("r_frame_rate"), rate("avg_frame_rate"))
+    });
+  let json_parsing_ms = start_parse.elapsed().as_secs_f64() * 1000.0;
 
-  eprintln!("[PERF] TOTAL probe_media took: {:?}", start_total.elapsed());
+  let start_sampling = Instant::now();
+  let frame_rate = video_rates.map(|(real, avg)| {
+    let intervals = sample_video_frame_intervals(ffprobe_path, input_path);
+    frame_rate_info(real, avg, &intervals)
+  });
+  let frame_sampling_ms = start_sampling.elapsed().as_secs_f64() * 1000.0;
+
+  let debug = SpawnDebugInfo {
+    phase: "probe".to_string(),
+    program: ffprobe_path_text.clone(),
+    args: ffprobe_args.clone(),
+    cwd: cwd_text.clone(),
+    program_exists: ffprobe_path.exists(),
+    exit_code,
+    success,
+    stdout_len: stdout_buf.len(),
+    stderr_len: stderr_buf.len(),
+    stderr_head: stderr_head_text(&stderr_buf),
+  };
 
-  let result = ProbeResult {
-    input_path: input_path.clone(),
+  let rotation_degrees = Some(video_streams.first().map(|v| v.rotation_degrees).unwrap_or(0));
+  let data = CachedProbeResult {
+    input_path: input_path.to_string(),
+    has_duration: duration_seconds.is_some(),
+    has_tracks: true,
+    has_subtitles: true,
+    is_full: true,
     duration_seconds,
+    video_streams,
     audio_streams,
     subtitle_streams,
-    ffmpeg_bin_dir_used,
+    chapters,
+    creation_time,
+    frame_rate,
+    format,
+    rotation_degrees,
+    ffmpeg_bin_dir_used: ffmpeg_bin_dir_used.to_string(),
     ffprobe_path: ffprobe_path_text,
-    ffprobe_args: ffprobe_args.clone(),
-    ffprobe_runner,
+    ffprobe_args,
+    ffprobe_runner: "direct".to_string(),
     cwd: cwd_text,
-    timing_ms,
   };
 
-  if let Ok(mut guard) = probe_cache().lock() {
-    guard.insert(
-      cache_key,
-      CachedProbeResult {
-        input_path: result.input_path.clone(),
-        has_duration: result.duration_seconds.is_some(),
-        has_tracks: !result.audio_streams.is_empty() || !result.subtitle_streams.is_empty(),
-        has_subtitles: true,
-        duration_seconds: result.duration_seconds,
-        audio_streams: result.audio_streams.clone(),
-        subtitle_streams: result.subtitle_streams.clone(),
-        ffmpeg_bin_dir_used: result.ffmpeg_bin_dir_used.clone(),
-        ffprobe_path: result.ffprobe_path.clone(),
-        ffprobe_args: result.ffprobe_args.clone(),
-        ffprobe_runner: result.ffprobe_runner.clone(),
-        cwd: result.cwd.clone(),
-      },
+  let run = FullProbeRun {
+    ffprobe_spawn_ms,
+    ffprobe_first_stdout_byte_ms,
+    ffprobe_first_stderr_byte_ms,
+    ffprobe_execution_ms,
+    ffprobe_wait_ms,
+    json_parsing_ms,
+    frame_sampling_ms,
+    debug,
+  };
+
+  Ok((data, run))
+}
+
+fn probe_chapters_best_effort(ffprobe_path: &Path, input_path: &str) -> Vec<ChapterInfo> {
+  let cache_key = probe_cache_key_best_effort(input_path);
+  if let Ok(guard) = probe_cache().lock() {
+    if let Some(cached) = guard.get(&cache_key).filter(|c| c.is_full) {
+      return cached.chapters.clone();
+    }
+  }
+
+  let output = media_backend::current()
+    .probe(
+      ffprobe_path,
+      &media_backend::args(&[
+        "-v",
+        "error",
+        "-print_format",
+        "json",
+        "-show_entries",
+        "chapter=id,start_time,end_time:chapter_tags=title",
+        input_path,
+      ]),
     );
+
+  let Ok(output) = output else {
+    return Vec::new();
+  };
+  if !output.success {
+    return Vec::new();
   }
+  serde_json::from_slice::<serde_json::Value>(&output.stdout)
+    .map(|json| parse_chapters_from_ffprobe_json(&json))
+    .unwrap_or_default()
+}
 
-  Ok(result)
+/// Keep chapters that overlap `[in_seconds, out_seconds)`, clamped to the range and shifted so IN becomes 0.
+fn retime_chapters_for_range(chapters: &[ChapterInfo], in_seconds: f64, out_seconds: f64) -> Vec<ChapterInfo> {
+  chapters
+    .iter()
+    .filter(|c| c.end_seconds > in_seconds && c.start_seconds < out_seconds)
+    .enumerate()
+    .map(|(i, c)| ChapterInfo {
+      index: i as i32,
+      title: c.title.clone(),
+      start_seconds: c.start_seconds.max(in_seconds) - in_seconds,
+      end_seconds: c.end_seconds.min(out_seconds) - in_seconds,
+    })
+    .filter(|c| c.end_seconds - c.start_seconds > 0.001)
+    .collect()
 }
 
-#[tauri::command]
-fn trim_media(
-  window: tauri::Window,
-  input_path: String,
+/// Turn source-timeline markers into clip chapters: each marker runs until the next one (or OUT).
+/// Markers before IN collapse into a single chapter starting at 0; markers at or after OUT are dropped.
+fn chapter_markers_to_clip_chapters(
+  markers: &[ChapterMarker],
+  in_seconds: f64,
+  out_seconds: f64,
+  frame_rate: Option<f64>,
+) -> Result<Vec<ChapterInfo>, String> {
+  let mut points: Vec<(f64, String)> = Vec::with_capacity(markers.len());
+  for m in markers {
+    let t = timecode::parse(&m.time, frame_rate)
+      .map_err(|e| format!("Invalid chapter marker time '{}': {e}", m.time))?;
+    points.push((t, m.title.trim().to_string()));
+  }
+  points.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(std::cmp::Ordering::Equal));
+
+  let mut starts: Vec<(f64, String)> = Vec::new();
+  for (t, title) in points {
+    if t >= out_seconds {
+      break;
+    }
+    let start = t.max(in_seconds) - in_seconds;
+    match starts.last_mut() {
+      // Same clip position (e.g. several markers before IN): the later marker wins.
+      Some(last) if (last.0 - start).abs() < 0.001 => *last = (start, title),
+      _ => starts.push((start, title)),
+    }
+  }
+
+  let clip_end = out_seconds - in_seconds;
+  Ok(starts
+    .iter()
+    .enumerate()
+    .map(|(i, (start, title))| ChapterInfo {
+      index: i as i32,
+      title: title.clone(),
+      start_seconds: *start,
+      end_seconds: starts.get(i + 1).map(|n| n.0).unwrap_or(clip_end),
+    })
+    .collect())
+}
+
+fn escape_ffmetadata_value(value: &str) -> String {
+  let mut out = String::with_capacity(value.len());
+  for c in value.chars() {
+    if matches!(c, '=' | ';' | '#' | '\\' | '\n') {
+      out.push('\\');
+    }
+    out.push(c);
+  }
+  out
+}
+
+fn render_ffmetadata_chapters(chapters: &[ChapterInfo]) -> String {
+  let mut text = String::from(";FFMETADATA1\n");
+  for c in chapters {
+    text.push_str("[CHAPTER]\nTIMEBASE=1/1000\n");
+    text.push_str(&format!("START={}\n", (c.start_seconds * 1000.0).round() as i64));
+    text.push_str(&format!("END={}\n", (c.end_seconds * 1000.0).round() as i64));
+    if !c.title.is_empty() {
+      text.push_str(&format!("title={}\n", escape_ffmetadata_value(&c.title)));
+    }
+  }
+  text
+}
+
+/// Temp path for an ffmetadata file used as an extra ffmpeg input (`-f ffmetadata -i <file>`).
+/// The sequence suffix keeps exports started within the same clock tick from sharing one file.
+fn ffmetadata_temp_path() -> PathBuf {
+  static SEQUENCE: std::sync::atomic::AtomicU64 = std::sync::atomic::AtomicU64::new(0);
+  let nanos = std::time::SystemTime::now()
+    .duration_since(std::time::UNIX_EPOCH)
+    .map(|d| d.as_nanos())
+    .unwrap_or(0);
+  let sequence = SEQUENCE.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+  env::temp_dir().join(format!("clipwave_chapters_{}_{nanos}_{sequence}.txt", std::process::id()))
+}
+
+#[derive(Debug, Serialize)]
+struct ChapterRangeResult {
+  chapter: ChapterInfo,
   in_time: String,
   out_time: String,
+}
+
+#[tauri::command]
+fn chapter_range(input_path: String, chapter_index: i32, ffmpeg_bin_dir: String) -> Result<ChapterRangeResult, String> {
+  let input_path = normalize_input_path_for_cli(&input_path);
+  ensure_input_file_exists(&input_path)?;
+  validate_ffmpeg_bin_dir(&ffmpeg_bin_dir)?;
+
+  let (_ffmpeg_path, ffprobe_path, _ffmpeg_bin_dir_used) =
+    resolve_ffmpeg_binaries_with_fallback(&ffmpeg_bin_dir);
+  let chapters = probe_chapters_best_effort(&ffprobe_path, &input_path);
+  let chapter = chapters
+    .into_iter()
+    .find(|c| c.index == chapter_index)
+    .ok_or_else(|| format!("Chapter {chapter_index} not found"))?;
+
+  Ok(ChapterRangeResult {
+    in_time: timecode::format_clock(chapter.start_seconds),
+    out_time: timecode::format_clock(chapter.end_seconds),
+    chapter,
+  })
+}
+
+#[derive(Debug, Serialize)]
+struct ChapterExportItem {
+  chapter: ChapterInfo,
+  result: Option<TrimResult>,
+  error: Option<String>,
+}
+
+// Everything `split_by_chapters` needs, sent as one `request` object.
+#[derive(Debug, Deserialize)]
+struct SplitByChaptersRequest {
+  input_path: String,
   mode: String,
   audio_stream_index: i32,
   subtitle_stream_index: i32,
+  // Source chapter indexes to export; None exports every chapter.
+  chapter_indices: Option<Vec<i32>>,
   ffmpeg_bin_dir: String,
-) -> Result<TrimResult, String> {
+  options: Option<TrimOptions>,
+}
+
+#[tauri::command]
+async fn split_by_chapters(
+  window: tauri::Window,
+  request: SplitByChaptersRequest,
+) -> Result<Vec<ChapterExportItem>, String> {
+  tauri::async_runtime::spawn_blocking(move || split_by_chapters_sync(window, request))
+    .await
+    .map_err(|e| format!("split_by_chapters failed: {e}"))?
+}
+
+fn split_by_chapters_sync(window: tauri::Window, request: SplitByChaptersRequest) -> Result<Vec<ChapterExportItem>, String> {
+  let SplitByChaptersRequest {
+    input_path,
+    mode,
+    audio_stream_index,
+    subtitle_stream_index,
+    chapter_indices,
+    ffmpeg_bin_dir,
+    options,
+  } = request;
+  let options = options.unwrap_or_default();
+  let input_path = normalize_input_path_for_cli(&input_path);
   ensure_input_file_exists(&input_path)?;
   validate_ffmpeg_bin_dir(&ffmpeg_bin_dir)?;
 
-  // Parse with millisecond precision to preserve exact keyframe times
-  let in_seconds_f64 = parse_hh_mm_ss_with_millis(&in_time)?;
-  let out_seconds_f64 = parse_hh_mm_ss_with_millis(&out_time)?;
-  if out_seconds_f64 <= in_seconds_f64 {
-    return Err("OUT must be greater than IN".to_string());
+  let (_ffmpeg_path, ffprobe_path, _ffmpeg_bin_dir_used) =
+    resolve_ffmpeg_binaries_with_fallback(&ffmpeg_bin_dir);
+  let chapters: Vec<ChapterInfo> = probe_chapters_best_effort(&ffprobe_path, &input_path)
+    .into_iter()
+    .filter(|c| match &chapter_indices {
+      Some(wanted) => wanted.contains(&c.index),
+      None => true,
+    })
+    .collect();
+  if chapters.is_empty() {
+    return Err("No chapters to export".to_string());
   }
 
-  // For file existence check and old code compatibility, also get whole seconds
-  let _in_seconds = in_seconds_f64.floor() as u64;
-  let _out_seconds = out_seconds_f64.floor() as u64;
-
-  let mode = mode.trim().to_lowercase();
-  if mode != "lossless" && mode != "exact" {
-    return Err("Mode must be 'lossless' or 'exact'".to_string());
+  let total = chapters.len();
+  let mut items = Vec::with_capacity(total);
+  for (i, chapter) in chapters.into_iter().enumerate() {
+    let _ = window.emit(
+      "chapter_split_progress",
+      serde_json::json!({ "current": i + 1, "total": total, "chapter_index": chapter.index }),
+    );
+    let outcome = trim_media(
+      window.clone(),
+      TrimRequest {
+        input_path: input_path.clone(),
+        in_time: timecode::format_clock(chapter.start_seconds),
+        out_time: timecode::format_clock(chapter.end_seconds),
+        mode: mode.clone(),
+        audio_stream_index,
+        subtitle_stream_index,
+        ffmpeg_bin_dir: ffmpeg_bin_dir.clone(),
+        options: Some(TrimOptions {
+          // Chapter number for `{index}` in naming templates.
+          index: Some(chapter.index as u32 + 1),
+          ..options.clone()
+        }),
+      },
+    );
+    let (result, error) = match outcome {
+      Ok(r) => (Some(r), None),
+      Err(e) => (None, Some(e)),
+    };
+    items.push(ChapterExportItem { chapter, result, error });
   }
 
-  let output_path = {
-    let base = build_output_path(&input_path, &mode, &in_time, &out_time)?;
-    if !base.exists() {
-      base
-    } else {
-      // Auto-number: file (1), file (2), etc.
-      let stem = base.file_stem().unwrap_or_default().to_string_lossy().to_string();
-      let ext = base.extension().map(|e| e.to_string_lossy().to_string()).unwrap_or_default();
-      let parent = base.parent().unwrap_or_else(|| Path::new("."));
-      let mut numbered = base.clone();
-      for i in 1..=999 {
-        numbered = parent.join(format!("{stem} ({i}).{ext}"));
-        if !numbered.exists() { break; }
-      }
-      numbered
-    }
+  Ok(items)
+}
+
+#[derive(Debug, Default)]
+struct SourceMetadataTags {
+  format: Vec<String>,
+  streams: Vec<SourceStreamTags>,
+}
+
+#[derive(Debug)]
+struct SourceStreamTags {
+  // Stream type letter (`v`, `a`, `s`, ...) and 0-based order within that type.
+  kind: char,
+  order: i32,
+  // Global ffprobe stream index.
+  index: i32,
+  keys: Vec<String>,
+}
+
+fn probe_metadata_tags_best_effort(ffprobe_path: &Path, input_path: &str) -> SourceMetadataTags {
+  let output = media_backend::current()
+    .probe(
+      ffprobe_path,
+      &media_backend::args(&[
+        "-v",
+        "error",
+        "-print_format",
+        "json",
+        "-show_entries",
+        "format_tags:stream=index,codec_type:stream_tags",
+        input_path,
+      ]),
+    );
+
+  let Ok(output) = output else {
+    return SourceMetadataTags::default();
+  };
+  if !output.success {
+    return SourceMetadataTags::default();
+  }
+  let Ok(json) = serde_json::from_slice::<serde_json::Value>(&output.stdout) else {
+    return SourceMetadataTags::default();
   };
 
-  let (ffmpeg_path, ffprobe_path, _ffmpeg_bin_dir_used) =
-    resolve_ffmpeg_binaries_with_fallback(&ffmpeg_bin_dir);
+  let tag_keys = |v: Option<&serde_json::Value>| -> Vec<String> {
+    v.and_then(|t| t.as_object())
+      .map(|tags| tags.keys().cloned().collect())
+      .unwrap_or_default()
+  };
 
-  let rotation_degrees = probe_video_rotation_degrees_best_effort(&ffprobe_path, &input_path);
-  let rotation_filter = rotation_filter_for_degrees(rotation_degrees);
+  let mut result = SourceMetadataTags {
+    format: tag_keys(json.get("format").and_then(|f| f.get("tags"))),
+    streams: Vec::new(),
+  };
 
-  if mode == "lossless" && rotation_degrees != 0 {
-    return Err(format!(
-      "Lossless cannot reliably preserve vertical orientation (input is rotated {rotation_degrees}°). Use Exact mode."
-    ));
+  let mut orders: HashMap<char, i32> = HashMap::new();
+  if let Some(streams) = json.get("streams").and_then(|s| s.as_array()) {
+    for stream in streams {
+      let kind = match stream.get("codec_type").and_then(|t| t.as_str()).unwrap_or("") {
+        "video" => 'v',
+        "audio" => 'a',
+        "subtitle" => 's',
+        "data" => 'd',
+        "attachment" => 't',
+        _ => continue,
+      };
+      let order = orders.entry(kind).or_insert(0);
+      result.streams.push(SourceStreamTags {
+        kind,
+        order: *order,
+        index: stream.get("index").and_then(|i| i.as_i64()).unwrap_or(-1) as i32,
+        keys: tag_keys(stream.get("tags")),
+      });
+      *order += 1;
+    }
   }
+  result
+}
 
-  let mut cmd = Command::new(ffmpeg_path);
-  apply_no_window(&mut cmd);
+/// Tags that identify where or with what device a recording was made.
+fn is_privacy_metadata_key(key: &str) -> bool {
+  let k = key.to_ascii_lowercase();
+  k.contains("location")
+    || k.contains("gps")
+    || k.contains("iso6709")
+    || k.contains("manufacturer")
+    || k.contains("serial")
+    || k.ends_with("make")
+    || k.ends_with("model")
+    || k.ends_with("software")
+    || k == "device"
+    || k == "com.android.version"
+}
 
-  // For millisecond precision, pass time as decimal seconds (e.g., "3.170000")
-  let in_time_arg = format!("{:.6}", in_seconds_f64);
-  let duration = out_seconds_f64 - in_seconds_f64;
-  let duration_arg = format!("{:.6}", duration);
+fn is_valid_stream_specifier(spec: &str) -> bool {
+  let mut parts = spec.split(':');
+  let kind_ok = matches!(parts.next(), Some("v" | "a" | "s"));
+  let order_ok = match parts.next() {
+    Some(n) => !n.is_empty() && n.chars().all(|c| c.is_ascii_digit()),
+    None => true,
+  };
+  kind_ok && order_ok && parts.next().is_none()
+}
 
-  if mode == "lossless" {
-    // LOSSLESS: -ss BEFORE -i for input-level seeking, with -t for duration.
-    // Placing -ss before -i makes FFmpeg seek to the nearest keyframe <= IN
-    // at the demuxer level.  With -c copy the output starts from that keyframe
-    // (so the start may be slightly early), and -t counts from the actual
-    // output start, giving the correct requested duration.
-    //
-    // Previously -ss was placed AFTER -i, which caused -t to count from the
-    // seek point while the output started at the earlier keyframe, inflating
-    // the output duration by the keyframe-to-IN gap.
-    cmd.args(["-v", "error", "-progress", "pipe:1"])
-      .args(["-ss"]).arg(&in_time_arg)
-      .args(["-i"]).arg(&input_path)
-      .args(["-t"]).arg(&duration_arg);
-  } else {
-    // EXACT: -ss BEFORE -i for fast seeking, then re-encode for frame accuracy.
-    cmd.args(["-v", "error", "-progress", "pipe:1", "-accurate_seek", "-ss"])
-      .arg(&in_time_arg);
+/// Build ffmpeg output options for metadata edits and stripping.
+/// `mapped` lists the source streams that reach the output as (type letter, order within type), in
+/// output order. Returns the arguments plus the tags of the container and those streams that will not
+/// reach the output; tags on unmapped streams are not reported because the streams are dropped anyway.
+fn build_metadata_args(
+  options: &MetadataOptions,
+  source: &SourceMetadataTags,
+  mapped: &[(char, i32)],
+) -> Result<(Vec<String>, Vec<String>), String> {
+  let mut args: Vec<String> = Vec::new();
+  let mut removed: Vec<String> = Vec::new();
+
+  // Each mapped source stream with its output specifier: the second audio stream mapped alone is `a:0`.
+  let mapped_streams = source.streams.iter().filter_map(|s| {
+    let position = mapped.iter().position(|m| *m == (s.kind, s.order))?;
+    let output_order = mapped[..position].iter().filter(|m| m.0 == s.kind).count();
+    Some((s, format!("{}:{output_order}", s.kind)))
+  });
 
-    if rotation_filter.is_some() {
-      cmd.arg("-noautorotate");
+  match options.strip.trim().to_lowercase().as_str() {
+    "" | "none" => {}
+    "all" => {
+      args.extend(["-map_metadata".to_string(), "-1".to_string()]);
+      removed.extend(source.format.iter().cloned());
+      for (s, _) in mapped_streams {
+        removed.extend(s.keys.iter().map(|k| format!("{}:{}:{k}", s.kind, s.order)));
+      }
+    }
+    "privacy" => {
+      for key in source.format.iter().filter(|k| is_privacy_metadata_key(k)) {
+        args.extend(["-metadata".to_string(), format!("{key}=")]);
+        removed.push(key.clone());
+      }
+      for (s, output_spec) in mapped_streams {
+        for key in s.keys.iter().filter(|k| is_privacy_metadata_key(k)) {
+          args.extend([format!("-metadata:s:{output_spec}"), format!("{key}=")]);
+          removed.push(format!("{}:{}:{key}", s.kind, s.order));
+        }
+      }
+    }
+    other => return Err(format!("Unknown metadata strip mode '{other}' (expected none, all or privacy)")),
+  }
+
+  for (key, value) in [
+    ("title", &options.title),
+    ("comment", &options.comment),
+    ("artist", &options.artist),
+    ("date", &options.date),
+  ] {
+    if let Some(v) = value {
+      args.extend(["-metadata".to_string(), format!("{key}={}", v.trim())]);
     }
+  }
 
-    cmd.args(["-i"]).arg(&input_path)
-      .args(["-t"]).arg(&duration_arg);
+  for edit in &options.streams {
+    let spec = edit.stream.trim();
+    if !is_valid_stream_specifier(spec) {
+      return Err(format!("Invalid stream specifier '{spec}' (expected e.g. v:0, a:1, s:0)"));
+    }
+    if let Some(language) = &edit.language {
+      args.extend([format!("-metadata:s:{spec}"), format!("language={}", language.trim())]);
+    }
+    if let Some(title) = &edit.title {
+      args.extend([format!("-metadata:s:{spec}"), format!("title={}", title.trim())]);
+    }
   }
 
-  cmd.args(["-map", "0:v:0"]);
+  Ok((args, removed))
+}
 
-  if audio_stream_index < 0 {
-    cmd.arg("-an");
-  } else {
-    // `audio_stream_index` is treated as the 0-based order within audio streams (not the global ffprobe stream index).
-    cmd.args(["-map", &format!("0:a:{audio_stream_index}")]);
+// Days since 1970-01-01 for a proleptic Gregorian date (Howard Hinnant's `days_from_civil`).
+fn days_from_civil(y: i64, m: i64, d: i64) -> i64 {
+  let y = if m <= 2 { y - 1 } else { y };
+  let era = if y >= 0 { y } else { y - 399 } / 400;
+  let yoe = y - era * 400;
+  let mp = (m + 9) % 12;
+  let doy = (153 * mp + 2) / 5 + d - 1;
+  let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+  era * 146_097 + doe - 719_468
+}
+
+fn civil_from_days(z: i64) -> (i64, i64, i64) {
+  let z = z + 719_468;
+  let era = if z >= 0 { z } else { z - 146_096 } / 146_097;
+  let doe = z - era * 146_097;
+  let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+  let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+  let mp = (5 * doy + 2) / 153;
+  let d = doy - (153 * mp + 2) / 5 + 1;
+  let m = if mp < 10 { mp + 3 } else { mp - 9 };
+  (if m <= 2 { yoe + era * 400 + 1 } else { yoe + era * 400 }, m, d)
+}
+
+/// Parse the ISO 8601 timestamps containers use (`2024-05-01T12:34:56.000000Z`, `2024-05-01T14:34:56+0200`).
+/// Returns Unix seconds and the UTC offset in minutes (0 when the zone is `Z` or missing).
+fn parse_iso8601_timestamp(input: &str) -> Option<(f64, i32)> {
+  let s = input.trim();
+  let (date, rest) = s.split_at(s.find(['T', ' '])?);
+  let rest = &rest[1..];
+
+  let mut date_parts = date.split('-');
+  let year: i64 = date_parts.next()?.parse().ok()?;
+  let month: i64 = date_parts.next()?.parse().ok()?;
+  let day: i64 = date_parts.next()?.parse().ok()?;
+  if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+    return None;
   }
 
-  if subtitle_stream_index >= 0 && mode != "lossless" {
+  let zone_at = rest.find(['Z', 'z', '+', '-']).unwrap_or(rest.len());
+  let (clock, zone) = rest.split_at(zone_at);
+  let mut clock_parts = clock.split(':');
+  let hour: i64 = clock_parts.next()?.parse().ok()?;
+  let minute: i64 = clock_parts.next()?.parse().ok()?;
+  let second: f64 = clock_parts.next().unwrap_or("0").parse().ok()?;
+
+  let offset_minutes: i32 = match zone {
+    "" | "Z" | "z" => 0,
+    _ => {
+      let sign = if zone.starts_with('-') { -1 } else { 1 };
+      // +hh, +hhmm or +hh:mm
+      let digits: String = zone[1..].chars().filter(|c| c.is_ascii_digit()).collect();
+      if digits.len() != 2 && digits.len() != 4 {
+        return None;
+      }
+      let hh: i32 = digits[..2].parse().ok()?;
+      let mm: i32 = digits.get(2..).filter(|m| !m.is_empty()).map_or(Ok(0), str::parse).ok()?;
+      sign * (hh * 60 + mm)
+    }
+  };
+
+  let local = days_from_civil(year, month, day) * 86_400 + hour * 3600 + minute * 60;
+  Some((local as f64 + second - offset_minutes as f64 * 60.0, offset_minutes))
+}
+
+/// Format Unix seconds as ISO 8601 in the given UTC offset (`Z` when 0, `+hhmm` otherwise).
+fn format_iso8601_timestamp(unix_seconds: f64, offset_minutes: i32) -> String {
+  let local_micros = ((unix_seconds + offset_minutes as f64 * 60.0) * 1_000_000.0).round() as i64;
+  let secs = local_micros.div_euclid(1_000_000);
+  let micros = local_micros.rem_euclid(1_000_000);
+  let (y, m, d) = civil_from_days(secs.div_euclid(86_400));
+  let tod = secs.rem_euclid(86_400);
+  let zone = if offset_minutes == 0 {
+    "Z".to_string()
+  } else {
+    let sign = if offset_minutes < 0 { '-' } else { '+' };
+    let abs = offset_minutes.abs();
+    format!("{sign}{:02}{:02}", abs / 60, abs % 60)
+  };
+  format!(
+    "{y:04}-{m:02}-{d:02}T{:02}:{:02}:{:02}.{micros:06}{zone}",
+    tod / 3600,
+    (tod / 60) % 60,
+    tod % 60
+  )
+}
+
+fn probe_creation_time_best_effort(ffprobe_path: &Path, input_path: &str) -> Option<String> {
+  let cache_key = probe_cache_key_best_effort(input_path);
+  if let Ok(guard) = probe_cache().lock() {
+    if let Some(cached) = guard.get(&cache_key).filter(|c| c.is_full) {
+      return cached.creation_time.clone();
+    }
+  }
+
+  let output = media_backend::current()
+    .probe(
+      ffprobe_path,
+      &media_backend::args(&[
+        "-v",
+        "error",
+        "-print_format",
+        "json",
+        "-show_entries",
+        "format_tags=creation_time,com.apple.quicktime.creationdate",
+        input_path,
+      ]),
+    )
+    .ok()?;
+  if !output.success {
+    return None;
+  }
+  let json = serde_json::from_slice::<serde_json::Value>(&output.stdout).ok()?;
+  parse_creation_time_from_ffprobe_json(&json)
+}
+
+#[derive(Debug, Serialize)]
+struct ExportPreflightResult {
+  output_dir: String,
+  estimated_output_bytes: Option<u64>,
+  free_bytes: Option<u64>,
+  writable: bool,
+  warnings: Vec<String>,
+  // Set when the export should not start (no write access, or not enough space).
+  error: Option<String>,
+}
+
+// Rough bits-per-pixel for libx264 at CRF 18 / veryfast; good enough for a space check. The rate
+// roughly halves for every 6 CRF steps above that.
+const EXACT_MODE_BITS_PER_PIXEL: f64 = 0.1;
+const EXACT_MODE_REFERENCE_CRF: f64 = 18.0;
+const DEFAULT_AUDIO_BITRATE_BPS: f64 = 192_000.0;
+// Leave headroom for container overhead and estimate error.
+const OUTPUT_SIZE_SAFETY_FACTOR: f64 = 1.1;
+
+// Packets sampled from the start of the video stream to measure frame interval jitter.
+const VFR_SAMPLE_PACKETS: u32 = 300;
+// Common broadcast/camera rates that detected averages are snapped to for CFR output.
+const STANDARD_FRAME_RATES: [(u32, u32); 10] = [
+  (24000, 1001),
+  (24, 1),
+  (25, 1),
+  (30000, 1001),
+  (30, 1),
+  (48, 1),
+  (50, 1),
+  (60000, 1001),
+  (60, 1),
+  (120, 1),
+];
+
+/// Presentation-order frame intervals over the first packets of the first video stream.
+fn sample_video_frame_intervals(ffprobe_path: &Path, input_path: &str) -> Vec<f64> {
+  let output = media_backend::current()
+    .probe(
+      ffprobe_path,
+      &media_backend::args(&[
+        "-v",
+        "error",
+        "-select_streams",
+        "v:0",
+        "-read_intervals",
+        &format!("%+#{VFR_SAMPLE_PACKETS}"),
+        "-show_entries",
+        "packet=pts_time",
+        "-of",
+        "csv=p=0",
+        input_path,
+      ]),
+    );
+  let Ok(output) = output else {
+    return Vec::new();
+  };
+  let mut pts: Vec<f64> = String::from_utf8_lossy(&output.stdout)
+    .lines()
+    .filter_map(|l| l.trim().trim_end_matches(',').parse::<f64>().ok())
+    .collect();
+  pts.sort_by(f64::total_cmp);
+  pts.windows(2).map(|w| w[1] - w[0]).filter(|d| *d > 0.0).collect()
+}
+
+/// Combine the container's rate metadata with sampled frame intervals into a VFR score.
+fn frame_rate_info(real: Option<f64>, avg: Option<f64>, intervals: &[f64]) -> FrameRateInfo {
+  let real = real.filter(|r| r.is_finite() && *r > 0.0);
+  let avg = avg.filter(|r| r.is_finite() && *r > 0.0);
+
+  // Metadata: r_frame_rate and avg_frame_rate agree for CFR; within 0.5% is rounding, past 5% it varies.
+  let metadata_score = match (real, avg) {
+    (Some(r), Some(a)) => (((r - a).abs() / r - 0.005) / 0.045).clamp(0.0, 1.0),
+    _ => 0.0,
+  };
+
+  // Samples: share of intervals more than 10% off the median. Phone footage often reports a
+  // clean 30/1 while its real intervals wander, so this catches what metadata misses.
+  let sample_score = if intervals.len() >= 10 {
+    let mut sorted = intervals.to_vec();
+    sorted.sort_by(f64::total_cmp);
+    let median = sorted[sorted.len() / 2];
+    let irregular = intervals.iter().filter(|d| ((*d - median) / median).abs() > 0.1).count();
+    // A handful of irregular intervals (edit points, a dropped frame) is normal for CFR.
+    ((irregular as f64 / intervals.len() as f64 - 0.02) / 0.1).clamp(0.0, 1.0)
+  } else {
+    0.0
+  };
+
+  let detected = avg.or(real);
+  let suggested_cfr_rate = detected.map(|rate| {
+    STANDARD_FRAME_RATES
+      .iter()
+      .find(|(n, d)| (rate - *n as f64 / *d as f64).abs() / rate < 0.01)
+      .map(|(n, d)| format!("{n}/{d}"))
+      .unwrap_or_else(|| format!("{:.3}", rate))
+  });
+
+  FrameRateInfo {
+    real_frame_rate: real,
+    avg_frame_rate: avg,
+    vfr_confidence: (metadata_score.max(sample_score) * 100.0).round() / 100.0,
+    suggested_cfr_rate,
+  }
+}
+
+fn probe_frame_rate_info_best_effort(ffprobe_path: &Path, input_path: &str) -> Option<FrameRateInfo> {
+  let cache_key = probe_cache_key_best_effort(input_path);
+  if let Ok(guard) = probe_cache().lock() {
+    if let Some(info) = guard.get(&cache_key).and_then(|c| c.frame_rate.clone()) {
+      return Some(info);
+    }
+  }
+
+  let output = media_backend::current()
+    .probe(
+      ffprobe_path,
+      &media_backend::args(&[
+        "-v",
+        "error",
+        "-select_streams",
+        "v:0",
+        "-show_entries",
+        "stream=r_frame_rate,avg_frame_rate",
+        "-of",
+        "json",
+        input_path,
+      ]),
+    )
+    .ok()?;
+  let json: serde_json::Value = serde_json::from_slice(&output.stdout).ok()?;
+  let video = json.get("streams")?.as_array()?.first()?;
+  let rate = |key: &str| video.get(key).and_then(|v| v.as_str()).and_then(parse_ffprobe_rate);
+  let intervals = sample_video_frame_intervals(ffprobe_path, input_path);
+  Some(frame_rate_info(rate("r_frame_rate"), rate("avg_frame_rate"), &intervals))
+}
+
+/// Resolve the `constant_frame_rate` option: "auto" uses the detected rate, anything else must be a rate.
+fn resolve_cfr_rate(requested: &str, ffprobe_path: &Path, input_path: &str) -> Result<String, String> {
+  let requested = requested.trim();
+  if requested.eq_ignore_ascii_case("auto") {
+    return probe_frame_rate_info_best_effort(ffprobe_path, input_path)
+      .and_then(|info| info.suggested_cfr_rate)
+      .ok_or_else(|| "Could not detect a frame rate for constant frame rate output".to_string());
+  }
+  match parse_ffprobe_rate(requested) {
+    Some(rate) if rate.is_finite() && rate > 0.0 && rate <= 240.0 => Ok(requested.to_string()),
+    _ => Err(format!("Invalid constant frame rate '{requested}' (use e.g. 30, 29.97, 30000/1001 or auto)")),
+  }
+}
+
+fn parse_ffprobe_rate(rate: &str) -> Option<f64> {
+  match rate.split_once('/') {
+    Some((n, d)) => {
+      let n: f64 = n.trim().parse().ok()?;
+      let d: f64 = d.trim().parse().ok()?;
+      if d == 0.0 { None } else { Some(n / d) }
+    }
+    None => rate.trim().parse().ok(),
+  }
+}
+
+/// Estimate the clip size: source bitrate for lossless copies, encoder settings for Exact re-encodes.
+/// `options` must already have its preset applied.
+fn estimate_output_bytes(
+  ffprobe_path: &Path,
+  input_path: &str,
+  mode: &str,
+  duration_seconds: f64,
+  audio_stream_index: i32,
+  options: &TrimOptions,
+) -> Option<u64> {
+  let output = media_backend::current()
+    .probe(
+      ffprobe_path,
+      &media_backend::args(&[
+        "-v",
+        "error",
+        "-print_format",
+        "json",
+        "-show_entries",
+        "format=bit_rate,duration,size:stream=codec_type,bit_rate,width,height,avg_frame_rate",
+        input_path,
+      ]),
+    )
+    .ok()?;
+  if !output.success {
+    return None;
+  }
+  let json = serde_json::from_slice::<serde_json::Value>(&output.stdout).ok()?;
+  let num = |v: Option<&serde_json::Value>| -> Option<f64> {
+    v.and_then(|x| x.as_str()).and_then(|s| s.parse::<f64>().ok())
+  };
+
+  let format = json.get("format");
+  let streams: Vec<serde_json::Value> = json
+    .get("streams")
+    .and_then(|s| s.as_array())
+    .cloned()
+    .unwrap_or_default();
+  let audio = options
+    .audio
+    .as_ref()
+    .filter(|a| mode == "exact" && !a.codec.trim().is_empty() && !a.codec.trim().eq_ignore_ascii_case("copy"));
+  let audio_bps = if audio_stream_index < 0 {
+    0.0
+  } else if let Some(audio) = audio {
+    audio.bitrate_kbps.map_or(DEFAULT_AUDIO_BITRATE_BPS, |kbps| kbps as f64 * 1000.0)
+  } else {
+    streams
+      .iter()
+      .filter(|s| s.get("codec_type").and_then(|t| t.as_str()) == Some("audio"))
+      .nth(audio_stream_index as usize)
+      .and_then(|s| num(s.get("bit_rate")))
+      .unwrap_or(DEFAULT_AUDIO_BITRATE_BPS)
+  };
+
+  let bits_per_second = if mode == "lossless" {
+    num(format.and_then(|f| f.get("bit_rate"))).or_else(|| {
+      let size = num(format.and_then(|f| f.get("size")))?;
+      let total = num(format.and_then(|f| f.get("duration"))).filter(|d| *d > 0.0)?;
+      Some(size * 8.0 / total)
+    })?
+  } else {
+    let video = streams
+      .iter()
+      .find(|s| s.get("codec_type").and_then(|t| t.as_str()) == Some("video"))?;
+    let mut width = video.get("width").and_then(|v| v.as_f64())?;
+    let mut height = video.get("height").and_then(|v| v.as_f64())?;
+    if let Some(max_height) = options.max_height.map(f64::from).filter(|h| *h < height) {
+      width *= max_height / height;
+      height = max_height;
+    }
+    let fps = video
+      .get("avg_frame_rate")
+      .and_then(|v| v.as_str())
+      .and_then(parse_ffprobe_rate)
+      .filter(|f| *f > 0.0)
+      .unwrap_or(30.0);
+    let settings = options.video.clone().unwrap_or_else(default_exact_video_settings);
+    let crf = settings.crf.map_or(EXACT_MODE_REFERENCE_CRF, f64::from);
+    let bits_per_pixel = EXACT_MODE_BITS_PER_PIXEL * 2f64.powf((EXACT_MODE_REFERENCE_CRF - crf) / 6.0);
+    let mut video_bps = width * height * fps * bits_per_pixel;
+    // A size target caps the bitrate (the same figure the encode uses), so it bounds the estimate.
+    if let Some(target_mb) = settings.target_size_mb {
+      let planned_audio_kbps = if audio_stream_index < 0 {
+        0.0
+      } else {
+        audio.and_then(|a| a.bitrate_kbps).map_or(DEFAULT_AUDIO_BITRATE_BPS / 1000.0, f64::from)
+      };
+      if let Ok(kbps) = target_video_bitrate_kbps(target_mb, duration_seconds, planned_audio_kbps) {
+        video_bps = video_bps.min(kbps as f64 * 1000.0);
+      }
+    }
+    video_bps + audio_bps
+  };
+
+  Some((bits_per_second * duration_seconds / 8.0 * OUTPUT_SIZE_SAFETY_FACTOR) as u64)
+}
+
+#[cfg(windows)]
+fn free_disk_bytes(dir: &Path) -> Option<u64> {
+  use std::os::windows::ffi::OsStrExt;
+  use windows::core::PCWSTR;
+  use windows::Win32::Storage::FileSystem::GetDiskFreeSpaceExW;
+
+  let wide: Vec<u16> = dir.as_os_str().encode_wide().chain(std::iter::once(0)).collect();
+  let mut available: u64 = 0;
+  unsafe {
+    GetDiskFreeSpaceExW(PCWSTR::from_raw(wide.as_ptr()), Some(&mut available as *mut u64), None, None).ok()?;
+  }
+  Some(available)
+}
+
+#[cfg(not(windows))]
+fn free_disk_bytes(dir: &Path) -> Option<u64> {
+  // POSIX `df -P` prints one data line: filesystem, 1K-blocks, used, available, capacity, mount.
+  let output = Command::new("df")
+    .arg("-Pk")
+    .arg(dir)
+    .stdin(Stdio::null())
+    .stdout(Stdio::piped())
+    .stderr(Stdio::null())
+    .output()
+    .ok()?;
+  if !output.status.success() {
+    return None;
+  }
+  let text = String::from_utf8_lossy(&output.stdout);
+  let line = text.lines().nth(1)?;
+  let available_kb: u64 = line.split_whitespace().nth(3)?.parse().ok()?;
+  Some(available_kb * 1024)
+}
+
+// The output folder may not exist yet (the export creates it); check the closest folder that does.
+fn nearest_existing_dir(dir: &Path) -> &Path {
+  dir.ancestors().find(|d| d.is_dir()).unwrap_or(dir)
+}
+
+fn check_dir_writable(dir: &Path) -> Result<(), String> {
+  let probe = dir.join(format!(".clipwave-write-test-{}", std::process::id()));
+  fs::write(&probe, b"")
+    .map_err(|e| format!("Output folder is not writable ({}): {e}", dir.to_string_lossy()))?;
+  let _ = fs::remove_file(&probe);
+  Ok(())
+}
+
+fn run_export_preflight(
+  ffprobe_path: &Path,
+  input_path: &str,
+  mode: &str,
+  duration_seconds: f64,
+  audio_stream_index: i32,
+  output_dir: &Path,
+  options: &TrimOptions,
+) -> ExportPreflightResult {
+  let mut result = ExportPreflightResult {
+    output_dir: output_dir.to_string_lossy().to_string(),
+    estimated_output_bytes: None,
+    free_bytes: None,
+    writable: false,
+    warnings: Vec::new(),
+    error: None,
+  };
+
+  let existing_dir = nearest_existing_dir(output_dir);
+  if let Err(e) = check_dir_writable(existing_dir) {
+    result.error = Some(e);
+    return result;
+  }
+  result.writable = true;
+
+  result.estimated_output_bytes =
+    estimate_output_bytes(ffprobe_path, input_path, mode, duration_seconds, audio_stream_index, options);
+  result.free_bytes = free_disk_bytes(existing_dir);
+
+  const MB: f64 = 1024.0 * 1024.0;
+  match (result.estimated_output_bytes, result.free_bytes) {
+    (Some(estimate), Some(free)) if estimate > free => {
+      result.error = Some(format!(
+        "Not enough disk space: the export needs about {:.0} MB but only {:.0} MB is free in {}.",
+        estimate as f64 / MB,
+        free as f64 / MB,
+        result.output_dir
+      ));
+    }
+    (Some(estimate), Some(free)) if (estimate as f64) > free as f64 * 0.9 => {
+      result.warnings.push(format!(
+        "Disk space is tight: the export needs about {:.0} MB and {:.0} MB is free.",
+        estimate as f64 / MB,
+        free as f64 / MB
+      ));
+    }
+    (None, _) => result.warnings.push("Could not estimate the output size.".to_string()),
+    (_, None) => result.warnings.push("Could not determine free disk space.".to_string()),
+    _ => {}
+  }
+
+  result
+}
+
+#[tauri::command]
+fn export_preflight(
+  input_path: String,
+  in_time: String,
+  out_time: String,
+  mode: String,
+  audio_stream_index: i32,
+  ffmpeg_bin_dir: String,
+  options: Option<TrimOptions>,
+) -> Result<ExportPreflightResult, String> {
+  let (mode, options) = resolve_export_preset(&mode, &options.unwrap_or_default())?;
+  let input_path = normalize_input_path_for_cli(&input_path);
+  ensure_input_file_exists(&input_path)?;
+  validate_ffmpeg_bin_dir(&ffmpeg_bin_dir)?;
+
+  let (_ffmpeg_path, ffprobe_path, _ffmpeg_bin_dir_used) =
+    resolve_ffmpeg_binaries_with_fallback(&ffmpeg_bin_dir);
+
+  let frame_rate = frame_rate_for_times(&ffprobe_path, &input_path, [in_time.as_str(), out_time.as_str()]);
+  let in_seconds = timecode::parse(&in_time, frame_rate)?;
+  let out_seconds = timecode::parse(&out_time, frame_rate)?;
+  if out_seconds <= in_seconds {
+    return Err("OUT must be greater than IN".to_string());
+  }
+  let mode = mode.trim().to_lowercase();
+  let output_path = build_output_path(&input_path, &mode, &in_time, &out_time, &options, "")?;
+  let output_dir = output_path
+    .parent()
+    .ok_or_else(|| "Could not determine output folder".to_string())?;
+
+  Ok(run_export_preflight(
+    &ffprobe_path,
+    &input_path,
+    &mode,
+    out_seconds - in_seconds,
+    audio_stream_index,
+    output_dir,
+    &options,
+  ))
+}
+
+const PARTIAL_EXPORT_MARKER: &str = ".clipwave-partial";
+
+/// Hidden sibling of `output_path` that keeps the extension so ffmpeg still picks the right muxer.
+/// The pid, clock and sequence suffix keep concurrent exports to the same name from sharing one file.
+fn partial_output_path(output_path: &Path) -> PathBuf {
+  static SEQUENCE: std::sync::atomic::AtomicU64 = std::sync::atomic::AtomicU64::new(0);
+  let stem = output_path.file_stem().unwrap_or_default().to_string_lossy();
+  let nanos = std::time::SystemTime::now()
+    .duration_since(std::time::UNIX_EPOCH)
+    .map(|d| d.as_nanos())
+    .unwrap_or(0);
+  let sequence = SEQUENCE.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+  let unique = format!("{}_{nanos}_{sequence}", std::process::id());
+  let name = match output_path.extension() {
+    Some(ext) => format!(".{stem}{PARTIAL_EXPORT_MARKER}-{unique}.{}", ext.to_string_lossy()),
+    None => format!(".{stem}{PARTIAL_EXPORT_MARKER}-{unique}"),
+  };
+  output_path.with_file_name(name)
+}
+
+fn app_data_dir_slot() -> &'static OnceLock<PathBuf> {
+  static DIR: OnceLock<PathBuf> = OnceLock::new();
+  &DIR
+}
+
+// Per-app data directory, resolved by Tauri during setup.
+fn app_data_dir() -> Option<PathBuf> {
+  app_data_dir_slot().get().cloned()
+}
+
+fn app_cache_dir_slot() -> &'static OnceLock<PathBuf> {
+  static DIR: OnceLock<PathBuf> = OnceLock::new();
+  &DIR
+}
+
+fn app_config_dir_slot() -> &'static OnceLock<PathBuf> {
+  static DIR: OnceLock<PathBuf> = OnceLock::new();
+  &DIR
+}
+
+// Per-app config directory for user settings such as export presets.
+fn app_config_dir() -> Option<PathBuf> {
+  app_config_dir_slot().get().cloned()
+}
+
+// Per-app cache directory for data that can be rebuilt (see `disk_cache`).
+fn app_cache_dir() -> Option<PathBuf> {
+  #[cfg(test)]
+  if let Some(dir) = TEST_CACHE_DIR.with(|d| d.borrow().clone()) {
+    return Some(dir);
+  }
+  app_cache_dir_slot().get().cloned()
+}
+
+#[cfg(test)]
+thread_local! {
+  static TEST_CACHE_DIR: std::cell::RefCell<Option<PathBuf>> = const { std::cell::RefCell::new(None) };
+}
+
+/// Use `dir` as the app cache folder on the current test thread.
+#[cfg(test)]
+fn set_app_cache_dir_for_test(dir: PathBuf) {
+  TEST_CACHE_DIR.with(|d| *d.borrow_mut() = Some(dir));
+}
+
+fn pending_exports_journal_path() -> Option<PathBuf> {
+  app_data_dir().map(|d| d.join("pending_exports.txt"))
+}
+
+fn pending_exports_lock() -> &'static Mutex<()> {
+  static LOCK: OnceLock<Mutex<()>> = OnceLock::new();
+  LOCK.get_or_init(|| Mutex::new(()))
+}
+
+fn read_pending_exports(journal: &Path) -> Vec<String> {
+  fs::read_to_string(journal)
+    .map(|text| text.lines().map(str::trim).filter(|l| !l.is_empty()).map(str::to_string).collect())
+    .unwrap_or_default()
+}
+
+// The journal lets the next launch find partial files left behind by a crash or forced quit.
+fn register_pending_export(partial_path: &Path) {
+  let Some(journal) = pending_exports_journal_path() else {
+    return;
+  };
+  let _guard = pending_exports_lock().lock();
+  if let Some(dir) = journal.parent() {
+    let _ = fs::create_dir_all(dir);
+  }
+  let mut entries = read_pending_exports(&journal);
+  entries.push(partial_path.to_string_lossy().to_string());
+  let _ = fs::write(&journal, entries.join("\n"));
+}
+
+fn unregister_pending_export(partial_path: &Path) {
+  let Some(journal) = pending_exports_journal_path() else {
+    return;
+  };
+  let _guard = pending_exports_lock().lock();
+  let target = partial_path.to_string_lossy();
+  let entries: Vec<String> = read_pending_exports(&journal)
+    .into_iter()
+    .filter(|e| e.as_str() != target)
+    .collect();
+  let _ = fs::write(&journal, entries.join("\n"));
+}
+
+/// Remove partial files recorded by exports that never finished. Returns how many were deleted.
+fn cleanup_interrupted_exports() -> usize {
+  let Some(journal) = pending_exports_journal_path() else {
+    return 0;
+  };
+  let _guard = pending_exports_lock().lock();
+  let mut removed = 0;
+  for entry in read_pending_exports(&journal) {
+    let path = Path::new(&entry);
+    // Only ever delete files we named ourselves.
+    let ours = path
+      .file_name()
+      .map(|n| n.to_string_lossy().contains(PARTIAL_EXPORT_MARKER))
+      .unwrap_or(false);
+    if ours && path.is_file() && fs::remove_file(path).is_ok() {
+      removed += 1;
+    }
+  }
+  let _ = fs::remove_file(&journal);
+  removed
+}
+
+// ffmpeg options accepted in extra arguments and how many values each consumes (stream specifiers
+// such as `-c:v` match on the part before the colon). Anything else is rejected, since guessing an
+// unknown option's arity would let a stray path through as an extra output file.
+const FFMPEG_EXTRA_OPTIONS: &[(&str, usize)] = &[
+  // Flags.
+  ("-an", 0), ("-vn", 0), ("-sn", 0), ("-dn", 0), ("-shortest", 0), ("-nostdin", 0), ("-nostats", 0),
+  ("-stats", 0), ("-hide_banner", 0), ("-autorotate", 0), ("-noautorotate", 0), ("-accurate_seek", 0),
+  ("-noaccurate_seek", 0), ("-copyts", 0), ("-start_at_zero", 0), ("-re", 0), ("-ignore_unknown", 0),
+  ("-copy_unknown", 0), ("-benchmark", 0), ("-xerror", 0), ("-bitexact", 0), ("-copyinkf", 0),
+  ("-fix_sub_duration", 0), ("-vstats", 0),
+  // Codecs and rate control.
+  ("-c", 1), ("-codec", 1), ("-vcodec", 1), ("-acodec", 1), ("-scodec", 1), ("-b", 1), ("-vb", 1), ("-ab", 1),
+  ("-maxrate", 1), ("-minrate", 1), ("-bufsize", 1), ("-crf", 1), ("-cq", 1), ("-qp", 1), ("-q", 1),
+  ("-qscale", 1), ("-rc", 1), ("-preset", 1), ("-tune", 1), ("-profile", 1), ("-level", 1), ("-g", 1),
+  ("-keyint_min", 1), ("-bf", 1), ("-refs", 1), ("-sc_threshold", 1), ("-x264-params", 1), ("-x264opts", 1),
+  ("-x265-params", 1), ("-svtav1-params", 1), ("-cpu-used", 1), ("-deadline", 1), ("-row-mt", 1),
+  ("-threads", 1), ("-filter_threads", 1), ("-strict", 1),
+  // Video and audio format.
+  ("-vf", 1), ("-af", 1), ("-filter", 1), ("-pix_fmt", 1), ("-r", 1), ("-fps_mode", 1), ("-vsync", 1),
+  ("-s", 1), ("-aspect", 1), ("-color_primaries", 1), ("-color_trc", 1), ("-colorspace", 1),
+  ("-color_range", 1), ("-field_order", 1), ("-ar", 1), ("-ac", 1), ("-sample_fmt", 1),
+  ("-channel_layout", 1),
+  // Timing, muxing and metadata.
+  ("-ss", 1), ("-t", 1), ("-to", 1), ("-sseof", 1), ("-itsoffset", 1), ("-fs", 1), ("-frames", 1),
+  ("-vframes", 1), ("-aframes", 1), ("-avoid_negative_ts", 1), ("-fflags", 1), ("-flags", 1),
+  ("-movflags", 1), ("-brand", 1), ("-muxdelay", 1), ("-muxpreload", 1), ("-max_muxing_queue_size", 1),
+  ("-metadata", 1), ("-map_metadata", 1), ("-map_chapters", 1), ("-disposition", 1), ("-tag", 1),
+  ("-timecode", 1), ("-write_tmcd", 1),
+  // Input probing and decoding.
+  ("-probesize", 1), ("-analyzeduration", 1), ("-thread_queue_size", 1), ("-hwaccel", 1),
+  ("-hwaccel_device", 1), ("-hwaccel_output_format", 1), ("-display_rotation", 1),
+  ("-v", 1), ("-loglevel", 1),
+];
+
+/// Reject user arguments that would break the export: overwrite flags (ffmpeg writes a temp file we
+/// rename), `-progress` (we parse it), extra inputs and stream maps (the export picks the streams),
+/// options outside `FFMPEG_EXTRA_OPTIONS` and anything that would add or replace the output file.
+fn validate_extra_ffmpeg_args(list: &str, args: &[String]) -> Result<(), String> {
+  let mut i = 0;
+  while i < args.len() {
+    let arg = args[i].as_str();
+    if arg == "-" || !arg.starts_with('-') {
+      return Err(format!(
+        "Extra {list} arguments: '{arg}' would be read as an output file; the output path is set by the export settings"
+      ));
+    }
+    let name = arg.split(':').next().unwrap_or(arg);
+    match name {
+      "-y" | "-n" => {
+        return Err(format!("Extra {list} arguments cannot include {arg}: overwrite behaviour is managed by the export"));
+      }
+      "-progress" => {
+        return Err(format!("Extra {list} arguments cannot include -progress: it is used to report export progress"));
+      }
+      "-i" => {
+        return Err(format!("Extra {list} arguments cannot add inputs (-i): stream selection assumes the source is the first input"));
+      }
+      "-map" | "-filter_complex" | "-lavfi" => {
+        return Err(format!("Extra {list} arguments cannot include {name}: streams are selected by the export settings"));
+      }
+      _ => {}
+    }
+    let Some(&(_, values)) = FFMPEG_EXTRA_OPTIONS.iter().find(|(option, _)| *option == name) else {
+      return Err(format!("Extra {list} arguments: {arg} is not a supported ffmpeg option"));
+    };
+    if i + values >= args.len() {
+      return Err(format!("Extra {list} arguments: {arg} is missing its value"));
+    }
+    i += 1 + values;
+  }
+  Ok(())
+}
+
+// What Exact mode encodes with when neither the request nor a preset says otherwise.
+fn default_exact_video_settings() -> presets::VideoSettings {
+  presets::VideoSettings {
+    codec: "libx264".to_string(),
+    crf: Some(18),
+    speed: Some("veryfast".to_string()),
+    pixel_format: Some("yuv420p".to_string()),
+    target_size_mb: None,
+  }
+}
+
+// Settings the request left unset come from the preset. Preset output args go first so the
+// request's own output args still win.
+fn apply_preset(options: &TrimOptions, preset: presets::ExportPreset) -> TrimOptions {
+  let mut resolved = options.clone();
+  resolved.container = resolved.container.or(preset.container);
+  resolved.video = resolved.video.or(preset.video);
+  resolved.max_height = resolved.max_height.or(preset.max_height);
+  resolved.audio = resolved.audio.or(preset.audio);
+  resolved.name_template = resolved.name_template.or(preset.name_template);
+  resolved.output_args = preset.output_args.into_iter().chain(resolved.output_args).collect();
+  resolved
+}
+
+// Average video bitrate (kbit/s, MB = 10^6 bytes) that fits a clip of `duration` seconds into
+// `target_mb` next to the audio, keeping 5% for container overhead.
+fn target_video_bitrate_kbps(target_mb: f64, duration: f64, audio_kbps: f64) -> Result<u32, String> {
+  let total_kbps = target_mb * 8000.0 * 0.95 / duration;
+  let video_kbps = total_kbps - audio_kbps;
+  if !video_kbps.is_finite() || video_kbps < 100.0 {
+    return Err(format!(
+      "A {duration:.1}s clip cannot fit in {target_mb} MB at a usable quality; shorten the range or raise the target size"
+    ));
+  }
+  Ok(video_kbps.floor() as u32)
+}
+
+// One export as the frontend sends it to `trim_media` and `plan_trim`.
+#[derive(Clone, Debug, Deserialize)]
+struct TrimRequest {
+  input_path: String,
+  in_time: String,
+  out_time: String,
+  mode: String,
+  audio_stream_index: i32,
+  subtitle_stream_index: i32,
+  ffmpeg_bin_dir: String,
+  options: Option<TrimOptions>,
+}
+
+// Everything trim_media decides before ffmpeg starts.
+struct TrimPlan {
+  ffmpeg_path: PathBuf,
+  ffprobe_path: PathBuf,
+  // Resolved through the request's preset.
+  mode: String,
+  options: TrimOptions,
+  audio_stream_index: i32,
+  subtitle_stream_index: i32,
+  in_seconds: f64,
+  out_seconds: f64,
+  output_path: PathBuf,
+  // ffmpeg writes here; the file is renamed to `output_path` once verified.
+  partial_path: PathBuf,
+  // Chapters ffmetadata (path, contents); only the run writes it, just before ffmpeg starts.
+  chapters_file: Option<(PathBuf, String)>,
+  // Full ffmpeg argument list, ending with `-y <partial_path>`.
+  args: Vec<String>,
+  preflight_warnings: Vec<String>,
+  removed_metadata_tags: Vec<String>,
+  shifted_creation: Option<f64>,
+  output_creation_time: Option<String>,
+}
+
+/// Apply the request's preset, if any: its mode wins and its settings fill whatever the request left
+/// unset. Every command that plans or checks an export resolves through here, once.
+fn resolve_export_preset(mode: &str, options: &TrimOptions) -> Result<(String, TrimOptions), String> {
+  match options.preset.as_deref().map(str::trim).filter(|n| !n.is_empty()) {
+    Some(name) => {
+      let preset = presets::find(name).ok_or_else(|| format!("Unknown export preset '{name}'"))?;
+      // User presets saved before a check was added are held to it here.
+      presets::validate(&preset).map_err(|e| format!("Export preset '{}' is invalid: {e}", preset.name))?;
+      Ok((preset.mode.clone(), apply_preset(options, preset)))
+    }
+    None => Ok((mode.to_string(), options.clone())),
+  }
+}
+
+/// Validate a trim request and build its ffmpeg command line, after applying its preset. Touches
+/// nothing on disk: the chapters metadata file is written by `run_trim_plan`.
+fn plan_trim_media(request: &TrimRequest) -> Result<TrimPlan, String> {
+  let (mode, options) = resolve_export_preset(&request.mode, &request.options.clone().unwrap_or_default())?;
+  let options = &options;
+  let TrimRequest {
+    input_path,
+    in_time,
+    out_time,
+    audio_stream_index,
+    subtitle_stream_index,
+    ffmpeg_bin_dir,
+    ..
+  } = request;
+  let (input_path, audio_stream_index, subtitle_stream_index) =
+    (input_path.as_str(), *audio_stream_index, *subtitle_stream_index);
+  ensure_input_file_exists(input_path)?;
+  validate_ffmpeg_bin_dir(ffmpeg_bin_dir)?;
+
+  let (ffmpeg_path, ffprobe_path, ffmpeg_bin_dir_used) =
+    resolve_ffmpeg_binaries_with_fallback(ffmpeg_bin_dir);
+
+  // Parse with full precision to preserve exact keyframe times; SMPTE and frame numbers use the probed rate
+  let marker_times = options.chapter_markers.iter().flatten().map(|m| m.time.as_str());
+  let frame_rate = frame_rate_for_times(
+    &ffprobe_path,
+    input_path,
+    [in_time.as_str(), out_time.as_str()].into_iter().chain(marker_times),
+  );
+  let in_seconds_f64 = timecode::parse(in_time, frame_rate)?;
+  let out_seconds_f64 = timecode::parse(out_time, frame_rate)?;
+  if out_seconds_f64 <= in_seconds_f64 {
+    return Err("OUT must be greater than IN".to_string());
+  }
+
+  let mode = mode.trim().to_lowercase();
+  if mode != "lossless" && mode != "exact" {
+    return Err("Mode must be 'lossless' or 'exact'".to_string());
+  }
+  if mode == "lossless" && (options.video.is_some() || options.audio.is_some() || options.max_height.is_some()) {
+    return Err("Video, scaling and audio settings require Exact mode".to_string());
+  }
+  if let Some(container) = options.container.as_deref().map(str::trim).filter(|c| !c.is_empty()) {
+    if !presets::CONTAINERS.contains(&container.to_lowercase().as_str()) {
+      return Err(format!(
+        "Unsupported container '{container}' (expected one of {})",
+        presets::CONTAINERS.join(", ")
+      ));
+    }
+  }
+  if mode == "exact" {
+    let container = match options.container.as_deref().map(str::trim).filter(|c| !c.is_empty()) {
+      Some(container) => container.to_string(),
+      None => Path::new(input_path)
+        .extension()
+        .map(|e| e.to_string_lossy().to_lowercase())
+        .unwrap_or_default(),
+    };
+    let video = options.video.clone().unwrap_or_else(default_exact_video_settings);
+    presets::check_codecs(&container, Some(&video), options.audio.as_ref())?;
+  }
+
+  validate_extra_ffmpeg_args("pre-input", &options.pre_input_args)?;
+  validate_extra_ffmpeg_args("post-input", &options.post_input_args)?;
+  validate_extra_ffmpeg_args("output", &options.output_args)?;
+
+  let output_path = {
+    let needs_lang = options
+      .name_template
+      .as_deref()
+      .is_some_and(|t| t.contains("{audio_lang}"));
+    let audio_lang = if needs_lang {
+      audio_language_best_effort(&ffprobe_path, input_path, audio_stream_index)
+    } else {
+      String::new()
+    };
+    let base = build_output_path(input_path, &mode, in_time, out_time, options, &audio_lang)?;
+    ensure_output_is_not_input(&base, input_path)?;
+    apply_output_collision_policy(base, options.collision.as_deref())?
+  };
+
+  // Fail fast on a read-only folder or a disk that cannot hold the clip, instead of partway through ffmpeg.
+  let preflight = run_export_preflight(
+    &ffprobe_path,
+    input_path,
+    &mode,
+    out_seconds_f64 - in_seconds_f64,
+    audio_stream_index,
+    output_path.parent().unwrap_or_else(|| Path::new(".")),
+    options,
+  );
+  if let Some(error) = &preflight.error {
+    return Err(error.clone());
+  }
+
+  let rotation_degrees = probe_video_rotation_degrees_best_effort(&ffprobe_path, &ffmpeg_bin_dir_used, input_path);
+  let rotation_filter = rotation_filter_for_degrees(rotation_degrees);
+
+  let cfr_rate = match options.constant_frame_rate.as_deref().filter(|r| !r.trim().is_empty()) {
+    Some(_) if mode == "lossless" => {
+      return Err("Constant frame rate output requires Exact mode".to_string());
+    }
+    Some(requested) => Some(resolve_cfr_rate(requested, &ffprobe_path, input_path)?),
+    None => None,
+  };
+
+  if mode == "lossless" && rotation_degrees != 0 {
+    return Err(format!(
+      "Lossless cannot reliably preserve vertical orientation (input is rotated {rotation_degrees}°). Use Exact mode."
+    ));
+  }
+
+  let video = options.video.clone().unwrap_or_else(default_exact_video_settings);
+  // Re-encoding audio codec; None copies the source stream.
+  let audio_codec = options
+    .audio
+    .as_ref()
+    .map(|a| a.codec.trim())
+    .filter(|c| !c.is_empty() && !c.eq_ignore_ascii_case("copy"));
+  let audio_bitrate_kbps = options.audio.as_ref().and_then(|a| a.bitrate_kbps);
+  let video_bitrate_kbps = match video.target_size_mb {
+    Some(target_mb) if mode == "exact" => {
+      let audio_kbps = if audio_stream_index < 0 {
+        0.0
+      } else {
+        audio_bitrate_kbps.map_or(DEFAULT_AUDIO_BITRATE_BPS / 1000.0, f64::from)
+      };
+      Some(target_video_bitrate_kbps(target_mb, out_seconds_f64 - in_seconds_f64, audio_kbps)?)
+    }
+    _ => None,
+  };
+
+  // Chapters overlapping the range are re-written relative to the clip start; the rest are dropped.
+  // ffmpeg's own chapter copy keeps source times, which land outside (or at the wrong place in) the clip.
+  let source_chapters = probe_chapters_best_effort(&ffprobe_path, input_path);
+  let clip_chapters = match &options.chapter_markers {
+    Some(markers) if !markers.is_empty() => {
+      chapter_markers_to_clip_chapters(markers, in_seconds_f64, out_seconds_f64, frame_rate)?
+    }
+    _ => retime_chapters_for_range(&source_chapters, in_seconds_f64, out_seconds_f64),
+  };
+
+  let (metadata_args, removed_metadata_tags) = match &options.metadata {
+    Some(meta) => {
+      let strip = meta.strip.trim();
+      let source_tags = if strip.is_empty() || strip.eq_ignore_ascii_case("none") {
+        SourceMetadataTags::default()
+      } else {
+        probe_metadata_tags_best_effort(&ffprobe_path, input_path)
+      };
+      // The same streams the `-map` options below select.
+      let mut mapped = vec![('v', 0)];
+      if audio_stream_index >= 0 {
+        mapped.push(('a', audio_stream_index));
+      }
+      if subtitle_stream_index >= 0 && mode != "lossless" {
+        let subtitle = source_tags.streams.iter().find(|s| s.index == subtitle_stream_index);
+        mapped.extend(subtitle.map(|s| (s.kind, s.order)));
+      }
+      build_metadata_args(meta, &source_tags, &mapped)?
+    }
+    None => (Vec::new(), Vec::new()),
+  };
+
+  // creation_time is written in UTC (what MP4/MKV store); the wall-clock instant matches the source's local tag.
+  let shifted_creation = if options.shift_creation_time {
+    probe_creation_time_best_effort(&ffprobe_path, input_path)
+      .and_then(|t| parse_iso8601_timestamp(&t))
+      .map(|(unix, _offset)| unix + in_seconds_f64)
+  } else {
+    None
+  };
+  let output_creation_time = shifted_creation.map(|unix| format_iso8601_timestamp(unix, 0));
+
+  let chapters_file =
+    (!clip_chapters.is_empty()).then(|| (ffmetadata_temp_path(), render_ffmetadata_chapters(&clip_chapters)));
+
+  let mut args: Vec<String> = Vec::new();
+  let mut push = |parts: &[&str]| args.extend(parts.iter().map(|p| p.to_string()));
+  fn as_refs(list: &[String]) -> Vec<&str> {
+    list.iter().map(String::as_str).collect()
+  }
+
+  // For millisecond precision, pass time as decimal seconds (e.g., "3.170000")
+  let in_time_arg = format!("{:.6}", in_seconds_f64);
+  let duration_arg = format!("{:.6}", out_seconds_f64 - in_seconds_f64);
+  let chapters_arg = chapters_file.as_ref().map(|(p, _)| p.to_string_lossy().to_string());
+
+  if mode == "lossless" {
+    // LOSSLESS: -ss BEFORE -i for input-level seeking, with -t for duration.
+    // Placing -ss before -i makes FFmpeg seek to the nearest keyframe <= IN
+    // at the demuxer level.  With -c copy the output starts from that keyframe
+    // (so the start may be slightly early), and -t counts from the actual
+    // output start, giving the correct requested duration.
+    //
+    // Previously -ss was placed AFTER -i, which caused -t to count from the
+    // seek point while the output started at the earlier keyframe, inflating
+    // the output duration by the keyframe-to-IN gap.
+    push(&["-v", "error", "-progress", "pipe:1"]);
+    push(&as_refs(&options.pre_input_args));
+    push(&["-ss", &in_time_arg, "-i", input_path]);
+    if let Some(path) = &chapters_arg {
+      push(&["-f", "ffmetadata", "-i", path]);
+    }
+    push(&["-t", &duration_arg]);
+  } else {
+    // EXACT: -ss BEFORE -i for fast seeking, then re-encode for frame accuracy.
+    push(&["-v", "error", "-progress", "pipe:1"]);
+    push(&as_refs(&options.pre_input_args));
+    push(&["-accurate_seek", "-ss", &in_time_arg]);
+
+    if rotation_filter.is_some() {
+      push(&["-noautorotate"]);
+    }
+
+    push(&["-i", input_path]);
+    if let Some(path) = &chapters_arg {
+      push(&["-f", "ffmetadata", "-i", path]);
+    }
+    push(&["-t", &duration_arg]);
+  }
+  push(&as_refs(&options.post_input_args));
+
+  push(&["-map", "0:v:0"]);
+  if chapters_file.is_some() {
+    push(&["-map_chapters", "1"]);
+  } else if !source_chapters.is_empty() {
+    push(&["-map_chapters", "-1"]);
+  }
+
+  if audio_stream_index < 0 {
+    push(&["-an"]);
+  } else {
+    // `audio_stream_index` is treated as the 0-based order within audio streams (not the global ffprobe stream index).
+    push(&["-map", &format!("0:a:{audio_stream_index}")]);
+  }
+
+  if subtitle_stream_index >= 0 && mode != "lossless" {
     // Subtitles are excluded in lossless mode: subtitle packets can span the
     // cut boundary and force FFmpeg to extend the output duration beyond the
     // requested range.  Exact mode re-encodes everything so it trims cleanly.
-    cmd.args(["-map", &format!("0:{subtitle_stream_index}")]);
+    push(&["-map", &format!("0:{subtitle_stream_index}")]);
+  }
+
+  if mode == "lossless" {
+    // Determine output container from extension
+    let output_ext = output_path
+      .extension()
+      .map(|e| e.to_string_lossy().to_lowercase())
+      .unwrap_or_default();
+
+    push(&["-c", "copy"]);
+
+    // MP4 container needs different timestamp handling than MKV
+    if output_ext == "mp4" || output_ext == "m4v" || output_ext == "mov" {
+      // For MP4: avoid_negative_ts with make_zero and fflags to fix timestamps
+      push(&["-avoid_negative_ts", "make_zero", "-fflags", "+genpts"]);
+    } else {
+      // For MKV and other containers: copyts works better
+      push(&["-copyts", "-avoid_negative_ts", "make_zero"]);
+    }
+
+    if rotation_degrees != 0 {
+      push(&["-metadata:s:v:0", &format!("rotate={rotation_degrees}")]);
+    }
+  } else {
+    // Rotate first so the height limit applies to the picture as displayed; never upscale.
+    let scale = options.max_height.map(|h| format!("scale=-2:'min(ih,{h})'"));
+    let filters: Vec<&str> = rotation_filter.into_iter().chain(scale.as_deref()).collect();
+    if !filters.is_empty() {
+      push(&["-vf", &filters.join(",")]);
+    }
+    if rotation_filter.is_some() {
+      push(&["-metadata:s:v:0", "rotate=0"]);
+    }
+
+    push(&["-c:v", &video.codec]);
+    if let Some(kbps) = video_bitrate_kbps {
+      // Capped average bitrate so the file lands under the target size.
+      push(&["-b:v", &format!("{kbps}k"), "-maxrate", &format!("{kbps}k"), "-bufsize", &format!("{}k", kbps * 2)]);
+    } else if let Some(crf) = video.crf {
+      push(&["-crf", &crf.to_string()]);
+    }
+    if let Some(speed) = &video.speed {
+      push(&["-preset", speed]);
+    }
+    if let Some(pixel_format) = &video.pixel_format {
+      push(&["-pix_fmt", pixel_format]);
+    }
+    let output_ext = output_path
+      .extension()
+      .map(|e| e.to_string_lossy().to_lowercase())
+      .unwrap_or_default();
+    if video.codec == "libx265" && matches!(output_ext.as_str(), "mp4" | "m4v" | "mov") {
+      // Apple players only recognise HEVC in MP4/MOV with the hvc1 tag.
+      push(&["-tag:v", "hvc1"]);
+    }
+
+    if let Some(rate) = &cfr_rate {
+      // Duplicate/drop frames onto a fixed grid; timestamps keep their wall-clock position.
+      push(&["-fps_mode", "cfr", "-r", rate]);
+    }
+
+    if audio_stream_index >= 0 {
+      if cfr_rate.is_some() {
+        // Resample audio against its timestamps so gaps and drift in VFR sources stay aligned with the CFR video.
+        let bitrate = format!("{}k", audio_bitrate_kbps.unwrap_or(192));
+        push(&["-af", "aresample=async=1:first_pts=0", "-c:a", audio_codec.unwrap_or("aac"), "-b:a", &bitrate]);
+      } else if let Some(codec) = audio_codec {
+        push(&["-c:a", codec]);
+        if let Some(kbps) = audio_bitrate_kbps {
+          push(&["-b:a", &format!("{kbps}k")]);
+        }
+      } else {
+        push(&["-c:a", "copy"]);
+      }
+      if let Some(channels) = options.audio.as_ref().and_then(|a| a.channels) {
+        if cfr_rate.is_some() || audio_codec.is_some() {
+          push(&["-ac", &channels.to_string()]);
+        }
+      }
+    }
+
+    if subtitle_stream_index >= 0 {
+      push(&["-c:s", "copy"]);
+      // Subtitle packet durations can extend past the requested cut end
+      // (e.g., a cue that starts before OUT but ends after it). Clamp output
+      // to the shortest mapped stream so Exact mode duration stays precise.
+      push(&["-shortest"]);
+    }
+  }
+
+  push(&as_refs(&metadata_args));
+
+  if let Some(ts) = &output_creation_time {
+    push(&["-metadata", &format!("creation_time={ts}")]);
+    push(&["-metadata:s:v", &format!("creation_time={ts}")]);
+    if audio_stream_index >= 0 {
+      push(&["-metadata:s:a", &format!("creation_time={ts}")]);
+    }
+  }
+
+  push(&as_refs(&options.output_args));
+
+  // ffmpeg writes to a hidden sibling that is only renamed into place once it has been validated,
+  // so a crash or failure never leaves a half-written file under the real name.
+  let partial_path = partial_output_path(&output_path);
+  push(&["-y", &partial_path.to_string_lossy()]);
+
+  Ok(TrimPlan {
+    ffmpeg_path,
+    ffprobe_path,
+    mode,
+    options: options.clone(),
+    audio_stream_index,
+    subtitle_stream_index,
+    in_seconds: in_seconds_f64,
+    out_seconds: out_seconds_f64,
+    output_path,
+    partial_path,
+    chapters_file,
+    args,
+    preflight_warnings: preflight.warnings,
+    removed_metadata_tags,
+    shifted_creation,
+    output_creation_time,
+  })
+}
+
+/// Built-in export presets followed by the user's.
+#[tauri::command]
+fn list_presets() -> Vec<presets::ExportPreset> {
+  presets::list()
+}
+
+/// Create or update a user preset (matched by name, ignoring case).
+#[tauri::command]
+fn save_preset(preset: presets::ExportPreset) -> Result<presets::ExportPreset, String> {
+  presets::save(preset)
+}
+
+#[tauri::command]
+fn delete_preset(name: String) -> Result<(), String> {
+  presets::delete(&name)
+}
+
+#[derive(Debug, Serialize)]
+struct TrimPlanResult {
+  program: String,
+  // Exactly what trim_media passes to ffmpeg, including the hidden partial output it renames at the end.
+  args: Vec<String>,
+  output_path: String,
+  // Copyable commands that write straight to `output_path`.
+  bash: String,
+  powershell: String,
+  warnings: Vec<String>,
+  // The command reads chapters from this ffmetadata file; a dry run does not write it.
+  chapters_path: Option<String>,
+  chapters_metadata: Option<String>,
+}
+
+// Characters that never need quoting; everything else is single-quoted.
+fn bash_quote(arg: &str) -> String {
+  let safe = |c: char| c.is_ascii_alphanumeric() || "_@%+=:,./-".contains(c);
+  if !arg.is_empty() && arg.chars().all(safe) {
+    arg.to_string()
+  } else {
+    format!("'{}'", arg.replace('\'', "'\\''"))
+  }
+}
+
+// PowerShell treats `,`, `@`, `$`, `;` and friends specially, so its unquoted set is narrower.
+fn powershell_quote(arg: &str) -> String {
+  let safe = |c: char| c.is_ascii_alphanumeric() || "_+=:./\\-".contains(c);
+  if !arg.is_empty() && arg.chars().all(safe) {
+    arg.to_string()
+  } else {
+    format!("'{}'", arg.replace('\'', "''"))
+  }
+}
+
+fn shell_command_line(program: &str, args: &[String], quote: fn(&str) -> String) -> String {
+  std::iter::once(quote(program))
+    .chain(args.iter().map(|a| quote(a)))
+    .collect::<Vec<_>>()
+    .join(" ")
+}
+
+/// Dry run of trim_media: same validation and decisions, returns the ffmpeg command instead of running it.
+/// Nothing is written; a chapters file the command reads is returned as text to save at `chapters_path`.
+#[tauri::command]
+fn plan_trim(request: TrimRequest) -> Result<TrimPlanResult, String> {
+  let plan = plan_trim_media(&request)?;
+
+  let program = plan.ffmpeg_path.to_string_lossy().to_string();
+  let output_path = plan.output_path.to_string_lossy().to_string();
+  let mut copy_args = plan.args.clone();
+  if let Some(last) = copy_args.last_mut() {
+    *last = output_path.clone();
+  }
+
+  Ok(TrimPlanResult {
+    bash: shell_command_line(&program, &copy_args, bash_quote),
+    powershell: format!("& {}", shell_command_line(&program, &copy_args, powershell_quote)),
+    program,
+    args: plan.args,
+    output_path,
+    warnings: plan.preflight_warnings,
+    chapters_path: plan.chapters_file.as_ref().map(|(p, _)| p.to_string_lossy().to_string()),
+    chapters_metadata: plan.chapters_file.map(|(_, text)| text),
+  })
+}
+
+#[tauri::command]
+fn trim_media(window: tauri::Window, request: TrimRequest) -> Result<TrimResult, String> {
+  let plan = plan_trim_media(&request)?;
+  run_trim_plan(plan, &mut |percent| {
+    let _ = window.emit("cut_progress", serde_json::json!({ "percent": percent }));
+  })
+}
+
+/// Run a planned export: encode into the partial file, verify it, move it into place and report.
+/// `on_progress` gets the encode percentage whenever it changes.
+fn run_trim_plan(plan: TrimPlan, on_progress: &mut dyn FnMut(i32)) -> Result<TrimResult, String> {
+  let TrimPlan {
+    ffmpeg_path,
+    ffprobe_path,
+    mode,
+    options,
+    audio_stream_index,
+    subtitle_stream_index,
+    in_seconds: in_seconds_f64,
+    out_seconds: out_seconds_f64,
+    output_path,
+    partial_path,
+    chapters_file,
+    args,
+    preflight_warnings,
+    removed_metadata_tags,
+    shifted_creation,
+    output_creation_time,
+  } = plan;
+
+  if let Some(dir) = output_path.parent() {
+    fs::create_dir_all(dir).map_err(|e| format!("Failed to create output folder: {e}"))?;
+  }
+  let chapters_path = match chapters_file {
+    Some((path, contents)) => {
+      fs::write(&path, contents).map_err(|e| format!("Failed to write chapter metadata: {e}"))?;
+      Some(path)
+    }
+    None => None,
+  };
+
+  register_pending_export(&partial_path);
+  let discard_partial = || {
+    let _ = fs::remove_file(&partial_path);
+    unregister_pending_export(&partial_path);
+  };
+
+  // Parse `-progress pipe:1` output and report progress.
+  // FFmpeg writes key=value lines; we parse `out_time_us` for current position.
+  let duration_us = ((out_seconds_f64 - in_seconds_f64) * 1_000_000.0) as i64;
+  let mut last_pct: i32 = -1;
+  let mut lines = media_backend::LineSplitter::default();
+  let result = media_backend::current().run_encode(&ffmpeg_path, &args, &mut |chunk| {
+    lines.push(chunk, |line| {
+      if let Some(val) = line.strip_prefix("out_time_us=") {
+        if let Ok(us) = val.trim().parse::<i64>() {
+          let pct = if duration_us > 0 {
+            ((us as f64 / duration_us as f64) * 100.0).round().min(100.0) as i32
+          } else { 0 };
+          if pct != last_pct {
+            last_pct = pct;
+            on_progress(pct);
+          }
+        }
+      }
+    });
+    true
+  });
+
+  if let Some(path) = &chapters_path {
+    let _ = fs::remove_file(path);
+  }
+  let output = result.inspect_err(|_| discard_partial())?;
+  if let Err(e) = output.into_stdout("ffmpeg failed") {
+    discard_partial();
+    return Err(e);
+  }
+
+  // Verify before the file gets its real name: an unreadable output is discarded, anything else is reported.
+  let verification = match verify_output_file(&ffprobe_path, &ffmpeg_path, &partial_path, options.verify_decode) {
+    Ok(report) => report,
+    Err(e) => {
+      discard_partial();
+      return Err(if mode == "lossless" {
+        format!("Lossless cut produced invalid output ({e}). This usually happens when the cut point is not near a keyframe. Try using 'Exact' mode instead, or adjust the cut times to be closer to a keyframe.")
+      } else {
+        format!("Export produced invalid output: {e}")
+      });
+    }
+  };
+
+  // Another export may have claimed the name while ffmpeg was running; re-apply the collision policy.
+  let output_path = if output_path.exists() {
+    apply_output_collision_policy(output_path, options.collision.as_deref()).inspect_err(|_| discard_partial())?
+  } else {
+    output_path
+  };
+  fs::rename(&partial_path, &output_path).map_err(|e| {
+    discard_partial();
+    format!("Failed to move finished export into place: {e}")
+  })?;
+  unregister_pending_export(&partial_path);
+
+  if options.set_file_mtime {
+    if let Some(unix) = shifted_creation.filter(|t| *t >= 0.0) {
+      let mtime = std::time::UNIX_EPOCH + std::time::Duration::from_secs_f64(unix);
+      let _ = fs::File::options()
+        .write(true)
+        .open(&output_path)
+        .and_then(|f| f.set_modified(mtime));
+    }
+  }
+
+  let requested_duration = out_seconds_f64 - in_seconds_f64;
+  let mut warnings: Vec<TrimWarning> = preflight_warnings
+    .into_iter()
+    .map(|message| TrimWarning {
+      category: TrimWarningCategory::Preflight,
+      message,
+    })
+    .collect();
+  warnings.extend(verification_warnings(
+    &verification,
+    requested_duration,
+    audio_stream_index >= 0,
+    subtitle_stream_index >= 0 && mode != "lossless",
+  ));
+  // Kept for the existing UI, which shows a single duration notice.
+  let duration_warning = warnings
+    .iter()
+    .find(|w| w.category == TrimWarningCategory::Duration)
+    .map(|w| w.message.clone());
+
+  Ok(TrimResult {
+    output_path: output_path.to_string_lossy().to_string(),
+    requested_duration_seconds: requested_duration,
+    actual_duration_seconds: verification.container_duration_seconds,
+    duration_warning,
+    removed_metadata_tags,
+    output_creation_time,
+    verification,
+    warnings,
+  })
+}
+
+// How much of the output tail to scan for each stream's last packet.
+const VERIFY_TAIL_SCAN_SECONDS: f64 = 10.0;
+const AV_START_OFFSET_WARN_SECONDS: f64 = 0.1;
+const DURATION_MISMATCH_WARN_SECONDS: f64 = 0.5;
+const MAX_REPORTED_DECODE_ERRORS: usize = 20;
+
+fn run_ffprobe_json(ffprobe_path: &Path, args: &[&str], path: &Path) -> Result<serde_json::Value, String> {
+  let mut args = media_backend::args(args);
+  args.push(path.to_string_lossy().to_string());
+  let stdout = media_backend::current()
+    .probe(ffprobe_path, &args)?
+    .into_stdout("ffprobe could not read the file")?;
+  serde_json::from_slice(&stdout).map_err(|e| format!("Invalid ffprobe JSON: {e}"))
+}
+
+/// Probe an exported file: streams, first/last PTS per stream, A/V start offset and optionally a full decode.
+/// Errors mean the file is unusable (unreadable, no streams, or zero length).
+fn verify_output_file(ffprobe_path: &Path, ffmpeg_path: &Path, path: &Path, decode: bool) -> Result<VerificationReport, String> {
+  let output_size_bytes = fs::metadata(path).map(|m| m.len()).unwrap_or(0);
+  if output_size_bytes == 0 {
+    return Err("0 bytes".to_string());
+  }
+
+  let json = run_ffprobe_json(
+    ffprobe_path,
+    &[
+      "-v",
+      "error",
+      "-print_format",
+      "json",
+      "-show_entries",
+      "format=duration:stream=index,codec_type,codec_name,start_time",
+    ],
+    path,
+  )?;
+  let num = |v: Option<&serde_json::Value>| -> Option<f64> {
+    v.and_then(|x| x.as_str()).and_then(|s| s.parse::<f64>().ok())
+  };
+
+  let container_duration_seconds = num(json.get("format").and_then(|f| f.get("duration")));
+  let mut streams: Vec<VerifiedStreamInfo> = json
+    .get("streams")
+    .and_then(|s| s.as_array())
+    .map(|arr| {
+      arr
+        .iter()
+        .map(|s| VerifiedStreamInfo {
+          index: s.get("index").and_then(|v| v.as_i64()).unwrap_or(-1) as i32,
+          codec_type: s.get("codec_type").and_then(|v| v.as_str()).unwrap_or("").to_string(),
+          codec_name: s.get("codec_name").and_then(|v| v.as_str()).unwrap_or("").to_string(),
+          first_pts_seconds: num(s.get("start_time")),
+          last_pts_seconds: None,
+          end_seconds: None,
+        })
+        .collect()
+    })
+    .unwrap_or_default();
+  if streams.is_empty() {
+    return Err("no streams".to_string());
+  }
+  if container_duration_seconds.is_some_and(|d| d <= 0.0) {
+    return Err("zero duration".to_string());
+  }
+
+  // Last PTS per stream: only read packets from the tail of the file.
+  let tail_start = (container_duration_seconds.unwrap_or(0.0) - VERIFY_TAIL_SCAN_SECONDS).max(0.0);
+  let read_intervals = format!("{tail_start}%");
+  if let Ok(packets_json) = run_ffprobe_json(
+    ffprobe_path,
+    &[
+      "-v",
+      "error",
+      "-print_format",
+      "json",
+      "-read_intervals",
+      &read_intervals,
+      "-show_entries",
+      "packet=stream_index,pts_time,duration_time",
+    ],
+    path,
+  ) {
+    if let Some(packets) = packets_json.get("packets").and_then(|p| p.as_array()) {
+      for packet in packets {
+        let Some(stream_index) = packet.get("stream_index").and_then(|v| v.as_i64()) else {
+          continue;
+        };
+        let Some(pts) = num(packet.get("pts_time")) else {
+          continue;
+        };
+        let end = pts + num(packet.get("duration_time")).unwrap_or(0.0);
+        if let Some(stream) = streams.iter_mut().find(|s| s.index as i64 == stream_index) {
+          stream.last_pts_seconds = Some(stream.last_pts_seconds.map_or(pts, |last| last.max(pts)));
+          stream.end_seconds = Some(stream.end_seconds.map_or(end, |last| last.max(end)));
+        }
+      }
+    }
+  }
+
+  let first_start = |kind: &str| {
+    streams
+      .iter()
+      .find(|s| s.codec_type == kind)
+      .and_then(|s| s.first_pts_seconds)
+  };
+  let av_start_offset_seconds = match (first_start("video"), first_start("audio")) {
+    (Some(v), Some(a)) => Some(a - v),
+    _ => None,
+  };
+
+  let mut decode_errors = Vec::new();
+  if decode {
+    let output = media_backend::current()
+      .run_encode(
+        ffmpeg_path,
+        &media_backend::args(&["-v", "error", "-nostats", "-i", &path.to_string_lossy(), "-f", "null", "-"]),
+        &mut |_| true,
+      )
+      .map_err(|e| format!("ffmpeg decode check failed: {e}"))?;
+    decode_errors = String::from_utf8_lossy(&output.stderr)
+      .lines()
+      .map(str::trim)
+      .filter(|l| !l.is_empty())
+      .take(MAX_REPORTED_DECODE_ERRORS)
+      .map(str::to_string)
+      .collect();
+    if !output.success && decode_errors.is_empty() {
+      decode_errors.push("ffmpeg could not decode the file".to_string());
+    }
+  }
+
+  Ok(VerificationReport {
+    output_size_bytes,
+    container_duration_seconds,
+    streams,
+    av_start_offset_seconds,
+    decode_checked: decode,
+    decode_errors,
+  })
+}
+
+fn verification_warnings(
+  report: &VerificationReport,
+  requested_duration: f64,
+  expect_audio: bool,
+  expect_subtitles: bool,
+) -> Vec<TrimWarning> {
+  let mut warnings = Vec::new();
+  let mut warn = |category: TrimWarningCategory, message: String| warnings.push(TrimWarning { category, message });
+
+  if let Some(actual) = report.container_duration_seconds {
+    let diff = (actual - requested_duration).abs();
+    if diff > DURATION_MISMATCH_WARN_SECONDS {
+      warn(
+        TrimWarningCategory::Duration,
+        format!(
+          "Output duration is {:.1}s (requested {:.1}s, difference {:.1}s). Lossless cuts can only split on keyframes, so the result may be slightly shorter or longer.",
+          actual, requested_duration, diff
+        ),
+      );
+    }
+  }
+
+  let has = |kind: &str| report.streams.iter().any(|s| s.codec_type == kind);
+  for (kind, expected) in [("video", true), ("audio", expect_audio), ("subtitle", expect_subtitles)] {
+    if expected && !has(kind) {
+      warn(TrimWarningCategory::MissingStream, format!("Output has no {kind} stream."));
+    }
+  }
+
+  if let Some(offset) = report.av_start_offset_seconds {
+    if offset.abs() > AV_START_OFFSET_WARN_SECONDS {
+      warn(
+        TrimWarningCategory::AvSync,
+        format!(
+          "Audio starts {:.3}s {} video; players may show it out of sync.",
+          offset.abs(),
+          if offset > 0.0 { "after" } else { "before" }
+        ),
+      );
+    }
+  }
+
+  for s in &report.streams {
+    if let (Some(first), Some(last)) = (s.first_pts_seconds, s.last_pts_seconds) {
+      if last < first {
+        warn(
+          TrimWarningCategory::Timestamps,
+          format!("Stream {} ({}) has non-increasing timestamps.", s.index, s.codec_type),
+        );
+      }
+    }
+  }
+
+  if !report.decode_errors.is_empty() {
+    warn(
+      TrimWarningCategory::Decode,
+      format!(
+        "Decoding the output reported {} error(s): {}",
+        report.decode_errors.len(),
+        report.decode_errors[0]
+      ),
+    );
+  }
+
+  warnings
+}
+
+#[derive(Clone, Debug)]
+struct LoudnessSample {
+  t: f64,
+  momentary: f64,
+  short_term: f64,
+}
+
+#[derive(Debug, Serialize)]
+struct LoudMomentCandidate {
+  rank: usize,
+  peak_seconds: f64,
+  peak_short_term_lufs: f64,
+  peak_momentary_lufs: f64,
+  // How far the peak sits above the file's median short-term loudness.
+  prominence_lu: f64,
+  start_seconds: f64,
+  end_seconds: f64,
+  // Ready to pass straight to `trim_media`.
+  in_time: String,
+  out_time: String,
+}
+
+#[derive(Debug, Serialize)]
+struct LoudMomentsResult {
+  input_path: String,
+  audio_stream_index: i32,
+  duration_seconds: Option<f64>,
+  integrated_lufs: Option<f64>,
+  median_short_term_lufs: Option<f64>,
+  samples_analyzed: usize,
+  candidates: Vec<LoudMomentCandidate>,
+}
+
+// EBU R128 short-term loudness integrates over the trailing 3 seconds.
+const EBUR128_SHORT_TERM_WINDOW_SECONDS: f64 = 3.0;
+// A loud moment extends while short-term loudness stays within this many LU of its peak.
+const LOUD_MOMENT_REGION_DROP_LU: f64 = 6.0;
+
+/// Parse `ametadata=mode=print` output from the `ebur128=metadata=1` filter.
+/// Accumulates per-frame samples and keeps the latest running integrated loudness.
+fn parse_ebur128_metadata_line(line: &str, samples: &mut Vec<LoudnessSample>, integrated: &mut Option<f64>) {
+  let line = line.trim();
+  if let Some(rest) = line.strip_prefix("frame:") {
+    if let Some(t) = rest
+      .split_whitespace()
+      .find_map(|part| part.strip_prefix("pts_time:"))
+      .and_then(|v| v.parse::<f64>().ok())
+    {
+      samples.push(LoudnessSample {
+        t,
+        momentary: f64::NEG_INFINITY,
+        short_term: f64::NEG_INFINITY,
+      });
+    }
+    return;
+  }
+
+  let Some((key, value)) = line.split_once('=') else {
+    return;
+  };
+  let Ok(value) = value.trim().parse::<f64>() else {
+    return;
+  };
+  match key {
+    "lavfi.r128.M" => {
+      if let Some(last) = samples.last_mut() {
+        last.momentary = value;
+      }
+    }
+    "lavfi.r128.S" => {
+      if let Some(last) = samples.last_mut() {
+        last.short_term = value;
+      }
+    }
+    "lavfi.r128.I" if value.is_finite() => {
+      *integrated = Some(value);
+    }
+    _ => {}
   }
+}
 
-  if mode == "lossless" {
-    // Determine output container from extension
-    let output_ext = output_path
-      .extension()
-      .map(|e| e.to_string_lossy().to_lowercase())
-      .unwrap_or_default();
-
-    cmd.args(["-c", "copy"]);
+fn rank_loud_moments(
+  samples: &[LoudnessSample],
+  duration_seconds: Option<f64>,
+  pre_roll: f64,
+  post_roll: f64,
+  min_gap: f64,
+  max_candidates: usize,
+) -> (Option<f64>, Vec<LoudMomentCandidate>) {
+  let mut finite: Vec<f64> = samples
+    .iter()
+    .map(|s| s.short_term)
+    .filter(|v| v.is_finite())
+    .collect();
+  if finite.is_empty() {
+    return (None, Vec::new());
+  }
+  finite.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+  let median = finite[finite.len() / 2];
+
+  // Peak picking: loudest short-term values first, suppressing anything within `min_gap` of an accepted
+  // peak or whose clip would overlap an accepted one.
+  let mut order: Vec<usize> = (0..samples.len()).filter(|&i| samples[i].short_term.is_finite()).collect();
+  order.sort_by(|&a, &b| {
+    samples[b]
+      .short_term
+      .partial_cmp(&samples[a].short_term)
+      .unwrap_or(std::cmp::Ordering::Equal)
+  });
 
-    // MP4 container needs different timestamp handling than MKV
-    if output_ext == "mp4" || output_ext == "m4v" || output_ext == "mov" {
-      // For MP4: avoid_negative_ts with make_zero and fflags to fix timestamps
-      cmd.args(["-avoid_negative_ts", "make_zero", "-fflags", "+genpts"]);
-    } else {
-      // For MKV and other containers: copyts works better
-      cmd.args(["-copyts", "-avoid_negative_ts", "make_zero"]);
+  let end_limit = duration_seconds.unwrap_or_else(|| samples.last().map(|s| s.t).unwrap_or(0.0));
+  let mut candidates: Vec<LoudMomentCandidate> = Vec::new();
+  for i in order {
+    if candidates.len() >= max_candidates {
+      break;
     }
-
-    if rotation_degrees != 0 {
-      cmd.args(["-metadata:s:v:0", &format!("rotate={rotation_degrees}")]);
+    let peak = &samples[i];
+    if candidates.iter().any(|c| (c.peak_seconds - peak.t).abs() < min_gap) {
+      continue;
     }
-  } else {
-    if let Some(filter) = rotation_filter {
-      cmd.arg("-vf").arg(filter);
-      cmd.args(["-metadata:s:v:0", "rotate=0"]);
-    }
-
-    cmd.args([
-      "-c:v",
-      "libx264",
-      "-crf",
-      "18",
-      "-preset",
-      "veryfast",
-      "-pix_fmt",
-      "yuv420p",
-    ]);
 
-    if audio_stream_index >= 0 {
-      cmd.args(["-c:a", "copy"]);
+    let floor = peak.short_term - LOUD_MOMENT_REGION_DROP_LU;
+    let mut first = i;
+    while first > 0 && samples[first - 1].short_term >= floor {
+      first -= 1;
+    }
+    let mut last = i;
+    while last + 1 < samples.len() && samples[last + 1].short_term >= floor {
+      last += 1;
     }
 
-    if subtitle_stream_index >= 0 {
-      cmd.args(["-c:s", "copy"]);
-      // Subtitle packet durations can extend past the requested cut end
-      // (e.g., a cue that starts before OUT but ends after it). Clamp output
-      // to the shortest mapped stream so Exact mode duration stays precise.
-      cmd.arg("-shortest");
+    // Short-term values describe the preceding 3 seconds, so the loud region starts that much earlier.
+    let region_start = (samples[first].t - EBUR128_SHORT_TERM_WINDOW_SECONDS).max(0.0);
+    let region_end = samples[last].t;
+    let start_seconds = (region_start - pre_roll).max(0.0);
+    let end_seconds = (region_end + post_roll).min(end_limit.max(region_end));
+    if candidates.iter().any(|c| start_seconds < c.end_seconds && c.start_seconds < end_seconds) {
+      continue;
     }
+
+    candidates.push(LoudMomentCandidate {
+      rank: 0,
+      peak_seconds: peak.t,
+      peak_short_term_lufs: peak.short_term,
+      peak_momentary_lufs: peak.momentary,
+      prominence_lu: peak.short_term - median,
+      start_seconds,
+      end_seconds,
+      in_time: timecode::format_clock(start_seconds),
+      out_time: timecode::format_clock(end_seconds),
+    });
   }
 
-  cmd.arg("-y")
-    .arg(&output_path)
-    .stdin(Stdio::null())
-    .stdout(Stdio::piped())
-    .stderr(Stdio::piped());
+  for (i, c) in candidates.iter_mut().enumerate() {
+    c.rank = i + 1;
+  }
+  (Some(median), candidates)
+}
 
-  let mut child = cmd
-    .spawn()
-    .map_err(|e| {
-      if e.kind() == ErrorKind::NotFound {
-        "Failed to run ffmpeg: program not found (set FFmpeg bin folder or add ffmpeg to PATH)".to_string()
-      } else {
-        format!("Failed to run ffmpeg: {e}")
-      }
-    })?;
+// Everything `detect_loud_moments` needs, sent as one `request` object.
+#[derive(Debug, Deserialize)]
+struct DetectLoudMomentsRequest {
+  input_path: String,
+  audio_stream_index: i32,
+  pre_roll_seconds: Option<f64>,
+  post_roll_seconds: Option<f64>,
+  min_gap_seconds: Option<f64>,
+  max_candidates: Option<usize>,
+  ffmpeg_bin_dir: String,
+}
 
-  // Read stdout for `-progress pipe:1` output and emit progress events.
-  // FFmpeg writes key=value lines; we parse `out_time_us` for current position.
-  let duration_us = (duration * 1_000_000.0) as i64;
-  if let Some(stdout) = child.stdout.take() {
-    let reader = std::io::BufReader::new(stdout);
-    let mut last_pct: i32 = -1;
-    use std::io::BufRead;
-    for line in reader.lines() {
-      let line = match line { Ok(l) => l, Err(_) => break };
-      if let Some(val) = line.strip_prefix("out_time_us=") {
-        if let Ok(us) = val.trim().parse::<i64>() {
-          let pct = if duration_us > 0 {
-            ((us as f64 / duration_us as f64) * 100.0).round().min(100.0) as i32
-          } else { 0 };
+#[tauri::command]
+async fn detect_loud_moments(
+  window: tauri::Window,
+  request: DetectLoudMomentsRequest,
+) -> Result<LoudMomentsResult, String> {
+  tauri::async_runtime::spawn_blocking(move || detect_loud_moments_sync(window, request))
+    .await
+    .map_err(|e| format!("detect_loud_moments failed: {e}"))?
+}
+
+fn detect_loud_moments_sync(window: tauri::Window, request: DetectLoudMomentsRequest) -> Result<LoudMomentsResult, String> {
+  let DetectLoudMomentsRequest {
+    input_path,
+    audio_stream_index,
+    pre_roll_seconds,
+    post_roll_seconds,
+    min_gap_seconds,
+    max_candidates,
+    ffmpeg_bin_dir,
+  } = request;
+  let input_path = normalize_input_path_for_cli(&input_path);
+  ensure_input_file_exists(&input_path)?;
+  validate_ffmpeg_bin_dir(&ffmpeg_bin_dir)?;
+
+  let pre_roll = pre_roll_seconds.unwrap_or(5.0).max(0.0);
+  let post_roll = post_roll_seconds.unwrap_or(3.0).max(0.0);
+  let min_gap = min_gap_seconds.unwrap_or(30.0).max(0.0);
+  let max_candidates = max_candidates.unwrap_or(10).max(1);
+  let audio_order = audio_stream_index.max(0);
+
+  let (ffmpeg_path, ffprobe_path, _ffmpeg_bin_dir_used) =
+    resolve_ffmpeg_binaries_with_fallback(&ffmpeg_bin_dir);
+  let duration_seconds = probe_duration_ffprobe(&ffprobe_path, Path::new(&input_path));
+
+  let args = media_backend::args(&[
+    "-v",
+    "error",
+    "-nostats",
+    "-i",
+    &input_path,
+    "-map",
+    &format!("0:a:{audio_order}"),
+    "-vn",
+    "-sn",
+    "-dn",
+    "-af",
+    "ebur128=metadata=1,ametadata=mode=print:file=-",
+    "-f",
+    "null",
+    "-",
+  ]);
+
+  let mut samples: Vec<LoudnessSample> = Vec::new();
+  let mut integrated_lufs: Option<f64> = None;
+  let mut last_pct: i32 = -1;
+  let mut lines = media_backend::LineSplitter::default();
+  let output = media_backend::current().run_encode(&ffmpeg_path, &args, &mut |chunk| {
+    lines.push(chunk, |line| {
+      parse_ebur128_metadata_line(line, &mut samples, &mut integrated_lufs);
+      if let (Some(total), Some(last)) = (duration_seconds, samples.last()) {
+        if total > 0.0 {
+          let pct = ((last.t / total) * 100.0).round().min(100.0) as i32;
           if pct != last_pct {
             last_pct = pct;
-            let _ = window.emit("cut_progress", serde_json::json!({ "percent": pct }));
+            let _ = window.emit("loudness_progress", serde_json::json!({ "percent": pct }));
           }
         }
       }
-    }
-  }
-
-  let status = child.wait().map_err(|e| format!("Failed to wait for ffmpeg: {e}"))?;
-  let stderr_bytes = child.stderr.take().map(|mut s| {
-    let mut buf = Vec::new();
-    let _ = std::io::Read::read_to_end(&mut s, &mut buf);
-    buf
-  }).unwrap_or_default();
-
-  if !status.success() {
-    let stderr = String::from_utf8_lossy(&stderr_bytes).trim().to_string();
-    return Err(if stderr.is_empty() {
-      "ffmpeg failed".to_string()
-    } else {
-      format!("ffmpeg failed: {stderr}")
     });
-  }
-
-  // Validate output file size - a file under 10KB is likely corrupt/empty
-  let output_size = std::fs::metadata(&output_path)
-    .map(|m| m.len())
-    .unwrap_or(0);
-  if output_size < 10_000 {
-    // Clean up the corrupt file
-    let _ = std::fs::remove_file(&output_path);
-    return Err(format!(
-      "Lossless cut produced invalid output ({} bytes). This usually happens when the cut point is not near a keyframe. Try using 'Exact' mode instead, or adjust the cut times to be closer to a keyframe.",
-      output_size
-    ));
-  }
+    true
+  })?;
+  output.into_stdout("ffmpeg loudness analysis failed")?;
 
-  // Post-cut: probe actual output duration and warn if it differs significantly
-  let requested_duration = out_seconds_f64 - in_seconds_f64;
-  let actual_duration = probe_duration_ffprobe(&ffprobe_path, &output_path);
-  let duration_warning = actual_duration.and_then(|actual| {
-    let diff = (actual - requested_duration).abs();
-    if diff > 0.5 {
-      Some(format!(
-        "Output duration is {:.1}s (requested {:.1}s, difference {:.1}s). Lossless cuts can only split on keyframes, so the result may be slightly shorter or longer.",
-        actual, requested_duration, diff
-      ))
-    } else {
-      None
-    }
-  });
+  let (median_short_term_lufs, candidates) =
+    rank_loud_moments(&samples, duration_seconds, pre_roll, post_roll, min_gap, max_candidates);
 
-  Ok(TrimResult {
-    output_path: output_path.to_string_lossy().to_string(),
-    requested_duration_seconds: requested_duration,
-    actual_duration_seconds: actual_duration,
-    duration_warning,
+  Ok(LoudMomentsResult {
+    input_path,
+    audio_stream_index: audio_order,
+    duration_seconds,
+    integrated_lufs,
+    median_short_term_lufs,
+    samples_analyzed: samples.len(),
+    candidates,
   })
 }
 
@@ -2926,23 +5496,9 @@ fn prewarm_ffprobe() {
     if let Some(detected_dir) = auto_detect_ffmpeg_bin_dir() {
       let dir_string = detected_dir.to_string_lossy().to_string();
       let (_, ffprobe, _) = resolve_ffmpeg_binaries_with_fallback(&dir_string);
-      let mut cmd = Command::new(&ffprobe);
-      apply_no_window(&mut cmd);
-      let _ = cmd
-        .arg("-version")
-        .stdin(Stdio::null())
-        .stdout(Stdio::null())
-        .stderr(Stdio::null())
-        .output();
+      let _ = media_backend::current().probe(&ffprobe, &media_backend::args(&["-version"]));
     } else {
-      let mut cmd = Command::new("ffprobe");
-      apply_no_window(&mut cmd);
-      let _ = cmd
-        .arg("-version")
-        .stdin(Stdio::null())
-        .stdout(Stdio::null())
-        .stderr(Stdio::null())
-        .output();
+      let _ = media_backend::current().probe(Path::new("ffprobe"), &media_backend::args(&["-version"]));
     }
   });
 }
@@ -2963,6 +5519,22 @@ fn main() {
         )?;
       }
 
+      if let Ok(dir) = app.path().app_data_dir() {
+        let _ = app_data_dir_slot().set(dir);
+      }
+      if let Ok(dir) = app.path().app_cache_dir() {
+        let _ = app_cache_dir_slot().set(dir);
+      }
+      if let Ok(dir) = app.path().app_config_dir() {
+        let _ = app_config_dir_slot().set(dir);
+      }
+      std::thread::spawn(|| {
+        let removed = cleanup_interrupted_exports();
+        if removed > 0 {
+          eprintln!("[STARTUP] Removed {removed} partial export(s) left by an interrupted run");
+        }
+      });
+
       // Dynamically size the window to 90% of the monitor height
       if let Some(window) = app.get_webview_window("main") {
         if let Some(monitor) = window.current_monitor().unwrap_or(None) {
@@ -2982,12 +5554,31 @@ fn main() {
       check_winget,
       install_ffmpeg_winget,
       lossless_preflight,
+      plan_trim,
+      list_presets,
+      save_preset,
+      delete_preset,
+      build_keyframe_index,
+      snap_to_keyframe,
+      neighbor_frames,
       warm_ffprobe,
       probe_duration,
       probe_tracks,
       probe_subtitles,
       probe_media,
       trim_media,
+      check_output_template,
+      convert_timecode,
+      cache_info,
+      clear_cache,
+      analyze_file,
+      cancel_analysis,
+      thumbnail_strip,
+      waveform_peaks,
+      export_preflight,
+      chapter_range,
+      split_by_chapters,
+      detect_loud_moments,
       add_defender_exclusion,
       check_defender_exclusion_needed,
       get_app_dir,
@@ -2996,3 +5587,773 @@ fn main() {
     .run(tauri::generate_context!())
     .expect("error while running tauri application");
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use media_backend::FakeBackend;
+
+  // An empty input file next to stub ffmpeg.exe/ffprobe.exe, so nothing real is ever resolved or run.
+  struct Fixture {
+    dir: PathBuf,
+    input: String,
+    backend: Arc<FakeBackend>,
+  }
+
+  impl Fixture {
+    fn new(name: &str, input_file: &str) -> Fixture {
+      let dir = env::temp_dir().join(format!("clipwave-test-{name}-{}", std::process::id()));
+      let _ = fs::remove_dir_all(&dir);
+      fs::create_dir_all(&dir).unwrap();
+      for file in [input_file, "ffmpeg.exe", "ffprobe.exe"] {
+        fs::write(dir.join(file), b"").unwrap();
+      }
+      let backend = Arc::new(FakeBackend::default());
+      media_backend::install_for_test(backend.clone());
+      let input = dir.join(input_file).to_string_lossy().to_string();
+      Fixture { dir, input, backend }
+    }
+
+    fn plan(&self, mode: &str, audio: i32, subtitle: i32) -> Result<TrimPlan, String> {
+      self.plan_with(mode, audio, subtitle, &TrimOptions::default())
+    }
+
+    fn plan_with(&self, mode: &str, audio: i32, subtitle: i32, options: &TrimOptions) -> Result<TrimPlan, String> {
+      plan_trim_media(&TrimRequest {
+        input_path: self.input.clone(),
+        in_time: "00:00:01.000".to_string(),
+        out_time: "00:00:03.500".to_string(),
+        mode: mode.to_string(),
+        audio_stream_index: audio,
+        subtitle_stream_index: subtitle,
+        ffmpeg_bin_dir: self.dir.to_string_lossy().to_string(),
+        options: Some(options.clone()),
+      })
+    }
+  }
+
+  impl Drop for Fixture {
+    fn drop(&mut self) {
+      let _ = fs::remove_dir_all(&self.dir);
+    }
+  }
+
+  fn contains_seq(args: &[String], seq: &[&str]) -> bool {
+    args.windows(seq.len()).any(|w| w.iter().zip(seq).all(|(a, b)| a == b))
+  }
+
+  #[test]
+  fn lossless_mp4_uses_stream_copy_with_regenerated_timestamps() {
+    let fx = Fixture::new("lossless-mp4", "clip.mp4");
+    let plan = fx.plan("lossless", 0, -1).unwrap();
+
+    assert!(contains_seq(&plan.args, &["-ss", "1.000000", "-i", &fx.input, "-t", "2.500000"]));
+    assert!(contains_seq(&plan.args, &["-map", "0:a:0"]));
+    assert!(contains_seq(&plan.args, &["-c", "copy"]));
+    assert!(contains_seq(&plan.args, &["-avoid_negative_ts", "make_zero", "-fflags", "+genpts"]));
+    assert!(!plan.args.iter().any(|a| a == "-copyts" || a == "-accurate_seek"));
+    assert_eq!(plan.args.last().map(PathBuf::from), Some(plan.partial_path.clone()));
+  }
+
+  #[test]
+  fn lossless_mkv_keeps_source_timestamps_and_drops_subtitles() {
+    let fx = Fixture::new("lossless-mkv", "clip.mkv");
+    let plan = fx.plan("lossless", -1, 3).unwrap();
+
+    assert!(contains_seq(&plan.args, &["-copyts", "-avoid_negative_ts", "make_zero"]));
+    assert!(!plan.args.iter().any(|a| a == "+genpts"));
+    assert!(plan.args.iter().any(|a| a == "-an"));
+    assert!(!contains_seq(&plan.args, &["-map", "0:3"]));
+  }
+
+  #[test]
+  fn exact_mode_reencodes_with_accurate_seek() {
+    let fx = Fixture::new("exact", "clip.mp4");
+    let plan = fx.plan("exact", 1, 2).unwrap();
+
+    assert!(contains_seq(&plan.args, &["-accurate_seek", "-ss", "1.000000", "-i"]));
+    assert!(contains_seq(&plan.args, &["-c:v", "libx264", "-crf", "18"]));
+    assert!(contains_seq(&plan.args, &["-map", "0:a:1"]));
+    assert!(contains_seq(&plan.args, &["-c:a", "copy"]));
+    assert!(contains_seq(&plan.args, &["-map", "0:2"]));
+    assert!(contains_seq(&plan.args, &["-c:s", "copy", "-shortest"]));
+    assert!(!plan.args.iter().any(|a| a == "-noautorotate" || a == "-vf"));
+  }
+
+  #[test]
+  fn exact_mode_bakes_rotation_into_pixels() {
+    let fx = Fixture::new("rotated", "clip.mp4");
+    fx.backend.on_probe("-show_streams", r#"{"streams":[{"tags":{"rotate":"90"}}]}"#);
+    let plan = fx.plan("exact", 0, -1).unwrap();
+
+    assert!(contains_seq(&plan.args, &["-noautorotate", "-i"]));
+    assert!(contains_seq(&plan.args, &["-vf", "transpose=2", "-metadata:s:v:0", "rotate=0"]));
+  }
+
+  #[test]
+  fn rotation_comes_from_the_probe_cache() {
+    let fx = Fixture::new("rotated-cached", "clip.mp4");
+    let entry = CachedProbeResult { rotation_degrees: Some(90), ..CachedProbeResult::default() };
+    probe_cache().lock().unwrap().insert(probe_cache_key_best_effort(&fx.input), entry);
+    let plan = fx.plan("exact", 0, -1).unwrap();
+
+    assert!(contains_seq(&plan.args, &["-vf", "transpose=2"]));
+    let calls = fx.backend.calls.lock().unwrap();
+    assert!(!calls.iter().any(|(_, args)| args.iter().any(|a| a == "-show_streams")));
+  }
+
+  #[test]
+  fn lossless_rejects_rotated_input() {
+    let fx = Fixture::new("rotated-lossless", "clip.mp4");
+    fx.backend.on_probe("-show_streams", r#"{"streams":[{"tags":{"rotate":"270"}}]}"#);
+    let err = fx.plan("lossless", 0, -1).err().unwrap();
+
+    assert!(err.contains("rotated 270"), "{err}");
+  }
+
+  #[test]
+  fn extra_args_land_in_their_positions() {
+    let fx = Fixture::new("extra-args", "clip.mp4");
+    let options = TrimOptions {
+      pre_input_args: media_backend::args(&["-hwaccel", "auto"]),
+      post_input_args: media_backend::args(&["-sn"]),
+      output_args: media_backend::args(&["-tune", "film", "-movflags", "+faststart"]),
+      ..TrimOptions::default()
+    };
+    let plan = fx.plan_with("exact", 0, -1, &options).unwrap();
+
+    assert!(contains_seq(&plan.args, &["pipe:1", "-hwaccel", "auto", "-accurate_seek", "-ss"]));
+    assert!(contains_seq(&plan.args, &["-t", "2.500000", "-sn", "-map", "0:v:0"]));
+    let tail = &plan.args[plan.args.len() - 6..];
+    assert_eq!(tail[..4], media_backend::args(&["-tune", "film", "-movflags", "+faststart"])[..]);
+    assert_eq!(tail[4], "-y");
+  }
+
+  #[test]
+  fn extra_args_that_break_the_export_are_rejected() {
+    for (args, expected) in [
+      (vec!["-y"], "-y"),
+      (vec!["-tune", "film", "-n"], "-n"),
+      (vec!["-progress", "pipe:2"], "-progress"),
+      (vec!["-i", "other.mp4"], "-i"),
+      (vec!["-c:v", "libx265", "elsewhere.mkv"], "elsewhere.mkv"),
+      (vec!["-crf"], "missing its value"),
+      (vec!["-bitexact", "/tmp/elsewhere.mp4"], "/tmp/elsewhere.mp4"),
+      (vec!["-vstats", "-fix_sub_duration", "out.mkv"], "out.mkv"),
+      (vec!["-map", "0:v:0"], "-map"),
+      (vec!["-filter_complex", "[0:v]null"], "-filter_complex"),
+      (vec!["-made_up_option", "1"], "not a supported ffmpeg option"),
+    ] {
+      let err = validate_extra_ffmpeg_args("output", &media_backend::args(&args)).unwrap_err();
+      assert!(err.contains(expected), "{args:?}: {err}");
+    }
+    assert!(validate_extra_ffmpeg_args("output", &media_backend::args(&["-an", "-vf", "scale=-2:720"])).is_ok());
+  }
+
+  #[test]
+  fn preset_supplies_mode_container_scaling_and_audio() {
+    let fx = Fixture::new("preset", "clip.mkv");
+    let options = TrimOptions {
+      preset: Some("whatsapp 720p".to_string()),
+      ..TrimOptions::default()
+    };
+    let plan = fx.plan_with("lossless", 0, -1, &options).unwrap();
+
+    assert!(plan.output_path.to_string_lossy().ends_with("_whatsapp.mp4"));
+    assert!(contains_seq(&plan.args, &["-vf", "scale=-2:'min(ih,720)'"]));
+    assert!(contains_seq(&plan.args, &["-c:v", "libx264", "-crf", "23", "-preset", "veryfast"]));
+    assert!(contains_seq(&plan.args, &["-c:a", "aac", "-b:a", "128k", "-ac", "2"]));
+    assert!(contains_seq(&plan.args, &["-movflags", "+faststart", "-y"]));
+  }
+
+  #[test]
+  fn target_size_preset_uses_a_capped_bitrate() {
+    let fx = Fixture::new("preset-size", "clip.mp4");
+    let options = TrimOptions {
+      preset: Some("Discord 1080p H.264 under 25MB".to_string()),
+      ..TrimOptions::default()
+    };
+    let plan = fx.plan_with("exact", 0, -1, &options).unwrap();
+
+    // 25 MB * 8000 * 0.95 / 2.5 s - 128 kbit/s audio
+    assert!(contains_seq(&plan.args, &["-b:v", "75872k", "-maxrate", "75872k"]));
+    assert!(!plan.args.iter().any(|a| a == "-crf"));
+    assert!(target_video_bitrate_kbps(25.0, 3600.0, 128.0).is_err());
+  }
+
+  #[test]
+  fn unknown_preset_and_lossless_encoder_settings_are_rejected() {
+    let fx = Fixture::new("preset-errors", "clip.mp4");
+    let unknown = TrimOptions {
+      preset: Some("Nope".to_string()),
+      ..TrimOptions::default()
+    };
+    assert!(fx.plan_with("exact", 0, -1, &unknown).err().unwrap().contains("Unknown export preset"));
+
+    let lossless_scaled = TrimOptions {
+      max_height: Some(720),
+      ..TrimOptions::default()
+    };
+    assert!(fx.plan_with("lossless", 0, -1, &lossless_scaled).err().unwrap().contains("Exact mode"));
+  }
+
+  #[test]
+  fn shell_quoting_handles_awkward_paths() {
+    let args = media_backend::args(&["-i", "/tmp/it's here.mp4", "-map", "0:a:0", ""]);
+    assert_eq!(
+      shell_command_line("ffmpeg", &args, bash_quote),
+      r#"ffmpeg -i '/tmp/it'\''s here.mp4' -map 0:a:0 ''"#
+    );
+    assert_eq!(
+      shell_command_line(r"C:\ffmpeg\ffmpeg.exe", &args, powershell_quote),
+      r#"C:\ffmpeg\ffmpeg.exe -i '/tmp/it''s here.mp4' -map 0:a:0 ''"#
+    );
+  }
+
+  #[test]
+  fn planning_never_runs_ffmpeg() {
+    let fx = Fixture::new("no-encode", "clip.mp4");
+    fx.plan("exact", 0, -1).unwrap();
+
+    assert!(fx.backend.encodes().is_empty());
+  }
+
+  #[test]
+  fn size_estimate_follows_the_resolved_encoder_settings() {
+    let fx = Fixture::new("estimate", "clip.mp4");
+    fx.backend.on_probe(
+      "stream=codec_type,bit_rate,width,height",
+      r#"{"streams":[{"codec_type":"video","width":3840,"height":2160,"avg_frame_rate":"60/1"},
+        {"codec_type":"audio","bit_rate":"320000"}]}"#,
+    );
+    let ffprobe = fx.dir.join("ffprobe.exe");
+    let estimate = |options: &TrimOptions| estimate_output_bytes(&ffprobe, &fx.input, "exact", 60.0, 0, options).unwrap();
+
+    let defaults = estimate(&TrimOptions::default());
+    assert!(defaults > 300_000_000, "{defaults}");
+
+    // The Discord preset's 25 MB target caps the bitrate however large the source is.
+    let discord = presets::find("Discord 1080p H.264 under 25MB").unwrap();
+    let capped = estimate(&apply_preset(&TrimOptions::default(), discord));
+    assert!(capped <= 25_000_000 * 11 / 10, "{capped}");
+
+    // Downscaling and a higher CRF shrink the estimate too.
+    let whatsapp = presets::find("WhatsApp 720p").unwrap();
+    let scaled = estimate(&apply_preset(&TrimOptions::default(), whatsapp));
+    assert!(scaled < defaults / 10, "{scaled}");
+  }
+
+  #[test]
+  fn export_preflight_applies_the_preset() {
+    let fx = Fixture::new("preflight-preset", "clip.mp4");
+    fx.backend.on_probe(
+      "stream=codec_type,bit_rate,width,height",
+      r#"{"streams":[{"codec_type":"video","width":3840,"height":2160,"avg_frame_rate":"60/1"}]}"#,
+    );
+    let options = TrimOptions {
+      preset: Some("Discord 1080p H.264 under 25MB".to_string()),
+      ..TrimOptions::default()
+    };
+    let result = export_preflight(
+      fx.input.clone(),
+      "0".to_string(),
+      "60".to_string(),
+      "lossless".to_string(),
+      0,
+      fx.dir.to_string_lossy().to_string(),
+      Some(options),
+    )
+    .unwrap();
+
+    assert!(result.estimated_output_bytes.is_some_and(|b| b <= 25_000_000 * 11 / 10));
+  }
+
+  #[test]
+  fn presets_reject_templates_and_codecs_the_export_cannot_use() {
+    let base = presets::find("WhatsApp 720p").unwrap();
+    let webm = presets::ExportPreset {
+      name: "Web".to_string(),
+      container: Some("webm".to_string()),
+      ..base.clone()
+    };
+    assert!(presets::validate(&webm).unwrap_err().contains("libx264"));
+
+    let bad_template = presets::ExportPreset {
+      name: "Named".to_string(),
+      name_template: Some("{stem}_{nope}.{ext}".to_string()),
+      ..base.clone()
+    };
+    assert!(presets::validate(&bad_template).unwrap_err().contains("{nope}"));
+
+    let remux = presets::ExportPreset {
+      name: "Remux".to_string(),
+      mode: "lossless".to_string(),
+      container: Some("mkv".to_string()),
+      ..presets::ExportPreset::default()
+    };
+    assert!(presets::validate(&remux).unwrap_err().contains("source container"));
+
+    for preset in presets::builtin_presets() {
+      presets::validate(&preset).unwrap();
+    }
+  }
+
+  #[test]
+  fn partial_files_are_unique_per_export() {
+    let output = Path::new("/videos/clip_cut.mp4");
+    let first = partial_output_path(output);
+    let second = partial_output_path(output);
+
+    assert_ne!(first, second);
+    for partial in [&first, &second] {
+      let name = partial.file_name().unwrap().to_string_lossy();
+      assert!(name.starts_with(".clip_cut") && name.ends_with(".mp4"), "{name}");
+      assert!(name.contains(PARTIAL_EXPORT_MARKER));
+    }
+  }
+
+  #[test]
+  fn iso8601_timestamps_parse_zones_and_fractions() {
+    // 2024-05-01T12:34:56Z
+    let noon = 1_714_566_896.0;
+    for (text, unix, offset) in [
+      ("2024-05-01T12:34:56Z", noon, 0),
+      ("2024-05-01T12:34:56.250000Z", noon + 0.25, 0),
+      ("2024-05-01 12:34:56", noon, 0),
+      ("2024-05-01T14:34:56+0200", noon, 120),
+      ("2024-05-01T14:34:56.5+02:00", noon + 0.5, 120),
+      ("2024-05-01T07:04:56-05:30", noon, -330),
+      ("2024-05-01T21:34:56+09", noon, 540),
+      ("2024-05-01T12:34Z", noon - 56.0, 0),
+    ] {
+      let (parsed, parsed_offset) = parse_iso8601_timestamp(text).unwrap_or_else(|| panic!("{text}"));
+      assert!((parsed - unix).abs() < 1e-6, "{text}: {parsed}");
+      assert_eq!(parsed_offset, offset, "{text}");
+    }
+    for bad in ["", "2024-05-01", "2024-13-01T00:00:00Z", "2024-05-01T12:34:56+2", "yesterday"] {
+      assert!(parse_iso8601_timestamp(bad).is_none(), "{bad}");
+    }
+    assert_eq!(format_iso8601_timestamp(noon + 0.25, 120), "2024-05-01T14:34:56.250000+0200");
+    assert_eq!(format_iso8601_timestamp(noon, -330), "2024-05-01T07:04:56.000000-0530");
+  }
+
+  const VERIFIED_CLIP_JSON: &str = r#"{"format":{"duration":"2.500000"},"streams":[
+    {"index":0,"codec_type":"video","codec_name":"h264","start_time":"0.000000"},
+    {"index":1,"codec_type":"audio","codec_name":"aac","start_time":"0.000000"}]}"#;
+
+  #[test]
+  fn trim_runs_the_plan_and_moves_the_verified_file_into_place() {
+    let fx = Fixture::new("run", "clip.mp4");
+    fx.backend
+      .on_probe("format=duration:stream=index,codec_type,codec_name,start_time", VERIFIED_CLIP_JSON)
+      .on_encode_stdout(vec![b"out_time_us=1250".to_vec(), b"000\nprogress=continue\nout_time_us=2500000\n".to_vec()]);
+    let plan = fx.plan("lossless", 0, -1).unwrap();
+    let (partial, output) = (plan.partial_path.clone(), plan.output_path.clone());
+
+    let mut percents = Vec::new();
+    let result = run_trim_plan(plan, &mut |p| percents.push(p)).unwrap();
+
+    assert_eq!(percents, [50, 100]);
+    assert_eq!(PathBuf::from(&result.output_path), output);
+    assert_eq!(fs::read(&output).unwrap(), b"fake media");
+    assert!(!partial.exists());
+    assert_eq!(result.verification.streams.len(), 2);
+    // Only the fake's missing size estimate is reported; the clip itself verified cleanly.
+    assert!(result.warnings.iter().all(|w| w.category == TrimWarningCategory::Preflight));
+  }
+
+  #[test]
+  fn trim_discards_an_empty_output() {
+    let fx = Fixture::new("run-empty", "clip.mp4");
+    fx.backend
+      .on_probe("format=duration:stream=index,codec_type,codec_name,start_time", VERIFIED_CLIP_JSON)
+      .on_encode_output(b"");
+    let plan = fx.plan("exact", 0, -1).unwrap();
+    let (partial, output) = (plan.partial_path.clone(), plan.output_path.clone());
+
+    let error = run_trim_plan(plan, &mut |_| {}).err().unwrap();
+
+    assert!(error.contains("0 bytes"), "{error}");
+    assert!(!partial.exists());
+    assert!(!output.exists());
+    assert_eq!(fx.backend.encodes().len(), 1);
+  }
+
+  #[test]
+  fn chapter_metadata_is_written_by_the_run_not_the_plan() {
+    let fx = Fixture::new("run-chapters", "clip.mp4");
+    fx.backend
+      .on_probe("format=duration:stream=index,codec_type,codec_name,start_time", VERIFIED_CLIP_JSON)
+      .on_probe(
+        "chapter=id,start_time,end_time:chapter_tags=title",
+        r#"{"chapters":[
+          {"id":0,"start_time":"0.000000","end_time":"2.000000","tags":{"title":"Intro"}},
+          {"id":1,"start_time":"2.000000","end_time":"9.000000","tags":{"title":"Main"}}]}"#,
+      );
+    let plan = fx.plan("exact", 0, -1).unwrap();
+    let (path, contents) = plan.chapters_file.clone().expect("chapters for the clip");
+    assert!(contents.contains("title=Main"), "{contents}");
+    assert!(!path.exists());
+
+    run_trim_plan(plan, &mut |_| {}).unwrap();
+    let args = &fx.backend.encodes()[0];
+    assert!(contains_seq(args, &["-f", "ffmetadata", "-i", &path.to_string_lossy()]));
+    assert!(!path.exists());
+  }
+
+  fn marker(time: &str, title: &str) -> ChapterMarker {
+    ChapterMarker { time: time.to_string(), title: title.to_string() }
+  }
+
+  #[test]
+  fn chapter_markers_become_clip_chapters() {
+    let markers = [
+      marker("00:00:30.000", " Outro "),
+      marker("00:00:02.000", "Cold open"),
+      marker("00:00:05.000", "Before IN"),
+      marker("00:00:12.500", "Main"),
+      marker("00:00:40.000", "After OUT"),
+    ];
+    let clip = chapter_markers_to_clip_chapters(&markers, 10.0, 40.0, None).unwrap();
+    // Markers before IN collapse into one chapter at 0 (the last wins); OUT is exclusive.
+    assert_eq!(chapter_spans(&clip), [(0, "Before IN", 0.0, 2.5), (1, "Main", 2.5, 20.0), (2, "Outro", 20.0, 30.0)]);
+
+    let clip = chapter_markers_to_clip_chapters(&[marker("00:00:10.000", "At IN")], 10.0, 12.0, None).unwrap();
+    assert_eq!(chapter_spans(&clip), [(0, "At IN", 0.0, 2.0)]);
+    assert!(chapter_markers_to_clip_chapters(&[marker("soon", "")], 0.0, 1.0, None).is_err());
+  }
+
+  #[test]
+  fn chapter_markers_replace_source_chapters_in_the_plan() {
+    let fx = Fixture::new("marker-chapters", "clip.mp4");
+    fx.backend.on_probe(
+      "chapter=id,start_time,end_time:chapter_tags=title",
+      r#"{"chapters":[{"id":0,"start_time":"0.000000","end_time":"9.000000","tags":{"title":"Source"}}]}"#,
+    );
+    let options = TrimOptions {
+      chapter_markers: Some(vec![marker("00:00:00.500", "Start"), marker("00:00:02.000", "Hit")]),
+      ..TrimOptions::default()
+    };
+    let plan = fx.plan_with("lossless", 0, -1, &options).unwrap();
+    let (_, contents) = plan.chapters_file.clone().expect("chapters from the markers");
+    assert_eq!(
+      contents,
+      ";FFMETADATA1\n\
+       [CHAPTER]\nTIMEBASE=1/1000\nSTART=0\nEND=1000\ntitle=Start\n\
+       [CHAPTER]\nTIMEBASE=1/1000\nSTART=1000\nEND=2500\ntitle=Hit\n"
+    );
+  }
+
+  #[test]
+  fn probe_commands_share_one_ffprobe_run() {
+    // The empty input is no MP4 the native probe can read, so everything comes from ffprobe.
+    let fx = Fixture::new("probe-service", "clip.mp4");
+    fx.backend.on_probe(
+      "format=duration,format_name",
+      r#"{"format":{"duration":"12.500000"},"streams":[
+        {"index":0,"codec_type":"video","codec_name":"h264","r_frame_rate":"25/1","avg_frame_rate":"25/1"},
+        {"index":1,"codec_type":"audio","codec_name":"aac","channels":2,"tags":{"language":"eng"}},
+        {"index":2,"codec_type":"subtitle","codec_name":"subrip"}]}"#,
+    );
+    let dir = fx.dir.to_string_lossy().to_string();
+
+    let duration = probe_duration(fx.input.clone(), dir.clone()).unwrap();
+    let tracks = probe_tracks(fx.input.clone(), dir.clone()).unwrap();
+    let subtitles = probe_subtitles(fx.input.clone(), dir.clone()).unwrap();
+    let media = probe_media(fx.input.clone(), dir).unwrap();
+
+    assert_eq!(duration.duration_seconds, Some(12.5));
+    assert!(!duration.timing_ms.cache_hit);
+    assert_eq!(tracks.audio_streams.len(), 1);
+    assert!(tracks.timing_ms.cache_hit && subtitles.timing_ms.cache_hit && media.timing_ms.cache_hit);
+    assert_eq!(subtitles.subtitle_streams[0].index, 2);
+    assert_eq!(media.video_streams.len(), 1);
+    let calls = fx.backend.calls.lock().unwrap();
+    assert_eq!(calls.iter().filter(|(_, args)| args.join(" ").contains("format_name")).count(), 1);
+  }
+
+  #[test]
+  fn a_probe_leader_that_panics_still_releases_its_waiters() {
+    let key = "clipwave-test-panicking-probe".to_string();
+    let slot = Arc::new(InflightProbe {
+      outcome: Mutex::new(None),
+      done: Condvar::new(),
+    });
+    inflight_probes().lock().unwrap().insert(key.clone(), slot.clone());
+
+    let (leader_key, leader_slot) = (key.clone(), slot.clone());
+    let leader = std::thread::spawn(move || {
+      let _leader = InflightLeader {
+        cache_key: &leader_key,
+        slot: &leader_slot,
+      };
+      panic!("ffprobe parsing blew up");
+    });
+    let mut outcome = slot.outcome.lock().unwrap();
+    while outcome.is_none() {
+      outcome = slot.done.wait(outcome).unwrap();
+    }
+
+    assert!(matches!(outcome.as_ref(), Some(Err(_))));
+    assert!(leader.join().is_err());
+    assert!(!inflight_probes().lock().unwrap().contains_key(&key));
+  }
+
+  #[test]
+  fn snapping_builds_the_keyframe_index_once() {
+    let fx = Fixture::new("snap-index", "clip.mp4");
+    fx.backend.on_keyframes(&[4.0, 0.0, 2.0]);
+    let dir = fx.dir.to_string_lossy().to_string();
+    let snap = |time: &str, direction: &str| {
+      snap_to_keyframe_sync(fx.input.clone(), time.to_string(), direction.to_string(), dir.clone()).unwrap()
+    };
+
+    assert_eq!(snap("00:00:02.900", "previous").seconds, 2.0);
+    assert_eq!(snap("00:00:02.900", "next").seconds, 4.0);
+    assert_eq!(snap("00:00:01.200", "nearest").seconds, 2.0);
+    let calls = fx.backend.calls.lock().unwrap();
+    assert_eq!(calls.iter().filter(|(kind, _)| kind == "keyframes").count(), 1);
+    assert!(!calls.iter().any(|(_, args)| args.iter().any(|a| a == "-read_intervals")));
+  }
+
+  // One short-term loudness sample per second; momentary tracks short-term.
+  fn loudness(short_term: &[f64]) -> Vec<LoudnessSample> {
+    short_term
+      .iter()
+      .enumerate()
+      .map(|(i, v)| LoudnessSample {
+        t: i as f64,
+        momentary: *v,
+        short_term: *v,
+      })
+      .collect()
+  }
+
+  #[test]
+  fn ebur128_metadata_lines_build_samples() {
+    let mut samples = Vec::new();
+    let mut integrated = None;
+    for line in [
+      "frame:0    pts:0       pts_time:0.1",
+      "lavfi.r128.M=-120.691",
+      "lavfi.r128.S=-inf",
+      "lavfi.r128.I=-70.000",
+      "frame:1    pts:4800    pts_time:0.2",
+      "lavfi.r128.M=-23.5",
+      "lavfi.r128.S=-24.25",
+      "lavfi.r128.I=-22.1",
+      "lavfi.r128.LRA=3.2",
+      "garbage",
+    ] {
+      parse_ebur128_metadata_line(line, &mut samples, &mut integrated);
+    }
+    assert_eq!(samples.len(), 2);
+    assert_eq!(samples[0].t, 0.1);
+    assert_eq!(samples[0].short_term, f64::NEG_INFINITY);
+    assert_eq!((samples[1].t, samples[1].momentary, samples[1].short_term), (0.2, -23.5, -24.25));
+    assert_eq!(integrated, Some(-22.1));
+
+    // Values before the first frame line have no sample to land on.
+    let mut samples = Vec::new();
+    parse_ebur128_metadata_line("lavfi.r128.S=-20", &mut samples, &mut integrated);
+    assert!(samples.is_empty());
+  }
+
+  #[test]
+  fn loud_moments_rank_peaks_and_never_overlap() {
+    let mut levels = vec![-40.0; 60];
+    levels[10] = -10.0;
+    levels[11] = -12.0;
+    levels[20] = -14.0;
+    levels[50] = -18.0;
+    let samples = loudness(&levels);
+
+    // A 5 s gap keeps the peak at 20 s, but its clip would overlap the loudest one's.
+    let (median, moments) = rank_loud_moments(&samples, Some(60.0), 5.0, 8.0, 5.0, 10);
+    assert_eq!(median, Some(-40.0));
+    let peaks: Vec<f64> = moments.iter().map(|m| m.peak_seconds).collect();
+    assert_eq!(peaks, [10.0, 50.0]);
+    assert_eq!(moments[0].rank, 1);
+    // The region runs from 3 s before its first loud sample; the rolls are added on both sides.
+    assert_eq!((moments[0].start_seconds, moments[0].end_seconds), (2.0, 19.0));
+    assert_eq!(moments[0].prominence_lu, 30.0);
+    // Clamped to the file.
+    assert_eq!((moments[1].start_seconds, moments[1].end_seconds), (42.0, 58.0));
+    assert_eq!(moments[1].out_time, "00:00:58.000");
+
+    // Without the post-roll the two clips are apart and both are kept.
+    let (_, moments) = rank_loud_moments(&samples, Some(60.0), 5.0, 0.0, 5.0, 10);
+    let peaks: Vec<f64> = moments.iter().map(|m| m.peak_seconds).collect();
+    assert_eq!(peaks, [10.0, 20.0, 50.0]);
+    let (_, moments) = rank_loud_moments(&samples, Some(60.0), 5.0, 0.0, 5.0, 2);
+    assert_eq!(moments.len(), 2);
+
+    assert_eq!(rank_loud_moments(&loudness(&[f64::NEG_INFINITY; 3]), None, 0.0, 0.0, 0.0, 5).0, None);
+  }
+
+  #[test]
+  fn collision_policies_resolve_existing_outputs() {
+    let fx = Fixture::new("collisions", "clip.mp4");
+    let base = fx.dir.join("out.mp4");
+    assert_eq!(apply_output_collision_policy(base.clone(), Some("number")).unwrap(), base);
+    fs::write(&base, b"").unwrap();
+
+    assert_eq!(apply_output_collision_policy(base.clone(), None).unwrap(), base);
+    assert_eq!(apply_output_collision_policy(base.clone(), Some("overwrite")).unwrap(), base);
+    assert!(apply_output_collision_policy(base.clone(), Some("fail")).is_err());
+    assert!(apply_output_collision_policy(base.clone(), Some("rename")).is_err());
+
+    fs::write(fx.dir.join("out (1).mp4"), b"").unwrap();
+    assert_eq!(apply_output_collision_policy(base.clone(), Some("number")).unwrap(), fx.dir.join("out (2).mp4"));
+    for i in 2..=999 {
+      fs::write(fx.dir.join(format!("out ({i}).mp4")), b"").unwrap();
+    }
+    let error = apply_output_collision_policy(base, Some("number")).unwrap_err();
+    assert!(error.contains("(999)"), "{error}");
+  }
+
+  fn chapter(index: i32, title: &str, start_seconds: f64, end_seconds: f64) -> ChapterInfo {
+    ChapterInfo { index, title: title.to_string(), start_seconds, end_seconds }
+  }
+
+  fn chapter_spans(chapters: &[ChapterInfo]) -> Vec<(i32, &str, f64, f64)> {
+    chapters.iter().map(|c| (c.index, c.title.as_str(), c.start_seconds, c.end_seconds)).collect()
+  }
+
+  #[test]
+  fn chapters_straddling_in_and_out_are_clamped_to_the_clip() {
+    let source = [
+      chapter(0, "Intro", 0.0, 10.0),
+      chapter(1, "Act 1", 10.0, 20.0),
+      chapter(2, "Act 2", 20.0, 30.0),
+      chapter(3, "Credits", 30.0, 40.0),
+    ];
+    let clip = retime_chapters_for_range(&source, 5.0, 25.0);
+    assert_eq!(chapter_spans(&clip), [(0, "Intro", 0.0, 5.0), (1, "Act 1", 5.0, 15.0), (2, "Act 2", 15.0, 20.0)]);
+
+    // A chapter that only touches IN or OUT is not part of the clip.
+    let clip = retime_chapters_for_range(&source, 10.0, 20.0);
+    assert_eq!(chapter_spans(&clip), [(0, "Act 1", 0.0, 10.0)]);
+    assert!(retime_chapters_for_range(&source, 40.0, 50.0).is_empty());
+  }
+
+  #[test]
+  fn ffmetadata_chapters_escape_special_characters() {
+    let text = render_ffmetadata_chapters(&[
+      chapter(0, "a=b; c#d \\ e", 0.0, 1.5),
+      chapter(1, "", 1.5, 2.0),
+    ]);
+    assert_eq!(
+      text,
+      ";FFMETADATA1\n\
+       [CHAPTER]\nTIMEBASE=1/1000\nSTART=0\nEND=1500\ntitle=a\\=b\\; c\\#d \\\\ e\n\
+       [CHAPTER]\nTIMEBASE=1/1000\nSTART=1500\nEND=2000\n"
+    );
+    assert_eq!(escape_ffmetadata_value("line\nbreak"), "line\\\nbreak");
+  }
+
+  fn strip_metadata(strip: &str) -> MetadataOptions {
+    MetadataOptions {
+      title: None,
+      comment: None,
+      artist: None,
+      date: None,
+      strip: strip.to_string(),
+      streams: Vec::new(),
+    }
+  }
+
+  #[test]
+  fn metadata_stripping_targets_only_mapped_streams() {
+    let stream = |kind, order, index, keys: &[&str]| SourceStreamTags {
+      kind,
+      order,
+      index,
+      keys: keys.iter().map(|k| k.to_string()).collect(),
+    };
+    let source = SourceMetadataTags {
+      format: vec!["title".to_string(), "location".to_string()],
+      streams: vec![
+        stream('v', 0, 0, &["handler_name", "location"]),
+        stream('a', 0, 1, &["location"]),
+        stream('a', 1, 2, &["language", "com.android.manufacturer"]),
+        stream('s', 0, 3, &["location"]),
+      ],
+    };
+    // Video plus the second audio track, which becomes the output's a:0.
+    let mapped = [('v', 0), ('a', 1)];
+
+    let (args, removed) = build_metadata_args(&strip_metadata("privacy"), &source, &mapped).unwrap();
+    assert_eq!(
+      args,
+      [
+        "-metadata",
+        "location=",
+        "-metadata:s:v:0",
+        "location=",
+        "-metadata:s:a:0",
+        "com.android.manufacturer=",
+      ]
+    );
+    assert_eq!(removed, ["location", "v:0:location", "a:1:com.android.manufacturer"]);
+
+    let (args, removed) = build_metadata_args(&strip_metadata("all"), &source, &mapped).unwrap();
+    assert_eq!(args, ["-map_metadata", "-1"]);
+    assert_eq!(
+      removed,
+      ["title", "location", "v:0:handler_name", "v:0:location", "a:1:language", "a:1:com.android.manufacturer"]
+    );
+
+    let (args, removed) = build_metadata_args(&strip_metadata("none"), &source, &mapped).unwrap();
+    assert!(args.is_empty() && removed.is_empty());
+    assert!(build_metadata_args(&strip_metadata("gps"), &source, &mapped).is_err());
+  }
+
+  #[test]
+  fn privacy_strip_follows_the_subtitle_the_plan_maps() {
+    let fx = Fixture::new("privacy-strip", "clip.mkv");
+    fx.backend.on_probe(
+      "format_tags:stream=index,codec_type:stream_tags",
+      r#"{"format":{"tags":{}},"streams":[
+        {"index":0,"codec_type":"video","tags":{}},
+        {"index":1,"codec_type":"subtitle","tags":{"location":"+1+2/"}},
+        {"index":2,"codec_type":"audio","tags":{}},
+        {"index":3,"codec_type":"subtitle","tags":{"location":"+3+4/"}}]}"#,
+    );
+    let options = TrimOptions { metadata: Some(strip_metadata("privacy")), ..TrimOptions::default() };
+
+    let plan = fx.plan_with("exact", 0, 3, &options).unwrap();
+    assert!(contains_seq(&plan.args, &["-metadata:s:s:0", "location="]));
+    assert_eq!(plan.removed_metadata_tags, ["s:1:location"]);
+
+    // Lossless drops subtitles, so there is nothing to strip.
+    let plan = fx.plan_with("lossless", 0, 3, &options).unwrap();
+    assert!(!plan.args.iter().any(|a| a.starts_with("-metadata:s")));
+    assert!(plan.removed_metadata_tags.is_empty());
+  }
+
+  #[test]
+  fn output_never_replaces_the_source_file() {
+    let fx = Fixture::new("same-as-input", "clip.mp4");
+    for collision in ["number", "overwrite", "fail"] {
+      let options = TrimOptions {
+        name_template: Some("{stem}.{ext}".to_string()),
+        collision: Some(collision.to_string()),
+        ..TrimOptions::default()
+      };
+      let error = fx.plan_with("lossless", 0, -1, &options).err().unwrap();
+      assert!(error.contains("replace the source file"), "{error}");
+    }
+  }
+
+  #[test]
+  fn planning_leaves_a_missing_output_folder_alone() {
+    let fx = Fixture::new("deferred-dir", "clip.mp4");
+    let out_dir = fx.dir.join("exports").join("today");
+    let options = TrimOptions { output_dir: Some(out_dir.to_string_lossy().to_string()), ..TrimOptions::default() };
+    let plan = fx.plan_with("lossless", 0, -1, &options).unwrap();
+
+    assert!(plan.output_path.starts_with(&out_dir));
+    assert!(!out_dir.exists());
+  }
+}
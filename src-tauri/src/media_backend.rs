@@ -0,0 +1,494 @@
+// Every ffmpeg/ffprobe launch goes through `MediaBackend`. `FfmpegBackend` runs the real binaries;
+// tests install `FakeBackend`, which answers from a script and records each invocation, so probing,
+// trim argument building and error paths can be exercised without ffmpeg installed.
+
+use std::io::{BufRead, BufReader, ErrorKind, Read};
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::sync::{Arc, OnceLock};
+use std::time::Instant;
+
+/// Result of one ffmpeg/ffprobe run. For `run_encode`, `stdout` is empty (it went to the callback).
+#[derive(Clone, Debug, Default)]
+pub struct ProcessOutput {
+  pub success: bool,
+  pub exit_code: Option<i32>,
+  pub stdout: Vec<u8>,
+  pub stderr: Vec<u8>,
+  pub timing: ProcessTiming,
+}
+
+/// Milliseconds, measured from just before the spawn.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ProcessTiming {
+  pub spawn_ms: f64,
+  pub first_stdout_byte_ms: Option<f64>,
+  pub first_stderr_byte_ms: Option<f64>,
+  pub wait_ms: f64,
+  pub total_ms: f64,
+}
+
+impl ProcessOutput {
+  /// stdout on success; otherwise `what failed: <stderr>` as the error.
+  pub fn into_stdout(self, what: &str) -> Result<Vec<u8>, String> {
+    if self.success {
+      return Ok(self.stdout);
+    }
+    let stderr = String::from_utf8_lossy(&self.stderr).trim().to_string();
+    Err(if stderr.is_empty() {
+      what.to_string()
+    } else {
+      format!("{what}: {stderr}")
+    })
+  }
+}
+
+pub trait MediaBackend: Send + Sync {
+  /// Run ffprobe to completion.
+  fn probe(&self, ffprobe_path: &Path, args: &[String]) -> Result<ProcessOutput, String>;
+
+  /// Every keyframe PTS of the first video stream, in decode order. `on_progress` gets 0-99 and
+  /// returns false to abort.
+  fn keyframes(
+    &self,
+    ffprobe_path: &Path,
+    input_path: &str,
+    duration_seconds: Option<f64>,
+    on_progress: &mut dyn FnMut(f64) -> bool,
+  ) -> Result<Vec<f64>, String>;
+
+  /// One frame at `seconds`, scaled to `width` pixels wide, as JPEG.
+  fn grab_frame(&self, ffmpeg_path: &Path, input_path: &str, seconds: f64, width: u32) -> Result<Vec<u8>, String>;
+
+  /// Run ffmpeg, handing stdout to `on_stdout` as it arrives; returning false kills the process.
+  fn run_encode(
+    &self,
+    ffmpeg_path: &Path,
+    args: &[String],
+    on_stdout: &mut dyn FnMut(&[u8]) -> bool,
+  ) -> Result<ProcessOutput, String>;
+}
+
+/// The backend commands should use.
+pub fn current() -> Arc<dyn MediaBackend> {
+  #[cfg(test)]
+  if let Some(backend) = TEST_BACKEND.with(|b| b.borrow().clone()) {
+    return backend;
+  }
+  static REAL: OnceLock<Arc<dyn MediaBackend>> = OnceLock::new();
+  REAL.get_or_init(|| Arc::new(FfmpegBackend)).clone()
+}
+
+/// Owned argument list from string slices.
+pub fn args(parts: &[&str]) -> Vec<String> {
+  parts.iter().map(|p| p.to_string()).collect()
+}
+
+fn program_name(path: &Path) -> String {
+  path
+    .file_stem()
+    .map(|s| s.to_string_lossy().to_lowercase())
+    .filter(|s| s == "ffmpeg" || s == "ffprobe")
+    .unwrap_or_else(|| "ffmpeg".to_string())
+}
+
+fn spawn_error(program: &Path, e: std::io::Error) -> String {
+  let name = program_name(program);
+  if e.kind() == ErrorKind::NotFound {
+    format!("Failed to run {name}: program not found (set FFmpeg bin folder or add {name} to PATH)")
+  } else {
+    format!("Failed to run {name}: {e}")
+  }
+}
+
+fn command(program: &Path, args: &[String]) -> Command {
+  let mut cmd = Command::new(program);
+  crate::apply_no_window(&mut cmd);
+  cmd.args(args);
+  if let Some(dir) = crate::stable_working_dir() {
+    cmd.current_dir(dir);
+  }
+  cmd.stdin(Stdio::null()).stdout(Stdio::piped()).stderr(Stdio::piped());
+  cmd
+}
+
+// Read a pipe to the end on its own thread, noting when the first byte arrived.
+fn drain<R: Read + Send + 'static>(
+  pipe: Option<R>,
+  started: Instant,
+) -> std::thread::JoinHandle<(Option<f64>, Vec<u8>)> {
+  std::thread::spawn(move || {
+    let mut buf = Vec::new();
+    let mut first_ms = None;
+    let mut tmp = [0_u8; 8192];
+    if let Some(mut pipe) = pipe {
+      loop {
+        match pipe.read(&mut tmp) {
+          Ok(0) => break,
+          Ok(n) => {
+            first_ms.get_or_insert_with(|| started.elapsed().as_secs_f64() * 1000.0);
+            buf.extend_from_slice(&tmp[..n]);
+          }
+          Err(e) if e.kind() == ErrorKind::Interrupted => {}
+          Err(_) => break,
+        }
+      }
+    }
+    (first_ms, buf)
+  })
+}
+
+pub struct FfmpegBackend;
+
+impl MediaBackend for FfmpegBackend {
+  fn probe(&self, ffprobe_path: &Path, args: &[String]) -> Result<ProcessOutput, String> {
+    let started = Instant::now();
+    let mut child = command(ffprobe_path, args)
+      .spawn()
+      .map_err(|e| spawn_error(ffprobe_path, e))?;
+    let spawn_ms = started.elapsed().as_secs_f64() * 1000.0;
+    // Both pipes drain concurrently so a full stderr can never stall stdout.
+    let stdout = drain(child.stdout.take(), started);
+    let stderr = drain(child.stderr.take(), started);
+
+    let start_wait = Instant::now();
+    let status = child
+      .wait()
+      .map_err(|e| format!("Failed waiting for {}: {e}", program_name(ffprobe_path)))?;
+    let wait_ms = start_wait.elapsed().as_secs_f64() * 1000.0;
+    let (first_stdout_byte_ms, stdout) = stdout.join().unwrap_or_default();
+    let (first_stderr_byte_ms, stderr) = stderr.join().unwrap_or_default();
+
+    Ok(ProcessOutput {
+      success: status.success(),
+      exit_code: status.code(),
+      stdout,
+      stderr,
+      timing: ProcessTiming {
+        spawn_ms,
+        first_stdout_byte_ms,
+        first_stderr_byte_ms,
+        wait_ms,
+        total_ms: started.elapsed().as_secs_f64() * 1000.0,
+      },
+    })
+  }
+
+  // Packet headers only (nothing is decoded), consumed line by line so multi-hour files use
+  // constant memory. Packet keyframe flags are what `-ss` with stream copy seeks to.
+  fn keyframes(
+    &self,
+    ffprobe_path: &Path,
+    input_path: &str,
+    duration_seconds: Option<f64>,
+    on_progress: &mut dyn FnMut(f64) -> bool,
+  ) -> Result<Vec<f64>, String> {
+    let scan_args = args(&[
+      "-v",
+      "error",
+      "-select_streams",
+      "v:0",
+      "-show_entries",
+      "packet=pts_time,dts_time,flags",
+      "-of",
+      "compact=p=0",
+      input_path,
+    ]);
+    let mut child = command(ffprobe_path, &scan_args)
+      .spawn()
+      .map_err(|e| spawn_error(ffprobe_path, e))?;
+    let stdout = child
+      .stdout
+      .take()
+      .ok_or_else(|| "Failed to capture ffprobe stdout".to_string())?;
+    let stderr = drain(child.stderr.take(), Instant::now());
+
+    let duration = duration_seconds.filter(|d| d.is_finite() && *d > 0.0);
+    let mut keyframes = Vec::new();
+    let mut last_percent = -1.0;
+    for line in BufReader::new(stdout).lines() {
+      let Ok(line) = line else {
+        break;
+      };
+      let (mut pts, mut dts, mut is_key) = (None, None, false);
+      for field in line.split('|') {
+        match field.split_once('=') {
+          Some(("pts_time", v)) => pts = v.parse::<f64>().ok(),
+          Some(("dts_time", v)) => dts = v.parse::<f64>().ok(),
+          Some(("flags", v)) => is_key = v.contains('K'),
+          _ => {}
+        }
+      }
+      let Some(t) = pts.or(dts) else {
+        continue;
+      };
+      if is_key {
+        keyframes.push(t);
+      }
+      if let Some(d) = duration {
+        let percent = (t / d * 100.0).clamp(0.0, 99.0).floor();
+        if percent > last_percent {
+          last_percent = percent;
+          if !on_progress(percent) {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Err("Keyframe indexing cancelled".to_string());
+          }
+        }
+      }
+    }
+
+    let status = child.wait().map_err(|e| format!("Failed to wait for ffprobe: {e}"))?;
+    let (_, stderr) = stderr.join().unwrap_or_default();
+    if !status.success() {
+      let stderr = String::from_utf8_lossy(&stderr).trim().to_string();
+      return Err(if stderr.is_empty() {
+        "ffprobe failed while indexing keyframes".to_string()
+      } else {
+        format!("ffprobe failed while indexing keyframes: {stderr}")
+      });
+    }
+    Ok(keyframes)
+  }
+
+  // Input-side seek, so only the GOP around `seconds` is decoded.
+  fn grab_frame(&self, ffmpeg_path: &Path, input_path: &str, seconds: f64, width: u32) -> Result<Vec<u8>, String> {
+    let grab_args = args(&[
+      "-v",
+      "error",
+      "-nostdin",
+      "-ss",
+      &format!("{seconds:.3}"),
+      "-i",
+      input_path,
+      "-map",
+      "0:v:0",
+      "-frames:v",
+      "1",
+      "-an",
+      "-sn",
+      "-dn",
+      "-vf",
+      &format!("scale={width}:-2"),
+      "-c:v",
+      "mjpeg",
+      "-q:v",
+      "5",
+      "-f",
+      "image2pipe",
+      "-",
+    ]);
+    let output = command(ffmpeg_path, &grab_args)
+      .output()
+      .map_err(|e| spawn_error(ffmpeg_path, e))?;
+    if !output.status.success() || output.stdout.is_empty() {
+      let stderr = String::from_utf8_lossy(&output.stderr).trim().to_string();
+      return Err(if stderr.is_empty() {
+        format!("ffmpeg produced no frame at {seconds:.3}s")
+      } else {
+        format!("ffmpeg frame grab at {seconds:.3}s failed: {stderr}")
+      });
+    }
+    Ok(output.stdout)
+  }
+
+  fn run_encode(
+    &self,
+    ffmpeg_path: &Path,
+    args: &[String],
+    on_stdout: &mut dyn FnMut(&[u8]) -> bool,
+  ) -> Result<ProcessOutput, String> {
+    let started = Instant::now();
+    let mut child = command(ffmpeg_path, args)
+      .spawn()
+      .map_err(|e| spawn_error(ffmpeg_path, e))?;
+    let spawn_ms = started.elapsed().as_secs_f64() * 1000.0;
+    // Drain stderr on its own thread so a chatty encoder cannot stall on a full pipe.
+    let stderr = drain(child.stderr.take(), started);
+
+    let mut first_stdout_byte_ms = None;
+    if let Some(mut stdout) = child.stdout.take() {
+      let mut buf = vec![0u8; 64 * 1024];
+      loop {
+        let n = match stdout.read(&mut buf) {
+          Ok(0) => break,
+          Ok(n) => n,
+          Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+          Err(_) => break,
+        };
+        first_stdout_byte_ms.get_or_insert_with(|| started.elapsed().as_secs_f64() * 1000.0);
+        if !on_stdout(&buf[..n]) {
+          let _ = child.kill();
+          let _ = child.wait();
+          return Err("ffmpeg was cancelled".to_string());
+        }
+      }
+    }
+
+    let start_wait = Instant::now();
+    let status = child.wait().map_err(|e| format!("Failed to wait for ffmpeg: {e}"))?;
+    let wait_ms = start_wait.elapsed().as_secs_f64() * 1000.0;
+    let (first_stderr_byte_ms, stderr) = stderr.join().unwrap_or_default();
+    Ok(ProcessOutput {
+      success: status.success(),
+      exit_code: status.code(),
+      stdout: Vec::new(),
+      stderr,
+      timing: ProcessTiming {
+        spawn_ms,
+        first_stdout_byte_ms,
+        first_stderr_byte_ms,
+        wait_ms,
+        total_ms: started.elapsed().as_secs_f64() * 1000.0,
+      },
+    })
+  }
+}
+
+/// Splits streamed stdout chunks into lines (for `-progress pipe:1` and metadata printing).
+#[derive(Default)]
+pub struct LineSplitter {
+  pending: Vec<u8>,
+}
+
+impl LineSplitter {
+  /// Feed a chunk; `on_line` gets each complete line without its terminator.
+  pub fn push(&mut self, chunk: &[u8], mut on_line: impl FnMut(&str)) {
+    self.pending.extend_from_slice(chunk);
+    while let Some(end) = self.pending.iter().position(|b| *b == b'\n') {
+      let line: Vec<u8> = self.pending.drain(..=end).collect();
+      on_line(String::from_utf8_lossy(&line).trim_end_matches(['\r', '\n']));
+    }
+  }
+}
+
+#[cfg(test)]
+thread_local! {
+  static TEST_BACKEND: std::cell::RefCell<Option<Arc<dyn MediaBackend>>> = const { std::cell::RefCell::new(None) };
+}
+
+/// Use `backend` for everything on the current test thread.
+#[cfg(test)]
+pub fn install_for_test(backend: Arc<dyn MediaBackend>) {
+  TEST_BACKEND.with(|b| *b.borrow_mut() = Some(backend));
+}
+
+/// Scripted backend for tests: probes answer with the first rule whose needle appears in the joined
+/// arguments (else `{}`), keyframe scans return the scripted times (else none), encodes stream the scripted stdout chunks and write the scripted bytes to
+/// their `-y <file>` output, everything else succeeds with no output, and every call is recorded.
+#[cfg(test)]
+#[derive(Default)]
+pub struct FakeBackend {
+  probe_rules: std::sync::Mutex<Vec<(String, String)>>,
+  encode_stdout: std::sync::Mutex<Vec<Vec<u8>>>,
+  encode_output: std::sync::Mutex<Option<Vec<u8>>>,
+  keyframe_times: std::sync::Mutex<Vec<f64>>,
+  pub calls: std::sync::Mutex<Vec<(String, Vec<String>)>>,
+}
+
+#[cfg(test)]
+impl FakeBackend {
+  pub fn on_probe(&self, needle: &str, stdout: &str) -> &Self {
+    self.probe_rules.lock().unwrap().push((needle.to_string(), stdout.to_string()));
+    self
+  }
+
+  /// Keyframe times every later keyframe scan reports.
+  pub fn on_keyframes(&self, times: &[f64]) -> &Self {
+    *self.keyframe_times.lock().unwrap() = times.to_vec();
+    self
+  }
+
+  /// Stdout of every later `run_encode`, delivered chunk by chunk as given.
+  pub fn on_encode_stdout(&self, chunks: Vec<Vec<u8>>) -> &Self {
+    *self.encode_stdout.lock().unwrap() = chunks;
+    self
+  }
+
+  /// Contents every later encode writes to its output file (default: a few placeholder bytes).
+  pub fn on_encode_output(&self, bytes: &[u8]) -> &Self {
+    *self.encode_output.lock().unwrap() = Some(bytes.to_vec());
+    self
+  }
+
+  /// Arguments of every `run_encode` call so far.
+  pub fn encodes(&self) -> Vec<Vec<String>> {
+    self
+      .calls
+      .lock()
+      .unwrap()
+      .iter()
+      .filter(|(kind, _)| kind == "encode")
+      .map(|(_, args)| args.clone())
+      .collect()
+  }
+
+  fn record(&self, kind: &str, args: Vec<String>) {
+    self.calls.lock().unwrap().push((kind.to_string(), args));
+  }
+}
+
+#[cfg(test)]
+impl MediaBackend for FakeBackend {
+  fn probe(&self, _ffprobe_path: &Path, args: &[String]) -> Result<ProcessOutput, String> {
+    self.record("probe", args.to_vec());
+    let joined = args.join(" ");
+    let stdout = self
+      .probe_rules
+      .lock()
+      .unwrap()
+      .iter()
+      .find(|(needle, _)| joined.contains(needle.as_str()))
+      .map(|(_, stdout)| stdout.clone())
+      .unwrap_or_else(|| "{}".to_string());
+    Ok(ProcessOutput {
+      success: true,
+      exit_code: Some(0),
+      stdout: stdout.into_bytes(),
+      ..ProcessOutput::default()
+    })
+  }
+
+  fn keyframes(
+    &self,
+    _ffprobe_path: &Path,
+    input_path: &str,
+    _duration_seconds: Option<f64>,
+    on_progress: &mut dyn FnMut(f64) -> bool,
+  ) -> Result<Vec<f64>, String> {
+    self.record("keyframes", vec![input_path.to_string()]);
+    on_progress(100.0);
+    Ok(self.keyframe_times.lock().unwrap().clone())
+  }
+
+  fn grab_frame(&self, _ffmpeg_path: &Path, input_path: &str, seconds: f64, width: u32) -> Result<Vec<u8>, String> {
+    self.record("grab_frame", vec![input_path.to_string(), seconds.to_string(), width.to_string()]);
+    Ok(vec![0xFF, 0xD8, 0xFF, 0xD9])
+  }
+
+  fn run_encode(
+    &self,
+    _ffmpeg_path: &Path,
+    args: &[String],
+    on_stdout: &mut dyn FnMut(&[u8]) -> bool,
+  ) -> Result<ProcessOutput, String> {
+    self.record("encode", args.to_vec());
+    for chunk in self.encode_stdout.lock().unwrap().iter() {
+      if !on_stdout(chunk) {
+        return Err("ffmpeg was cancelled".to_string());
+      }
+    }
+    // Like ffmpeg, create the file named after `-y`; decode checks (`-f null -`) write nothing.
+    if let [.., flag, output] = args {
+      if flag == "-y" {
+        let bytes = self.encode_output.lock().unwrap().clone();
+        std::fs::write(output, bytes.unwrap_or_else(|| b"fake media".to_vec())).map_err(|e| e.to_string())?;
+      }
+    }
+    Ok(ProcessOutput {
+      success: true,
+      exit_code: Some(0),
+      ..ProcessOutput::default()
+    })
+  }
+}
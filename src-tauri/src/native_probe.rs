@@ -0,0 +1,1328 @@
+// In-process probing for MP4/MOV and Matroska/WebM, so Linux and macOS skip the ffprobe launch for
+// the common containers (Windows uses Media Foundation for the same purpose), plus keyframe lists
+// read from the container index on every platform.
+//
+// Only container headers are read: the `moov` box for MP4, the EBML header plus segment Info,
+// Tracks and Cues for Matroska. Anything this module is unsure about (fragmented files without a duration,
+// unknown codecs, QuickTime chapter text tracks) returns None so the caller falls back to ffprobe;
+// results must match what ffprobe would report, including stream order for `0:a:N` / `0:s:N`.
+
+use crate::{AudioStreamInfo, SubtitleStreamInfo};
+use std::fs::File;
+use std::io::{BufReader, Read, Seek, SeekFrom};
+use std::path::Path;
+
+// Headers larger than this are unusual enough to leave to ffprobe.
+const MAX_HEADER_BYTES: u64 = 64 * 1024 * 1024;
+
+pub struct NativeProbe {
+  pub container: &'static str,
+  pub duration_seconds: f64,
+  pub audio_streams: Vec<AudioStreamInfo>,
+  pub subtitle_streams: Vec<SubtitleStreamInfo>,
+  // Display rotation of the first video track, in ffprobe's side-data convention (normalized 0-359).
+  pub rotation_degrees: i32,
+}
+
+/// Probe `path` without spawning ffprobe. None means "ask ffprobe".
+pub fn probe(path: &Path) -> Option<NativeProbe> {
+  let mut file = File::open(path).ok()?;
+  let file_len = file.metadata().ok()?.len();
+  let mut magic = [0u8; 8];
+  file.read_exact(&mut magic).ok()?;
+  file.seek(SeekFrom::Start(0)).ok()?;
+
+  if magic[..4] == [0x1A, 0x45, 0xDF, 0xA3] {
+    return probe_matroska(&mut file, file_len);
+  }
+  if matches!(&magic[4..8], b"ftyp" | b"moov" | b"free" | b"skip" | b"wide" | b"mdat") {
+    return probe_mp4(&mut file, file_len);
+  }
+  None
+}
+
+fn read_at(file: &mut File, pos: u64, len: u64) -> Option<Vec<u8>> {
+  if len > MAX_HEADER_BYTES {
+    return None;
+  }
+  file.seek(SeekFrom::Start(pos)).ok()?;
+  let mut buf = vec![0u8; len as usize];
+  file.read_exact(&mut buf).ok()?;
+  Some(buf)
+}
+
+fn be_uint(bytes: &[u8]) -> u64 {
+  bytes.iter().fold(0u64, |acc, b| (acc << 8) | *b as u64)
+}
+
+fn be_at(data: &[u8], offset: usize, len: usize) -> Option<u64> {
+  data.get(offset..offset + len).map(be_uint)
+}
+
+fn text(bytes: &[u8]) -> String {
+  String::from_utf8_lossy(bytes).trim_end_matches('\0').trim().to_string()
+}
+
+fn normalize_degrees(deg: f64) -> i32 {
+  (deg.round() as i32).rem_euclid(360)
+}
+
+// ---------------------------------------------------------------------------------------------
+// MP4 / MOV
+
+// Child boxes of an in-memory box payload.
+fn mp4_boxes(data: &[u8]) -> Vec<([u8; 4], &[u8])> {
+  let mut out = Vec::new();
+  let mut pos = 0usize;
+  while pos + 8 <= data.len() {
+    let size32 = be_uint(&data[pos..pos + 4]);
+    let kind: [u8; 4] = data[pos + 4..pos + 8].try_into().unwrap_or_default();
+    let (header_len, size) = match size32 {
+      0 => (8, (data.len() - pos) as u64),
+      1 => match be_at(data, pos + 8, 8) {
+        Some(size) => (16, size),
+        None => break,
+      },
+      n => (8, n),
+    };
+    let end = pos.saturating_add(size as usize);
+    if size < header_len || end > data.len() {
+      break;
+    }
+    out.push((kind, &data[pos + header_len as usize..end]));
+    pos = end;
+  }
+  out
+}
+
+fn mp4_child<'a>(data: &'a [u8], kind: &[u8; 4]) -> Option<&'a [u8]> {
+  mp4_boxes(data).into_iter().find(|(k, _)| k == kind).map(|(_, body)| body)
+}
+
+fn mp4_path<'a>(data: &'a [u8], path: &[&[u8; 4]]) -> Option<&'a [u8]> {
+  path.iter().try_fold(data, |d, kind| mp4_child(d, kind))
+}
+
+// Top-level boxes are walked by seeking, so `moov` after a large `mdat` costs one seek.
+fn read_moov(file: &mut File, file_len: u64) -> Option<Vec<u8>> {
+  let mut pos = 0u64;
+  while pos + 8 <= file_len {
+    let header = read_at(file, pos, 16.min(file_len - pos))?;
+    let (header_len, size) = match be_uint(&header[..4]) {
+      0 => (8, file_len - pos),
+      1 => (16, be_at(&header, 8, 8)?),
+      n => (8, n),
+    };
+    if size < header_len {
+      return None;
+    }
+    if &header[4..8] == b"moov" {
+      return read_at(file, pos + header_len, size - header_len);
+    }
+    pos = pos.checked_add(size)?;
+  }
+  None
+}
+
+// (timescale, duration) from an mvhd or mdhd body.
+fn mp4_time_fields(full_box: &[u8]) -> Option<(u64, Option<u64>)> {
+  let (timescale, duration) = if *full_box.first()? == 1 {
+    (be_at(full_box, 20, 4)?, be_at(full_box, 24, 8)?)
+  } else {
+    let d = be_at(full_box, 16, 4)?;
+    (be_at(full_box, 12, 4)?, if d == u32::MAX as u64 { u64::MAX } else { d })
+  };
+  let duration = (duration != 0 && duration != u64::MAX).then_some(duration);
+  Some((timescale, duration))
+}
+
+fn mp4_language(mdhd: &[u8]) -> String {
+  let offset = if mdhd.first() == Some(&1) { 32 } else { 20 };
+  let Some(packed) = be_at(mdhd, offset, 2) else {
+    return "und".to_string();
+  };
+  let letters: String = [10u32, 5, 0]
+    .iter()
+    .filter_map(|shift| char::from_u32((((packed as u32) >> shift) & 0x1F) + 0x60))
+    .collect();
+  if letters.chars().all(|c| c.is_ascii_lowercase()) {
+    letters
+  } else {
+    "und".to_string()
+  }
+}
+
+// Rotation from the tkhd display matrix, as ffprobe's `rotation` side data reports it.
+fn mp4_rotation(tkhd: &[u8]) -> i32 {
+  let offset = if tkhd.first() == Some(&1) { 52 } else { 40 };
+  let fixed = |i: usize| be_at(tkhd, offset + i * 4, 4).map(|v| v as u32 as i32 as f64 / 65536.0);
+  match (fixed(0), fixed(1)) {
+    (Some(a), Some(b)) if a != 0.0 || b != 0.0 => normalize_degrees(-b.atan2(a).to_degrees()),
+    _ => 0,
+  }
+}
+
+fn mp4_track_id(tkhd: &[u8]) -> Option<u64> {
+  be_at(tkhd, if tkhd.first() == Some(&1) { 20 } else { 12 }, 4)
+}
+
+// Length-prefixed MPEG-4 descriptor: (tag, header length).
+fn mp4_descriptor(data: &[u8], pos: usize) -> Option<(u8, usize)> {
+  let tag = *data.get(pos)?;
+  let mut len = 1;
+  while data.get(pos + len)? & 0x80 != 0 && len < 5 {
+    len += 1;
+  }
+  Some((tag, len + 1))
+}
+
+fn esds_object_type(esds: &[u8]) -> Option<u8> {
+  let mut pos = 4;
+  let (tag, header_len) = mp4_descriptor(esds, pos)?;
+  if tag != 0x03 {
+    return None;
+  }
+  pos += header_len;
+  let flags = *esds.get(pos + 2)?;
+  pos += 3;
+  if flags & 0x80 != 0 {
+    pos += 2;
+  }
+  if flags & 0x40 != 0 {
+    pos += 1 + *esds.get(pos)? as usize;
+  }
+  if flags & 0x20 != 0 {
+    pos += 2;
+  }
+  let (tag, header_len) = mp4_descriptor(esds, pos)?;
+  if tag != 0x04 {
+    return None;
+  }
+  esds.get(pos + header_len).copied()
+}
+
+// (codec_name, channels) for the first audio sample entry, named as ffprobe names it.
+fn mp4_audio_codec(entry_kind: &[u8; 4], entry: &[u8]) -> Option<(String, Option<i32>)> {
+  // `entry` starts after the 8-byte box header: 6 reserved, data ref index, then the sound fields.
+  let version = be_at(entry, 8, 2)?;
+  let (channels, children_offset) = match version {
+    0 => (be_at(entry, 16, 2)?, 28),
+    1 => (be_at(entry, 16, 2)?, 44),
+    2 => (be_at(entry, 40, 4)?, 64),
+    _ => return None,
+  };
+  let codec = match entry_kind {
+    b"mp4a" => {
+      let esds = entry.get(children_offset..).and_then(|c| mp4_child(c, b"esds"))?;
+      match esds_object_type(esds)? {
+        0x40 | 0x66 | 0x67 | 0x68 => "aac",
+        0x69 | 0x6B => "mp3",
+        0xA5 => "ac3",
+        0xA6 => "eac3",
+        0xAD => "opus",
+        0xDD => "vorbis",
+        _ => return None,
+      }
+    }
+    b"ac-3" => "ac3",
+    b"ec-3" => "eac3",
+    b"ac-4" => "ac4",
+    b"Opus" => "opus",
+    b"fLaC" => "flac",
+    b"alac" => "alac",
+    b".mp3" => "mp3",
+    b"sowt" => "pcm_s16le",
+    b"twos" => "pcm_s16be",
+    b"samr" => "amr_nb",
+    b"sawb" => "amr_wb",
+    b"dtsc" | b"dtsh" | b"dtsl" => "dts",
+    _ => return None,
+  };
+  Some((codec.to_string(), Some(channels as i32)))
+}
+
+fn mp4_subtitle_codec(entry_kind: &[u8; 4]) -> Option<&'static str> {
+  Some(match entry_kind {
+    b"tx3g" => "mov_text",
+    b"wvtt" => "webvtt",
+    b"stpp" => "ttml",
+    b"c608" => "eia_608",
+    _ => return None,
+  })
+}
+
+fn probe_mp4(file: &mut File, file_len: u64) -> Option<NativeProbe> {
+  let moov = read_moov(file, file_len)?;
+  let (movie_timescale, movie_duration) = mp4_time_fields(mp4_child(&moov, b"mvhd")?)?;
+  // Fragmented files keep the total in mvex/mehd; without it the length is only in the fragments.
+  let movie_duration = movie_duration.or_else(|| {
+    let mehd = mp4_path(&moov, &[b"mvex", b"mehd"])?;
+    let d = if mehd.first() == Some(&1) { be_at(mehd, 4, 8)? } else { be_at(mehd, 4, 4)? };
+    (d != 0).then_some(d)
+  })?;
+  if movie_timescale == 0 {
+    return None;
+  }
+
+  let traks: Vec<&[u8]> = mp4_boxes(&moov)
+    .into_iter()
+    .filter(|(k, _)| k == b"trak")
+    .map(|(_, body)| body)
+    .collect();
+  // QuickTime chapter tracks show up in ffprobe as data streams, not subtitles.
+  let chapter_track_ids: Vec<u64> = traks
+    .iter()
+    .filter_map(|t| mp4_path(t, &[b"tref", b"chap"]))
+    .flat_map(|chap| chap.chunks_exact(4).map(be_uint).collect::<Vec<_>>())
+    .collect();
+
+  let mut audio_streams = Vec::new();
+  let mut subtitle_streams = Vec::new();
+  let mut rotation_degrees = None;
+  // ffprobe creates one stream per trak, in file order, whatever its handler.
+  for (index, trak) in traks.into_iter().enumerate() {
+    let index = index as i32;
+    let tkhd = mp4_child(trak, b"tkhd")?;
+    let mdia = mp4_child(trak, b"mdia")?;
+    let handler: [u8; 4] = mp4_child(mdia, b"hdlr")?.get(8..12)?.try_into().ok()?;
+    let language = mp4_child(mdia, b"mdhd").map(mp4_language).unwrap_or_else(|| "und".to_string());
+    let title = mp4_path(trak, &[b"udta", b"name"]).map(text).unwrap_or_default();
+    let stsd = mp4_path(mdia, &[b"minf", b"stbl", b"stsd"]);
+    let first_entry = stsd.and_then(|s| s.get(8..)).and_then(|s| mp4_boxes(s).into_iter().next());
+
+    match &handler {
+      b"vide" => {
+        rotation_degrees.get_or_insert_with(|| mp4_rotation(tkhd));
+      }
+      b"soun" => {
+        let (kind, entry) = first_entry?;
+        let (codec_name, channels) = mp4_audio_codec(&kind, entry)?;
+        audio_streams.push(AudioStreamInfo {
+          order: audio_streams.len() as i32,
+          index,
+          codec_name,
+          channels,
+          language,
+          title,
+        });
+      }
+      b"sbtl" | b"subt" | b"clcp" => {
+        if mp4_track_id(tkhd).is_some_and(|id| chapter_track_ids.contains(&id)) {
+          return None;
+        }
+        let (kind, _) = first_entry?;
+        subtitle_streams.push(SubtitleStreamInfo {
+          order: subtitle_streams.len() as i32,
+          index,
+          codec_name: mp4_subtitle_codec(&kind)?.to_string(),
+          language,
+          title,
+        });
+      }
+      // Plain text tracks are usually chapters; leave the classification to ffprobe.
+      b"text" => return None,
+      _ => {}
+    }
+  }
+
+  Some(NativeProbe {
+    container: "mp4",
+    duration_seconds: movie_duration as f64 / movie_timescale as f64,
+    audio_streams,
+    subtitle_streams,
+    rotation_degrees: rotation_degrees.unwrap_or(0),
+  })
+}
+
+// ---------------------------------------------------------------------------------------------
+// Matroska / WebM
+
+const EBML_HEADER: u64 = 0x1A45_DFA3;
+const EBML_DOC_TYPE: u64 = 0x4282;
+const MKV_SEGMENT: u64 = 0x1853_8067;
+const MKV_SEEK_HEAD: u64 = 0x114D_9B74;
+const MKV_SEEK: u64 = 0x4DBB;
+const MKV_SEEK_ID: u64 = 0x53AB;
+const MKV_SEEK_POSITION: u64 = 0x53AC;
+const MKV_INFO: u64 = 0x1549_A966;
+const MKV_TIMESTAMP_SCALE: u64 = 0x2A_D7B1;
+const MKV_DURATION: u64 = 0x4489;
+const MKV_TRACKS: u64 = 0x1654_AE6B;
+const MKV_TRACK_ENTRY: u64 = 0xAE;
+const MKV_TRACK_TYPE: u64 = 0x83;
+const MKV_CODEC_ID: u64 = 0x86;
+const MKV_NAME: u64 = 0x536E;
+const MKV_LANGUAGE: u64 = 0x22_B59C;
+const MKV_VIDEO: u64 = 0xE0;
+const MKV_PROJECTION: u64 = 0x7670;
+const MKV_PROJECTION_POSE_ROLL: u64 = 0x7675;
+const MKV_AUDIO: u64 = 0xE1;
+const MKV_CHANNELS: u64 = 0x9F;
+const MKV_BIT_DEPTH: u64 = 0x6264;
+const MKV_CLUSTER: u64 = 0x1F43_B675;
+
+// EBML variable-length integer at `pos`: (value, length). IDs keep their marker bit.
+fn ebml_vint(data: &[u8], pos: usize, keep_marker: bool) -> Option<(u64, usize)> {
+  let first = *data.get(pos)?;
+  let len = first.leading_zeros() as usize + 1;
+  if len > 8 {
+    return None;
+  }
+  let raw = be_at(data, pos, len)?;
+  let value = if keep_marker { raw } else { raw & (u64::MAX >> (64 - 7 * len)) };
+  Some((value, len))
+}
+
+// Element header at `pos`: (id, payload size or None when unknown, header length).
+fn ebml_header(data: &[u8], pos: usize) -> Option<(u64, Option<u64>, usize)> {
+  let (id, id_len) = ebml_vint(data, pos, true)?;
+  let (size, size_len) = ebml_vint(data, pos + id_len, false)?;
+  let unknown = size == u64::MAX >> (64 - 7 * size_len);
+  Some((id, (!unknown).then_some(size), id_len + size_len))
+}
+
+fn ebml_children(data: &[u8]) -> Vec<(u64, &[u8])> {
+  let mut out = Vec::new();
+  let mut pos = 0usize;
+  while let Some((id, Some(size), header_len)) = ebml_header(data, pos) {
+    let start = pos + header_len;
+    let Some(end) = start.checked_add(size as usize).filter(|e| *e <= data.len()) else {
+      break;
+    };
+    out.push((id, &data[start..end]));
+    pos = end;
+  }
+  out
+}
+
+fn ebml_child(data: &[u8], id: u64) -> Option<&[u8]> {
+  ebml_children(data).into_iter().find(|(i, _)| *i == id).map(|(_, body)| body)
+}
+
+fn ebml_float(bytes: &[u8]) -> Option<f64> {
+  match bytes.len() {
+    4 => Some(f32::from_bits(be_uint(bytes) as u32) as f64),
+    8 => Some(f64::from_bits(be_uint(bytes))),
+    _ => None,
+  }
+}
+
+fn mkv_audio_codec(codec_id: &str, bit_depth: Option<u64>) -> Option<&'static str> {
+  Some(match codec_id {
+    id if id.starts_with("A_AAC") => "aac",
+    "A_AC3" => "ac3",
+    "A_EAC3" => "eac3",
+    id if id.starts_with("A_DTS") => "dts",
+    "A_TRUEHD" => "truehd",
+    "A_OPUS" => "opus",
+    "A_VORBIS" => "vorbis",
+    "A_FLAC" => "flac",
+    "A_ALAC" => "alac",
+    "A_MPEG/L3" => "mp3",
+    "A_MPEG/L2" => "mp2",
+    "A_PCM/INT/LIT" => match bit_depth {
+      Some(8) => "pcm_u8",
+      Some(16) => "pcm_s16le",
+      Some(24) => "pcm_s24le",
+      Some(32) => "pcm_s32le",
+      _ => return None,
+    },
+    "A_PCM/FLOAT/IEEE" => match bit_depth {
+      Some(32) => "pcm_f32le",
+      Some(64) => "pcm_f64le",
+      _ => return None,
+    },
+    _ => return None,
+  })
+}
+
+fn mkv_subtitle_codec(codec_id: &str) -> Option<&'static str> {
+  Some(match codec_id {
+    "S_TEXT/UTF8" => "subrip",
+    "S_TEXT/ASS" | "S_ASS" => "ass",
+    "S_TEXT/SSA" | "S_SSA" => "ssa",
+    "S_TEXT/WEBVTT" => "webvtt",
+    "S_HDMV/PGS" => "hdmv_pgs_subtitle",
+    "S_VOBSUB" => "dvd_subtitle",
+    "S_DVBSUB" => "dvb_subtitle",
+    _ => return None,
+  })
+}
+
+struct MatroskaSegment {
+  container: &'static str,
+  start: u64,
+  end: u64,
+  // (id, body position, size) of the level-1 elements before the first cluster.
+  elements: Vec<(u64, u64, u64)>,
+  // (id, position relative to `start`) from the SeekHead.
+  seek_positions: Vec<(u64, u64)>,
+  first_cluster: Option<u64>,
+}
+
+fn open_matroska(file: &mut File, file_len: u64) -> Option<MatroskaSegment> {
+  let head = read_at(file, 0, 64.min(file_len))?;
+  let (id, Some(size), header_len) = ebml_header(&head, 0)? else {
+    return None;
+  };
+  if id != EBML_HEADER {
+    return None;
+  }
+  let ebml = read_at(file, header_len as u64, size)?;
+  let doc_type = ebml_child(&ebml, EBML_DOC_TYPE).map(text).unwrap_or_default();
+  let container = match doc_type.as_str() {
+    "matroska" => "matroska",
+    "webm" => "webm",
+    _ => return None,
+  };
+
+  let segment_pos = header_len as u64 + size;
+  let head = read_at(file, segment_pos, 16.min(file_len.saturating_sub(segment_pos)))?;
+  let (id, segment_size, header_len) = ebml_header(&head, 0)?;
+  if id != MKV_SEGMENT {
+    return None;
+  }
+  let start = segment_pos + header_len as u64;
+  let end = segment_size.map_or(file_len, |s| (start + s).min(file_len));
+
+  // Level-1 elements up to the first cluster; Info and Tracks almost always come before it.
+  let mut segment = MatroskaSegment {
+    container,
+    start,
+    end,
+    elements: Vec::new(),
+    seek_positions: Vec::new(),
+    first_cluster: None,
+  };
+  let mut pos = start;
+  while pos < end {
+    let head = read_at(file, pos, 16.min(end - pos))?;
+    let (id, size, header_len) = ebml_header(&head, 0)?;
+    if id == MKV_CLUSTER {
+      segment.first_cluster = Some(pos);
+      break;
+    }
+    let Some(size) = size else {
+      break;
+    };
+    let body_pos = pos + header_len as u64;
+    if id == MKV_SEEK_HEAD {
+      let seek_head = read_at(file, body_pos, size)?;
+      for (_, seek) in ebml_children(&seek_head).into_iter().filter(|(i, _)| *i == MKV_SEEK) {
+        let target = ebml_child(seek, MKV_SEEK_ID).map(be_uint);
+        let position = ebml_child(seek, MKV_SEEK_POSITION).map(be_uint);
+        if let (Some(target), Some(position)) = (target, position) {
+          segment.seek_positions.push((target, position));
+        }
+      }
+    }
+    segment.elements.push((id, body_pos, size));
+    pos = body_pos + size;
+  }
+  Some(segment)
+}
+
+// Body of level-1 element `id`, found by the header walk or, for elements after the clusters, the SeekHead.
+fn matroska_element(file: &mut File, segment: &MatroskaSegment, id: u64) -> Option<Vec<u8>> {
+  if let Some((_, body_pos, size)) = segment.elements.iter().find(|(i, _, _)| *i == id) {
+    return read_at(file, *body_pos, *size);
+  }
+  let (_, position) = segment.seek_positions.iter().find(|(t, _)| *t == id)?;
+  let at = segment.start + position;
+  let head = read_at(file, at, 16.min(segment.end.saturating_sub(at)))?;
+  let (found, Some(size), header_len) = ebml_header(&head, 0)? else {
+    return None;
+  };
+  if found != id {
+    return None;
+  }
+  read_at(file, at + header_len as u64, size)
+}
+
+fn probe_matroska(file: &mut File, file_len: u64) -> Option<NativeProbe> {
+  let segment = open_matroska(file, file_len)?;
+  let container = segment.container;
+  let info = matroska_element(file, &segment, MKV_INFO)?;
+  let tracks = matroska_element(file, &segment, MKV_TRACKS)?;
+
+  let timestamp_scale = ebml_child(&info, MKV_TIMESTAMP_SCALE).map(be_uint).unwrap_or(1_000_000);
+  let duration = ebml_child(&info, MKV_DURATION).and_then(ebml_float)?;
+  let duration_seconds = duration * timestamp_scale as f64 / 1e9;
+  if !duration_seconds.is_finite() || duration_seconds <= 0.0 {
+    return None;
+  }
+
+  let mut audio_streams = Vec::new();
+  let mut subtitle_streams = Vec::new();
+  let mut rotation_degrees = None;
+  let entries = ebml_children(&tracks).into_iter().filter(|(i, _)| *i == MKV_TRACK_ENTRY);
+  // ffprobe numbers video, audio and subtitle tracks in TrackEntry order; it skips other track
+  // types, which are rare enough to leave to it.
+  for (index, (_, entry)) in entries.enumerate() {
+    let index = index as i32;
+    let track_type = ebml_child(entry, MKV_TRACK_TYPE).map(be_uint)?;
+    let codec_id = ebml_child(entry, MKV_CODEC_ID).map(text).unwrap_or_default();
+    let title = ebml_child(entry, MKV_NAME).map(text).unwrap_or_default();
+    // Matroska's default language is English; ffprobe omits the tag for "und".
+    let language = ebml_child(entry, MKV_LANGUAGE).map(text).unwrap_or_else(|| "eng".to_string());
+    let language = if language.is_empty() { "und".to_string() } else { language };
+
+    match track_type {
+      1 => {
+        let roll = ebml_child(entry, MKV_VIDEO)
+          .and_then(|v| ebml_child(v, MKV_PROJECTION))
+          .and_then(|p| ebml_child(p, MKV_PROJECTION_POSE_ROLL))
+          .and_then(ebml_float)
+          .unwrap_or(0.0);
+        rotation_degrees.get_or_insert(normalize_degrees(roll));
+      }
+      2 => {
+        let audio = ebml_child(entry, MKV_AUDIO);
+        let channels = audio.and_then(|a| ebml_child(a, MKV_CHANNELS)).map(be_uint).unwrap_or(1);
+        let bit_depth = audio.and_then(|a| ebml_child(a, MKV_BIT_DEPTH)).map(be_uint);
+        audio_streams.push(AudioStreamInfo {
+          order: audio_streams.len() as i32,
+          index,
+          codec_name: mkv_audio_codec(&codec_id, bit_depth)?.to_string(),
+          channels: Some(channels as i32),
+          language,
+          title,
+        });
+      }
+      0x11 => subtitle_streams.push(SubtitleStreamInfo {
+        order: subtitle_streams.len() as i32,
+        index,
+        codec_name: mkv_subtitle_codec(&codec_id)?.to_string(),
+        language,
+        title,
+      }),
+      _ => return None,
+    }
+  }
+
+  Some(NativeProbe {
+    container,
+    duration_seconds,
+    audio_streams,
+    subtitle_streams,
+    rotation_degrees: rotation_degrees.unwrap_or(0),
+  })
+}
+
+// ---------------------------------------------------------------------------------------------
+// Keyframe indexes
+
+const MKV_TRACK_NUMBER: u64 = 0xD7;
+const MKV_CUES: u64 = 0x1C53_BB6B;
+const MKV_CUE_POINT: u64 = 0xBB;
+const MKV_CUE_TIME: u64 = 0xB3;
+const MKV_CUE_TRACK_POSITIONS: u64 = 0xB7;
+const MKV_CUE_TRACK: u64 = 0xF7;
+const MKV_CLUSTER_TIMESTAMP: u64 = 0xE7;
+const MKV_SIMPLE_BLOCK: u64 = 0xA3;
+const MKV_BLOCK_GROUP: u64 = 0xA0;
+const MKV_BLOCK: u64 = 0xA1;
+const MKV_REFERENCE_BLOCK: u64 = 0xFB;
+const MKV_CUE_CLUSTER_POSITION: u64 = 0xF1;
+// Bytes read from the start of a cluster to find its first block header.
+const MKV_CLUSTER_HEAD_BYTES: u64 = 64;
+
+/// Presentation times of every keyframe of the first video stream, read from the container's own
+/// index: MP4 stss/stts/ctts or complete Matroska Cues. Sorted; None means "ask ffprobe". Only
+/// headers are read, so this is cheap enough for preflight and snapping.
+pub fn keyframes(path: &Path) -> Option<Vec<f64>> {
+  keyframes_with(path, false)
+}
+
+/// Like `keyframes`, but Matroska files without complete Cues get their blocks scanned. That reads
+/// the whole file, so it belongs on the explicit index build only.
+pub fn scanned_keyframes(path: &Path) -> Option<Vec<f64>> {
+  keyframes_with(path, true)
+}
+
+fn keyframes_with(path: &Path, scan_blocks: bool) -> Option<Vec<f64>> {
+  let mut file = File::open(path).ok()?;
+  let file_len = file.metadata().ok()?.len();
+  let mut magic = [0u8; 8];
+  file.read_exact(&mut magic).ok()?;
+  file.seek(SeekFrom::Start(0)).ok()?;
+
+  let mut times = if magic[..4] == [0x1A, 0x45, 0xDF, 0xA3] {
+    matroska_keyframes(&mut file, file_len, scan_blocks)?
+  } else if matches!(&magic[4..8], b"ftyp" | b"moov" | b"free" | b"skip" | b"wide" | b"mdat") {
+    mp4_keyframes(&mut file, file_len)?
+  } else {
+    return None;
+  };
+  if times.is_empty() {
+    return None;
+  }
+  times.sort_by(f64::total_cmp);
+  times.dedup();
+  Some(times)
+}
+
+// Sample-table runs: (sample count, value) pairs of an stts or ctts box.
+fn mp4_runs(table: &[u8], signed_values: bool) -> Option<Vec<(u64, i64)>> {
+  let count = be_at(table, 4, 4)? as usize;
+  (0..count)
+    .map(|i| {
+      let samples = be_at(table, 8 + i * 8, 4)?;
+      let raw = be_at(table, 12 + i * 8, 4)?;
+      let value = if signed_values { raw as u32 as i32 as i64 } else { raw as i64 };
+      Some((samples, value))
+    })
+    .collect()
+}
+
+// Seconds the edit list adds to media timestamps: leading empty edits delay the track, the first
+// real edit's media time is cut off. More than one real edit is left to ffprobe.
+fn mp4_edit_shift(trak: &[u8], movie_timescale: u64, media_timescale: u64) -> Option<f64> {
+  let Some(elst) = mp4_path(trak, &[b"edts", b"elst"]) else {
+    return Some(0.0);
+  };
+  let v1 = elst.first() == Some(&1);
+  let entry_len = if v1 { 20 } else { 12 };
+  let count = be_at(elst, 4, 4)? as usize;
+  let mut delay = 0u64;
+  let mut media_time = None;
+  for i in 0..count {
+    let at = 8 + i * entry_len;
+    let (duration, time) = if v1 {
+      (be_at(elst, at, 8)?, be_at(elst, at + 8, 8)? as i64)
+    } else {
+      (be_at(elst, at, 4)?, be_at(elst, at + 4, 4)? as u32 as i32 as i64)
+    };
+    match (time, media_time) {
+      (-1, None) => delay += duration,
+      (-1, Some(_)) => {}
+      (t, None) => media_time = Some(t),
+      (_, Some(_)) => return None,
+    }
+  }
+  Some(delay as f64 / movie_timescale as f64 - media_time.unwrap_or(0) as f64 / media_timescale as f64)
+}
+
+fn mp4_keyframes(file: &mut File, file_len: u64) -> Option<Vec<f64>> {
+  let moov = read_moov(file, file_len)?;
+  let (movie_timescale, _) = mp4_time_fields(mp4_child(&moov, b"mvhd")?)?;
+  let trak = mp4_boxes(&moov)
+    .into_iter()
+    .filter(|(k, _)| k == b"trak")
+    .map(|(_, body)| body)
+    .find(|t| mp4_path(t, &[b"mdia", b"hdlr"]).and_then(|h| h.get(8..12)) == Some(b"vide".as_slice()))?;
+  let mdia = mp4_child(trak, b"mdia")?;
+  let (timescale, _) = mp4_time_fields(mp4_child(mdia, b"mdhd")?)?;
+  if timescale == 0 || movie_timescale == 0 {
+    return None;
+  }
+  let stbl = mp4_path(mdia, &[b"minf", b"stbl"])?;
+  let stts = mp4_runs(mp4_child(stbl, b"stts")?, false)?;
+  let sample_count: u64 = stts.iter().map(|r| r.0).sum();
+  // Fragmented files keep their samples in moof boxes. Every sample takes at least a byte of the
+  // file, so a larger count is a corrupt table and must not size the allocation below.
+  if sample_count == 0 || sample_count > file_len {
+    return None;
+  }
+  let ctts = mp4_child(stbl, b"ctts").map(|c| mp4_runs(c, true)).unwrap_or(Some(Vec::new()))?;
+  let shift = mp4_edit_shift(trak, movie_timescale, timescale)?;
+
+  // 1-based sample numbers; without stss every sample is a sync sample.
+  let mut sync: Vec<u64> = match mp4_child(stbl, b"stss") {
+    Some(stss) => {
+      let count = be_at(stss, 4, 4)? as usize;
+      (0..count).map(|i| be_at(stss, 8 + i * 4, 4)).collect::<Option<_>>()?
+    }
+    None => (1..=sample_count).collect(),
+  };
+  sync.sort_unstable();
+
+  // Walk both run tables once with cursors, since sync samples are ascending.
+  let (mut stts_i, mut stts_first, mut stts_dts) = (0usize, 0u64, 0i64);
+  let (mut ctts_i, mut ctts_first) = (0usize, 0u64);
+  let mut times = Vec::with_capacity(sync.len());
+  for n in sync.into_iter().filter_map(|n| n.checked_sub(1)) {
+    while stts_i < stts.len() && stts_first + stts[stts_i].0 <= n {
+      stts_dts += stts[stts_i].0 as i64 * stts[stts_i].1;
+      stts_first += stts[stts_i].0;
+      stts_i += 1;
+    }
+    let (_, delta) = *stts.get(stts_i)?;
+    let dts = stts_dts + (n - stts_first) as i64 * delta;
+
+    while ctts_i < ctts.len() && ctts_first + ctts[ctts_i].0 <= n {
+      ctts_first += ctts[ctts_i].0;
+      ctts_i += 1;
+    }
+    let offset = ctts.get(ctts_i).map_or(0, |r| r.1);
+    times.push((dts + offset) as f64 / timescale as f64 + shift);
+  }
+  Some(times)
+}
+
+fn matroska_video_track(tracks: &[u8]) -> Option<u64> {
+  ebml_children(tracks)
+    .into_iter()
+    .filter(|(i, _)| *i == MKV_TRACK_ENTRY)
+    .find(|(_, entry)| ebml_child(entry, MKV_TRACK_TYPE).map(be_uint) == Some(1))
+    .and_then(|(_, entry)| ebml_child(entry, MKV_TRACK_NUMBER).map(be_uint))
+}
+
+// (cue time, CueClusterPosition) of the cue points for `track`.
+fn matroska_cue_points(cues: &[u8], track: u64) -> Vec<(i64, Option<u64>)> {
+  ebml_children(cues)
+    .into_iter()
+    .filter(|(i, _)| *i == MKV_CUE_POINT)
+    .filter_map(|(_, point)| {
+      let children = ebml_children(point);
+      let positions = children.iter().find(|(i, positions)| {
+        *i == MKV_CUE_TRACK_POSITIONS && ebml_child(positions, MKV_CUE_TRACK).map(be_uint) == Some(track)
+      })?;
+      let time = children.iter().find(|(i, _)| *i == MKV_CUE_TIME).map(|(_, t)| be_uint(t) as i64)?;
+      Some((time, ebml_child(positions.1, MKV_CUE_CLUSTER_POSITION).map(be_uint)))
+    })
+    .collect()
+}
+
+// (track, relative timestamp, keyframe flag) from the start of a Block or SimpleBlock body.
+fn matroska_block_head(head: &[u8]) -> Option<(u64, i64, u8)> {
+  let (track, len) = ebml_vint(head, 0, false)?;
+  let relative = be_at(head, len, 2)? as u16 as i16 as i64;
+  Some((track, relative, *head.get(len + 2)?))
+}
+
+// Positions (relative to the segment start) of every cluster, from level-1 element headers only.
+fn matroska_cluster_positions(file: &mut File, segment: &MatroskaSegment) -> Option<Vec<u64>> {
+  let mut positions = Vec::new();
+  let mut pos = segment.first_cluster?;
+  while pos < segment.end {
+    let head = read_at(file, pos, 16.min(segment.end - pos))?;
+    // Clusters of unknown size (live recordings) cannot be stepped over.
+    let (id, Some(size), header_len) = ebml_header(&head, 0)? else {
+      return None;
+    };
+    if id == MKV_CLUSTER {
+      positions.push(pos - segment.start);
+    }
+    pos += header_len as u64 + size;
+  }
+  Some(positions)
+}
+
+// Whether the cluster at `pos` opens with a keyframe of `track`, from its first bytes. None when the
+// head does not tell (a BlockGroup, whose ReferenceBlock may come after the frame data).
+fn matroska_cluster_opens_with_keyframe(file: &mut File, segment: &MatroskaSegment, pos: u64, track: u64) -> Option<bool> {
+  let head = read_at(file, pos, MKV_CLUSTER_HEAD_BYTES.min(segment.end - pos))?;
+  let (_, _, mut at) = ebml_header(&head, 0)?;
+  loop {
+    let (id, size, header_len) = ebml_header(&head, at)?;
+    match id {
+      MKV_SIMPLE_BLOCK => {
+        let (block_track, _, flags) = matroska_block_head(head.get(at + header_len..)?)?;
+        return Some(block_track == track && flags & 0x80 != 0);
+      }
+      MKV_BLOCK_GROUP => return None,
+      _ => at = at.checked_add(header_len)?.checked_add(usize::try_from(size?).ok()?)?,
+    }
+  }
+}
+
+// Cues are complete when every cluster that starts with a video keyframe is indexed. Muxers start a
+// cluster at each keyframe and cue it; clusters cut by a time or size limit start mid-GOP.
+fn matroska_cues_are_complete(
+  file: &mut File,
+  segment: &MatroskaSegment,
+  cue_points: &[(i64, Option<u64>)],
+  track: u64,
+) -> Option<bool> {
+  let mut cued: Vec<u64> = cue_points.iter().map(|(_, p)| *p).collect::<Option<_>>()?;
+  cued.sort_unstable();
+  for cluster in matroska_cluster_positions(file, segment)? {
+    if cued.binary_search(&cluster).is_err()
+      && matroska_cluster_opens_with_keyframe(file, segment, segment.start + cluster, track)?
+    {
+      return Some(false);
+    }
+  }
+  Some(true)
+}
+
+// Files without complete Cues: walk every cluster, reading only element headers and block headers
+// through one buffered reader.
+fn matroska_block_scan(file: &mut File, segment: &MatroskaSegment, track: u64) -> Option<Vec<i64>> {
+  let mut reader = BufReader::with_capacity(256 * 1024, file);
+  let mut at = reader.stream_position().ok()?;
+  let mut read_into = |pos: u64, buf: &mut [u8]| -> Option<()> {
+    reader.seek_relative(pos as i64 - at as i64).ok()?;
+    reader.read_exact(buf).ok()?;
+    at = pos + buf.len() as u64;
+    Some(())
+  };
+
+  let mut times = Vec::new();
+  let mut cluster_time = 0i64;
+  let mut group_end = None;
+  let mut group_key = None;
+  let mut buf = [0u8; 16];
+  let mut pos = segment.first_cluster?;
+  while pos < segment.end {
+    // A BlockGroup is a keyframe when it carries no ReferenceBlock.
+    if group_end.is_some_and(|end| pos >= end) {
+      if let Some(t) = group_key.take() {
+        times.push(t);
+      }
+      group_end = None;
+    }
+    let head = &mut buf[..16.min(segment.end - pos) as usize];
+    read_into(pos, head)?;
+    let (id, size, header_len) = ebml_header(head, 0)?;
+    let body_pos = pos + header_len as u64;
+    match (id, size) {
+      // Descend into clusters (possibly of unknown size) and block groups.
+      (MKV_CLUSTER, _) => {
+        pos = body_pos;
+        continue;
+      }
+      (MKV_BLOCK_GROUP, Some(size)) => {
+        group_end = Some(body_pos + size);
+        group_key = None;
+        pos = body_pos;
+        continue;
+      }
+      (MKV_CLUSTER_TIMESTAMP, Some(size)) if size <= 8 => {
+        let body = &mut buf[..size as usize];
+        read_into(body_pos, body)?;
+        cluster_time = be_uint(body) as i64;
+      }
+      (MKV_SIMPLE_BLOCK | MKV_BLOCK, Some(size)) => {
+        let body = &mut buf[..8.min(size) as usize];
+        read_into(body_pos, body)?;
+        let (block_track, relative, flags) = matroska_block_head(body)?;
+        let is_simple_key = id == MKV_SIMPLE_BLOCK && flags & 0x80 != 0;
+        if block_track == track {
+          if is_simple_key {
+            times.push(cluster_time + relative);
+          } else if id == MKV_BLOCK {
+            group_key = Some(cluster_time + relative);
+          }
+        }
+      }
+      (MKV_REFERENCE_BLOCK, Some(_)) => group_key = None,
+      (_, Some(_)) => {}
+      (_, None) => return None,
+    }
+    pos = body_pos + size?;
+  }
+  if let Some(t) = group_key {
+    times.push(t);
+  }
+  Some(times)
+}
+
+fn matroska_keyframes(file: &mut File, file_len: u64, scan_blocks: bool) -> Option<Vec<f64>> {
+  let segment = open_matroska(file, file_len)?;
+  let info = matroska_element(file, &segment, MKV_INFO)?;
+  let tracks = matroska_element(file, &segment, MKV_TRACKS)?;
+  let timestamp_scale = ebml_child(&info, MKV_TIMESTAMP_SCALE).map(be_uint).unwrap_or(1_000_000);
+  let track = matroska_video_track(&tracks)?;
+
+  let cue_points = matroska_element(file, &segment, MKV_CUES)
+    .map(|cues| matroska_cue_points(&cues, track))
+    .unwrap_or_default();
+  let complete_cues = !cue_points.is_empty()
+    && matroska_cues_are_complete(file, &segment, &cue_points, track).unwrap_or(false);
+  let times = if complete_cues {
+    cue_points.into_iter().map(|(t, _)| t).collect()
+  } else if scan_blocks {
+    matroska_block_scan(file, &segment, track)?
+  } else {
+    return None;
+  };
+  Some(times.into_iter().map(|t| t as f64 * timestamp_scale as f64 / 1e9).collect())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::path::PathBuf;
+
+  // Hand-built container headers, written to a temp file because the readers work on files.
+  struct TempFile(PathBuf);
+
+  impl TempFile {
+    fn new(name: &str, bytes: &[u8]) -> TempFile {
+      let path = std::env::temp_dir().join(format!("clipwave-native-{name}-{}", std::process::id()));
+      std::fs::write(&path, bytes).unwrap();
+      TempFile(path)
+    }
+  }
+
+  impl Drop for TempFile {
+    fn drop(&mut self) {
+      let _ = std::fs::remove_file(&self.0);
+    }
+  }
+
+  fn u16be(v: u16) -> Vec<u8> {
+    v.to_be_bytes().to_vec()
+  }
+
+  fn u32be(v: u32) -> Vec<u8> {
+    v.to_be_bytes().to_vec()
+  }
+
+  fn mp4_box(kind: &[u8; 4], parts: &[Vec<u8>]) -> Vec<u8> {
+    let body = parts.concat();
+    [u32be(body.len() as u32 + 8), kind.to_vec(), body].concat()
+  }
+
+  // Version 0 full box: version/flags, then the fields.
+  fn mp4_full_box(kind: &[u8; 4], fields: &[Vec<u8>]) -> Vec<u8> {
+    mp4_box(kind, &[vec![0; 4], fields.concat()])
+  }
+
+  fn mp4_language_code(lang: &str) -> u16 {
+    lang.bytes().fold(0u16, |acc, c| (acc << 5) | (c - 0x60) as u16)
+  }
+
+  fn mp4_file(traks: &[Vec<u8>]) -> Vec<u8> {
+    let mvhd = mp4_full_box(b"mvhd", &[u32be(0), u32be(0), u32be(1000), u32be(10_000)]);
+    let moov = mp4_box(b"moov", &[&[mvhd][..], traks].concat());
+    [mp4_box(b"ftyp", &[b"isom".to_vec(), u32be(0)]), moov].concat()
+  }
+
+  // A trak with handler, language and one sample entry; `stbl_extra` holds sample tables.
+  fn mp4_trak(id: u32, handler: &[u8; 4], lang: &str, entry: Vec<u8>, stbl_extra: &[Vec<u8>]) -> Vec<u8> {
+    mp4_trak_with(id, handler, lang, entry, stbl_extra, &[])
+  }
+
+  // `trak_extra` holds trak-level boxes such as `edts`.
+  fn mp4_trak_with(
+    id: u32,
+    handler: &[u8; 4],
+    lang: &str,
+    entry: Vec<u8>,
+    stbl_extra: &[Vec<u8>],
+    trak_extra: &[Vec<u8>],
+  ) -> Vec<u8> {
+    let tkhd = mp4_full_box(b"tkhd", &[u32be(0), u32be(0), u32be(id)]);
+    let mdhd = mp4_full_box(
+      b"mdhd",
+      &[u32be(0), u32be(0), u32be(1000), u32be(10_000), u16be(mp4_language_code(lang))],
+    );
+    let hdlr = mp4_full_box(b"hdlr", &[u32be(0), handler.to_vec(), vec![0; 12]]);
+    let stsd = mp4_full_box(b"stsd", &[u32be(1), entry]);
+    let stbl = mp4_box(b"stbl", &[&[stsd][..], stbl_extra].concat());
+    let mdia = mp4_box(b"mdia", &[mdhd, hdlr, mp4_box(b"minf", &[stbl])]);
+    mp4_box(b"trak", &[&[tkhd, mdia][..], trak_extra].concat())
+  }
+
+  fn mp4_ac3_entry(channels: u16) -> Vec<u8> {
+    // Reserved, data reference index, sound version 0, then channels at offset 16.
+    mp4_box(b"ac-3", &[vec![0; 6], u16be(1), vec![0; 8], u16be(channels), vec![0; 10]])
+  }
+
+  fn ebml(id: u32, parts: &[Vec<u8>]) -> Vec<u8> {
+    let body = parts.concat();
+    let id_bytes: Vec<u8> = id.to_be_bytes().into_iter().skip_while(|b| *b == 0).collect();
+    // Eight-byte size vint: marker 0x01, then seven bytes of length.
+    let size = [vec![0x01], (body.len() as u64).to_be_bytes()[1..].to_vec()].concat();
+    [id_bytes, size, body].concat()
+  }
+
+  fn ebml_uint(id: u32, value: u64) -> Vec<u8> {
+    ebml(id, &[value.to_be_bytes().to_vec()])
+  }
+
+  fn ebml_text(id: u32, value: &str) -> Vec<u8> {
+    ebml(id, &[value.as_bytes().to_vec()])
+  }
+
+  // Info with 1 ms timestamps and a 10 s duration; always the first element of the segment.
+  fn matroska_info() -> Vec<u8> {
+    ebml(
+      MKV_INFO as u32,
+      &[ebml_uint(MKV_TIMESTAMP_SCALE as u32, 1_000_000), ebml(MKV_DURATION as u32, &[10_000f64.to_be_bytes().to_vec()])],
+    )
+  }
+
+  // EBML header plus a Segment with Info and the given level-1 elements.
+  fn matroska_file(elements: &[Vec<u8>]) -> Vec<u8> {
+    let header = ebml(EBML_HEADER as u32, &[ebml_text(EBML_DOC_TYPE as u32, "matroska")]);
+    [header, ebml(MKV_SEGMENT as u32, &[&[matroska_info()][..], elements].concat())].concat()
+  }
+
+  fn matroska_track(number: u64, track_type: u64, codec_id: &str, lang: &str) -> Vec<u8> {
+    ebml(
+      MKV_TRACK_ENTRY as u32,
+      &[
+        ebml_uint(MKV_TRACK_NUMBER as u32, number),
+        ebml_uint(MKV_TRACK_TYPE as u32, track_type),
+        ebml_text(MKV_CODEC_ID as u32, codec_id),
+        ebml_text(MKV_LANGUAGE as u32, lang),
+      ],
+    )
+  }
+
+  // (order, index, codec, language) as the app sees it, for comparing native and ffprobe results.
+  type StreamRow = (i32, i32, String, String);
+
+  fn rows(audio: &[AudioStreamInfo], subtitles: &[SubtitleStreamInfo]) -> (Vec<StreamRow>, Vec<StreamRow>) {
+    (
+      audio.iter().map(|s| (s.order, s.index, s.codec_name.clone(), s.language.clone())).collect(),
+      subtitles.iter().map(|s| (s.order, s.index, s.codec_name.clone(), s.language.clone())).collect(),
+    )
+  }
+
+  fn assert_matches_ffprobe(path: &Path, ffprobe_json: &str) {
+    let native = probe(path).expect("native probe");
+    let (audio, subtitles) = crate::parse_streams_from_ffprobe_json(ffprobe_json.as_bytes()).unwrap();
+    assert_eq!(rows(&native.audio_streams, &native.subtitle_streams), rows(&audio, &subtitles));
+  }
+
+  #[test]
+  fn mp4_stream_indexes_follow_trak_order_like_ffprobe() {
+    let file = TempFile::new(
+      "indexes.mp4",
+      &mp4_file(&[
+        mp4_trak(1, b"vide", "und", mp4_box(b"avc1", &[vec![0; 8]]), &[]),
+        mp4_trak(2, b"tmcd", "und", mp4_box(b"tmcd", &[vec![0; 8]]), &[]),
+        mp4_trak(3, b"soun", "eng", mp4_ac3_entry(6), &[]),
+        mp4_trak(4, b"sbtl", "fra", mp4_box(b"tx3g", &[vec![0; 8]]), &[]),
+        mp4_trak(5, b"soun", "jpn", mp4_ac3_entry(2), &[]),
+      ]),
+    );
+    // What ffprobe -show_streams reports for this layout: the timecode track is data stream 1.
+    assert_matches_ffprobe(
+      &file.0,
+      r#"{"streams":[
+        {"index":0,"codec_type":"video","codec_name":"h264"},
+        {"index":1,"codec_type":"data","codec_name":"none"},
+        {"index":2,"codec_type":"audio","codec_name":"ac3","channels":6,"tags":{"language":"eng"}},
+        {"index":3,"codec_type":"subtitle","codec_name":"mov_text","tags":{"language":"fra"}},
+        {"index":4,"codec_type":"audio","codec_name":"ac3","channels":2,"tags":{"language":"jpn"}}
+      ]}"#,
+    );
+  }
+
+  #[test]
+  fn matroska_stream_indexes_follow_track_entry_order_like_ffprobe() {
+    let tracks = ebml(
+      MKV_TRACKS as u32,
+      &[
+        matroska_track(1, 1, "V_MPEG4/ISO/AVC", "und"),
+        matroska_track(2, 2, "A_AC3", "eng"),
+        matroska_track(3, 0x11, "S_TEXT/UTF8", "fre"),
+        matroska_track(4, 2, "A_OPUS", "jpn"),
+      ],
+    );
+    let file = TempFile::new("indexes.mkv", &matroska_file(&[tracks]));
+    assert_matches_ffprobe(
+      &file.0,
+      r#"{"streams":[
+        {"index":0,"codec_type":"video","codec_name":"h264"},
+        {"index":1,"codec_type":"audio","codec_name":"ac3","channels":1,"tags":{"language":"eng"}},
+        {"index":2,"codec_type":"subtitle","codec_name":"subrip","tags":{"language":"fre"}},
+        {"index":3,"codec_type":"audio","codec_name":"opus","channels":1,"tags":{"language":"jpn"}}
+      ]}"#,
+    );
+  }
+
+  // Sample-table boxes: (count, value) runs for stts/ctts, 1-based sample numbers for stss.
+  fn mp4_table(kind: &[u8; 4], runs: &[(u32, u32)]) -> Vec<u8> {
+    let entries: Vec<Vec<u8>> = runs.iter().flat_map(|(n, v)| [u32be(*n), u32be(*v)]).collect();
+    mp4_full_box(kind, &[&[u32be(runs.len() as u32)][..], &entries].concat())
+  }
+
+  fn mp4_stss(samples: &[u32]) -> Vec<u8> {
+    let entries: Vec<Vec<u8>> = samples.iter().map(|n| u32be(*n)).collect();
+    mp4_full_box(b"stss", &[&[u32be(samples.len() as u32)][..], &entries].concat())
+  }
+
+  // Version 0 edit list of (segment duration in movie ticks, media time or -1 for an empty edit).
+  fn mp4_edts(edits: &[(u32, i32)]) -> Vec<u8> {
+    let entries: Vec<Vec<u8>> = edits
+      .iter()
+      .flat_map(|(duration, time)| [u32be(*duration), time.to_be_bytes().to_vec(), u32be(0x0001_0000)])
+      .collect();
+    mp4_box(b"edts", &[mp4_full_box(b"elst", &[&[u32be(edits.len() as u32)][..], &entries].concat())])
+  }
+
+  // Ten 100 ms video samples (timescale 1000) plus whatever tables and edits the test adds.
+  fn mp4_video_keyframes(name: &str, stbl: &[Vec<u8>], edits: Option<&[(u32, i32)]>) -> Option<Vec<f64>> {
+    let tables = [&[mp4_table(b"stts", &[(10, 100)])][..], stbl].concat();
+    let edts: Vec<Vec<u8>> = edits.map(mp4_edts).into_iter().collect();
+    let trak = mp4_trak_with(1, b"vide", "und", mp4_box(b"avc1", &[vec![0; 8]]), &tables, &edts);
+    let file = TempFile::new(name, &mp4_file(&[trak]));
+    keyframes(&file.0)
+  }
+
+  fn assert_times(actual: Option<Vec<f64>>, expected: &[f64]) {
+    let actual = actual.expect("keyframes");
+    assert_eq!(actual.len(), expected.len(), "{actual:?}");
+    for (a, e) in actual.iter().zip(expected) {
+      assert!((a - e).abs() < 1e-9, "{actual:?} != {expected:?}");
+    }
+  }
+
+  #[test]
+  fn mp4_keyframes_come_from_stss_and_stts() {
+    assert_times(mp4_video_keyframes("stss.mp4", &[mp4_stss(&[6, 1])], None), &[0.0, 0.5]);
+    // Without stss every sample is a sync sample.
+    let all: Vec<f64> = (0..10).map(|i| i as f64 / 10.0).collect();
+    assert_times(mp4_video_keyframes("no-stss.mp4", &[], None), &all);
+    // A sample count no file of this size can hold is a corrupt table, not a list to allocate.
+    let huge = mp4_table(b"stts", &[(u32::MAX, 1), (u32::MAX, 1)]);
+    let trak = mp4_trak(1, b"vide", "und", mp4_box(b"avc1", &[vec![0; 8]]), &[huge]);
+    let file = TempFile::new("huge-stts.mp4", &mp4_file(&[trak]));
+    assert!(keyframes(&file.0).is_none());
+  }
+
+  #[test]
+  fn mp4_keyframes_apply_ctts_offsets_and_edit_lists() {
+    let stss = mp4_stss(&[1, 6]);
+    let ctts = mp4_table(b"ctts", &[(5, 200), (5, 100)]);
+    assert_times(mp4_video_keyframes("ctts.mp4", &[stss.clone(), ctts.clone()], None), &[0.2, 0.6]);
+    // The usual B-frame edit cuts the first composition offset off again.
+    assert_times(
+      mp4_video_keyframes("elst.mp4", &[stss.clone(), ctts], Some(&[(1000, 200)])),
+      &[0.0, 0.4],
+    );
+    // A leading empty edit delays the track by its duration in movie ticks.
+    assert_times(
+      mp4_video_keyframes("delay.mp4", std::slice::from_ref(&stss), Some(&[(1500, -1), (1000, 0)])),
+      &[1.5, 2.0],
+    );
+    // Several real edits reorder the timeline; that is left to ffprobe.
+    assert!(mp4_video_keyframes("edits.mp4", &[stss], Some(&[(500, 0), (500, 700)])).is_none());
+  }
+
+  fn matroska_video_tracks() -> Vec<u8> {
+    ebml(
+      MKV_TRACKS as u32,
+      &[matroska_track(1, 1, "V_MPEG4/ISO/AVC", "und"), matroska_track(2, 2, "A_AAC", "eng")],
+    )
+  }
+
+  // Cue points of (track, time, cluster position); ebml_uint is fixed-width, so the size of the
+  // Cues does not depend on the positions.
+  fn matroska_cues(points: &[(u64, u64, u64)]) -> Vec<u8> {
+    let points: Vec<Vec<u8>> = points
+      .iter()
+      .map(|(track, time, cluster)| {
+        ebml(
+          MKV_CUE_POINT as u32,
+          &[
+            ebml_uint(MKV_CUE_TIME as u32, *time),
+            ebml(
+              MKV_CUE_TRACK_POSITIONS as u32,
+              &[ebml_uint(MKV_CUE_TRACK as u32, *track), ebml_uint(MKV_CUE_CLUSTER_POSITION as u32, *cluster)],
+            ),
+          ],
+        )
+      })
+      .collect();
+    ebml(MKV_CUES as u32, &points)
+  }
+
+  // Tracks, Cues and clusters, with each (track, time, cluster index) cue pointing at its cluster.
+  fn matroska_indexed(clusters: &[Vec<u8>], cues: &[(u64, u64, usize)]) -> Vec<Vec<u8>> {
+    let tracks = matroska_video_tracks();
+    let cues_len = matroska_cues(&cues.iter().map(|(t, time, _)| (*t, *time, 0)).collect::<Vec<_>>()).len();
+    let mut offsets = vec![(matroska_info().len() + tracks.len() + cues_len) as u64];
+    for cluster in clusters {
+      offsets.push(offsets.last().unwrap() + cluster.len() as u64);
+    }
+    let points: Vec<(u64, u64, u64)> = cues.iter().map(|(t, time, c)| (*t, *time, offsets[*c])).collect();
+    [vec![tracks, matroska_cues(&points)], clusters.to_vec()].concat()
+  }
+
+  fn matroska_block_body(track: u8, relative_ms: i16, flags: u8) -> Vec<u8> {
+    [vec![0x80 | track], relative_ms.to_be_bytes().to_vec(), vec![flags, 0]].concat()
+  }
+
+  // A cluster opening with a video SimpleBlock (a keyframe or not), then audio and a non-key frame.
+  fn matroska_cluster(time_ms: u64, opens_with_keyframe: bool) -> Vec<u8> {
+    let flags = if opens_with_keyframe { 0x80 } else { 0 };
+    ebml(
+      MKV_CLUSTER as u32,
+      &[
+        ebml_uint(MKV_CLUSTER_TIMESTAMP as u32, time_ms),
+        ebml(MKV_SIMPLE_BLOCK as u32, &[matroska_block_body(1, 0, flags)]),
+        ebml(MKV_SIMPLE_BLOCK as u32, &[matroska_block_body(2, 20, 0x80)]),
+        ebml(MKV_SIMPLE_BLOCK as u32, &[matroska_block_body(1, 40, 0)]),
+      ],
+    )
+  }
+
+  // One-second GOPs, one cluster each.
+  fn matroska_simple_block_clusters(seconds: u64) -> Vec<Vec<u8>> {
+    (0..seconds).map(|s| matroska_cluster(s * 1000, true)).collect()
+  }
+
+  fn matroska_file_of(name: &str, elements: &[Vec<u8>]) -> TempFile {
+    TempFile::new(name, &matroska_file(elements))
+  }
+
+  #[test]
+  fn matroska_keyframes_come_from_cues_for_the_video_track() {
+    let clusters = matroska_simple_block_clusters(10);
+    let mut cues: Vec<(u64, u64, usize)> = (0..10).map(|s| (1, s * 1000, s as usize)).collect();
+    cues.push((2, 20, 0));
+    let file = matroska_file_of("cues.mkv", &matroska_indexed(&clusters, &cues));
+    let expected: Vec<f64> = (0..10).map(f64::from).collect();
+    assert_times(keyframes(&file.0), &expected);
+  }
+
+  #[test]
+  fn matroska_long_gop_cues_are_complete_without_a_scan() {
+    // Ten-second GOPs in five-second clusters: the mid-GOP clusters carry no cue.
+    let clusters: Vec<Vec<u8>> = (0..4).map(|i| matroska_cluster(i * 5000, i % 2 == 0)).collect();
+    let file = matroska_file_of("long-gop.mkv", &matroska_indexed(&clusters, &[(1, 0, 0), (1, 10_000, 2)]));
+    assert_times(keyframes(&file.0), &[0.0, 10.0]);
+  }
+
+  #[test]
+  fn matroska_keyframes_scan_blocks_without_cues() {
+    let group = |relative: i16, reference: bool| {
+      let mut parts = vec![ebml(MKV_BLOCK as u32, &[matroska_block_body(1, relative, 0)])];
+      if reference {
+        parts.push(ebml_uint(MKV_REFERENCE_BLOCK as u32, 40));
+      }
+      ebml(MKV_BLOCK_GROUP as u32, &parts)
+    };
+    let groups = ebml(
+      MKV_CLUSTER as u32,
+      &[ebml_uint(MKV_CLUSTER_TIMESTAMP as u32, 5000), group(0, false), group(40, true), group(80, false)],
+    );
+    let mut elements = vec![matroska_video_tracks()];
+    elements.extend(matroska_simple_block_clusters(2));
+    elements.push(groups);
+    let file = matroska_file_of("blocks.mkv", &elements);
+    // The header-only lookup leaves Cue-less files to the index build.
+    assert!(keyframes(&file.0).is_none());
+    assert_times(scanned_keyframes(&file.0), &[0.0, 1.0, 5.0, 5.08]);
+  }
+
+  #[test]
+  fn matroska_sparse_cues_are_only_scanned_on_the_index_build() {
+    // Cues for two of ten clusters that each open with a keyframe.
+    let clusters = matroska_simple_block_clusters(10);
+    let file = matroska_file_of("sparse-cues.mkv", &matroska_indexed(&clusters, &[(1, 0, 0), (1, 5000, 5)]));
+    assert!(keyframes(&file.0).is_none());
+    let expected: Vec<f64> = (0..10).map(f64::from).collect();
+    assert_times(scanned_keyframes(&file.0), &expected);
+  }
+
+  #[test]
+  fn matroska_cues_without_cluster_positions_are_not_trusted() {
+    let cue = ebml(
+      MKV_CUE_POINT as u32,
+      &[ebml_uint(MKV_CUE_TIME as u32, 0), ebml(MKV_CUE_TRACK_POSITIONS as u32, &[ebml_uint(MKV_CUE_TRACK as u32, 1)])],
+    );
+    let mut elements = vec![matroska_video_tracks(), ebml(MKV_CUES as u32, &[cue])];
+    elements.push(matroska_cluster(0, true));
+    let file = matroska_file_of("unpositioned-cues.mkv", &elements);
+    assert!(keyframes(&file.0).is_none());
+  }
+}
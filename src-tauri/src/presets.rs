@@ -0,0 +1,296 @@
+// Export presets: named bundles of trim settings (mode, container, encoder, scaling, audio, naming).
+//
+// Built-in presets live in code and are always listed first; user presets are stored as a JSON array
+// in `<app config dir>/presets.json`. A user preset cannot reuse a built-in name, so a name always
+// means the same thing to `trim_media`.
+
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{Mutex, OnceLock};
+
+const PRESETS_FILE: &str = "presets.json";
+pub const CONTAINERS: [&str; 5] = ["mp4", "m4v", "mov", "mkv", "webm"];
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct VideoSettings {
+  // ffmpeg encoder, e.g. libx264 or libx265.
+  pub codec: String,
+  pub crf: Option<u32>,
+  // Encoder speed preset (`-preset`), e.g. veryfast or slow.
+  pub speed: Option<String>,
+  pub pixel_format: Option<String>,
+  // Fit the whole clip into this many megabytes with a computed average bitrate; replaces `crf`.
+  pub target_size_mb: Option<f64>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AudioSettings {
+  // ffmpeg encoder ("aac", "libopus", ...) or "copy".
+  pub codec: String,
+  pub bitrate_kbps: Option<u32>,
+  pub channels: Option<u32>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ExportPreset {
+  pub name: String,
+  // "lossless" or "exact". Video, scaling and audio settings need Exact.
+  pub mode: String,
+  // Output extension; None keeps the source's.
+  pub container: Option<String>,
+  pub video: Option<VideoSettings>,
+  // Downscale to at most this many pixels high, keeping the aspect ratio.
+  pub max_height: Option<u32>,
+  pub audio: Option<AudioSettings>,
+  pub name_template: Option<String>,
+  // Appended right before the output file (see `TrimOptions::output_args`).
+  pub output_args: Vec<String>,
+  // Set on listed built-ins; ignored on save.
+  #[serde(skip_deserializing)]
+  pub builtin: bool,
+}
+
+fn faststart() -> Vec<String> {
+  vec!["-movflags".to_string(), "+faststart".to_string()]
+}
+
+pub fn builtin_presets() -> Vec<ExportPreset> {
+  vec![
+    ExportPreset {
+      name: "Discord 1080p H.264 under 25MB".to_string(),
+      mode: "exact".to_string(),
+      container: Some("mp4".to_string()),
+      video: Some(VideoSettings {
+        codec: "libx264".to_string(),
+        crf: None,
+        speed: Some("medium".to_string()),
+        pixel_format: Some("yuv420p".to_string()),
+        target_size_mb: Some(25.0),
+      }),
+      max_height: Some(1080),
+      audio: Some(AudioSettings {
+        codec: "aac".to_string(),
+        bitrate_kbps: Some(128),
+        channels: Some(2),
+      }),
+      name_template: Some("{stem}_{in}-{out}_discord.{ext}".to_string()),
+      output_args: faststart(),
+      builtin: true,
+    },
+    ExportPreset {
+      name: "Archive HEVC".to_string(),
+      mode: "exact".to_string(),
+      container: Some("mkv".to_string()),
+      video: Some(VideoSettings {
+        codec: "libx265".to_string(),
+        crf: Some(20),
+        speed: Some("slow".to_string()),
+        pixel_format: None,
+        target_size_mb: None,
+      }),
+      max_height: None,
+      audio: Some(AudioSettings {
+        codec: "copy".to_string(),
+        bitrate_kbps: None,
+        channels: None,
+      }),
+      name_template: Some("{stem}_{in}-{out}_archive.{ext}".to_string()),
+      output_args: Vec::new(),
+      builtin: true,
+    },
+    ExportPreset {
+      name: "WhatsApp 720p".to_string(),
+      mode: "exact".to_string(),
+      container: Some("mp4".to_string()),
+      video: Some(VideoSettings {
+        codec: "libx264".to_string(),
+        crf: Some(23),
+        speed: Some("veryfast".to_string()),
+        pixel_format: Some("yuv420p".to_string()),
+        target_size_mb: None,
+      }),
+      max_height: Some(720),
+      audio: Some(AudioSettings {
+        codec: "aac".to_string(),
+        bitrate_kbps: Some(128),
+        channels: Some(2),
+      }),
+      name_template: Some("{stem}_{in}-{out}_whatsapp.{ext}".to_string()),
+      output_args: faststart(),
+      builtin: true,
+    },
+  ]
+}
+
+fn is_builtin_name(name: &str) -> bool {
+  builtin_presets().iter().any(|p| p.name.eq_ignore_ascii_case(name.trim()))
+}
+
+// Serializes read-modify-write of the presets file.
+fn store_lock() -> &'static Mutex<()> {
+  static LOCK: OnceLock<Mutex<()>> = OnceLock::new();
+  LOCK.get_or_init(|| Mutex::new(()))
+}
+
+fn store_path() -> Option<PathBuf> {
+  crate::app_config_dir().map(|d| d.join(PRESETS_FILE))
+}
+
+// A missing or unreadable file reads as "no user presets" rather than failing every export.
+fn read_user_presets() -> Vec<ExportPreset> {
+  store_path()
+    .and_then(|path| fs::read(path).ok())
+    .and_then(|bytes| serde_json::from_slice::<Vec<ExportPreset>>(&bytes).ok())
+    .unwrap_or_default()
+}
+
+fn write_user_presets(presets: &[ExportPreset]) -> Result<(), String> {
+  let path = store_path().ok_or_else(|| "App config folder is not available".to_string())?;
+  if let Some(dir) = path.parent() {
+    fs::create_dir_all(dir).map_err(|e| format!("Failed to create config folder: {e}"))?;
+  }
+  let json = serde_json::to_vec_pretty(presets).map_err(|e| format!("Failed to serialize presets: {e}"))?;
+  let tmp = path.with_extension("json.tmp");
+  fs::write(&tmp, json).map_err(|e| format!("Failed to save presets: {e}"))?;
+  fs::rename(&tmp, &path).map_err(|e| format!("Failed to save presets: {e}"))
+}
+
+/// Built-ins followed by the user's presets.
+pub fn list() -> Vec<ExportPreset> {
+  let _guard = store_lock().lock();
+  let mut presets = builtin_presets();
+  presets.extend(read_user_presets());
+  presets
+}
+
+/// Preset by name, ignoring case.
+pub fn find(name: &str) -> Option<ExportPreset> {
+  list().into_iter().find(|p| p.name.eq_ignore_ascii_case(name.trim()))
+}
+
+// Whether `container` can hold a stream from this encoder. Matroska takes anything; "copy" is the
+// source's codec, which only the export itself can check.
+fn codec_fits(container: &str, codec: &str, video: bool) -> bool {
+  let codec = codec.trim().to_lowercase();
+  let is = |families: &[&str]| families.iter().any(|f| codec == *f || (f.ends_with('_') && codec.starts_with(f)));
+  const WEBM_VIDEO: &[&str] = &["libvpx", "libvpx-vp9", "libaom-av1", "libsvtav1", "librav1e", "av1_"];
+  const WEBM_AUDIO: &[&str] = &["libopus", "opus", "libvorbis", "vorbis"];
+  const MP4_VIDEO: &[&str] = &[
+    "libx264", "libx265", "h264_", "hevc_", "libaom-av1", "libsvtav1", "librav1e", "av1_", "libvpx-vp9", "mpeg4",
+    "libxvid",
+  ];
+  const MP4_AUDIO: &[&str] = &["aac", "aac_", "libfdk_aac", "libmp3lame", "ac3", "eac3", "libopus", "opus", "flac", "alac"];
+  const MOV_ONLY: &[&str] = &["prores", "prores_", "dnxhd", "pcm_"];
+  if codec == "copy" {
+    return true;
+  }
+  match (container, video) {
+    ("mkv", _) => true,
+    ("webm", true) => is(WEBM_VIDEO),
+    ("webm", false) => is(WEBM_AUDIO),
+    ("mp4" | "m4v", true) => is(MP4_VIDEO),
+    ("mp4" | "m4v", false) => is(MP4_AUDIO),
+    ("mov", true) => is(MP4_VIDEO) || is(MOV_ONLY),
+    ("mov", false) => is(MP4_AUDIO) || is(MOV_ONLY),
+    _ => true,
+  }
+}
+
+/// Reject encoders the container cannot hold, e.g. H.264 or AAC in WebM.
+pub fn check_codecs(container: &str, video: Option<&VideoSettings>, audio: Option<&AudioSettings>) -> Result<(), String> {
+  let container = container.trim().to_lowercase();
+  if let Some(video) = video.filter(|v| !codec_fits(&container, &v.codec, true)) {
+    return Err(format!("A .{container} file cannot hold {} video", video.codec.trim()));
+  }
+  if let Some(audio) = audio.filter(|a| !codec_fits(&container, &a.codec, false)) {
+    return Err(format!("A .{container} file cannot hold {} audio", audio.codec.trim()));
+  }
+  Ok(())
+}
+
+pub fn validate(preset: &ExportPreset) -> Result<(), String> {
+  if preset.name.trim().is_empty() {
+    return Err("Preset name cannot be empty".to_string());
+  }
+  let mode = preset.mode.trim().to_lowercase();
+  if mode != "lossless" && mode != "exact" {
+    return Err("Preset mode must be 'lossless' or 'exact'".to_string());
+  }
+  if mode == "lossless" && (preset.video.is_some() || preset.audio.is_some() || preset.max_height.is_some()) {
+    return Err("Video, scaling and audio settings need Exact mode".to_string());
+  }
+  if let Some(container) = &preset.container {
+    if mode == "lossless" {
+      return Err("Lossless presets keep the source container; use Exact mode to change it".to_string());
+    }
+    if !CONTAINERS.contains(&container.trim().to_lowercase().as_str()) {
+      return Err(format!("Unsupported container '{container}' (expected one of {})", CONTAINERS.join(", ")));
+    }
+    check_codecs(container, preset.video.as_ref(), preset.audio.as_ref())?;
+  }
+  if let Some(template) = &preset.name_template {
+    crate::validate_output_template(template)?;
+  }
+  if let Some(video) = &preset.video {
+    if video.codec.trim().is_empty() {
+      return Err("Video codec cannot be empty".to_string());
+    }
+    if video.crf.is_some_and(|crf| crf > 63) {
+      return Err("CRF must be between 0 and 63".to_string());
+    }
+    if video.target_size_mb.is_some_and(|mb| !mb.is_finite() || mb <= 0.0) {
+      return Err("Target size must be a positive number of megabytes".to_string());
+    }
+  }
+  if preset.max_height.is_some_and(|h| !(16..=8640).contains(&h)) {
+    return Err("Maximum height must be between 16 and 8640 pixels".to_string());
+  }
+  if let Some(audio) = &preset.audio {
+    if audio.codec.trim().is_empty() {
+      return Err("Audio codec cannot be empty".to_string());
+    }
+    if audio.channels.is_some_and(|c| !(1..=8).contains(&c)) {
+      return Err("Audio channels must be between 1 and 8".to_string());
+    }
+  }
+  crate::validate_extra_ffmpeg_args("preset output", &preset.output_args)
+}
+
+/// Create or replace the user preset with this name.
+pub fn save(mut preset: ExportPreset) -> Result<ExportPreset, String> {
+  preset.name = preset.name.trim().to_string();
+  preset.mode = preset.mode.trim().to_lowercase();
+  preset.container = preset.container.map(|c| c.trim().to_lowercase()).filter(|c| !c.is_empty());
+  preset.builtin = false;
+  validate(&preset)?;
+  if is_builtin_name(&preset.name) {
+    return Err(format!("'{}' is a built-in preset; save your changes under a new name", preset.name));
+  }
+
+  let _guard = store_lock().lock();
+  let mut presets = read_user_presets();
+  match presets.iter_mut().find(|p| p.name.eq_ignore_ascii_case(&preset.name)) {
+    Some(existing) => *existing = preset.clone(),
+    None => presets.push(preset.clone()),
+  }
+  write_user_presets(&presets)?;
+  Ok(preset)
+}
+
+pub fn delete(name: &str) -> Result<(), String> {
+  if is_builtin_name(name) {
+    return Err(format!("'{}' is a built-in preset and cannot be deleted", name.trim()));
+  }
+  let _guard = store_lock().lock();
+  let mut presets = read_user_presets();
+  let before = presets.len();
+  presets.retain(|p| !p.name.eq_ignore_ascii_case(name.trim()));
+  if presets.len() == before {
+    return Err(format!("No preset named '{}'", name.trim()));
+  }
+  write_user_presets(&presets)
+}
@@ -0,0 +1,273 @@
+// Timecode parsing and formatting.
+//
+// Accepted input forms:
+//   h:mm:ss, h:mm:ss.fff, mm:ss[.fff]   clock time
+//   hh:mm:ss:ff                          SMPTE non-drop-frame
+//   hh:mm:ss;ff                          SMPTE drop-frame (29.97 / 59.94 only)
+//   1234f, #1234                         frame number (0-based)
+//   83.5, 83.5s                          plain seconds
+//
+// SMPTE and frame numbers need the video frame rate. Frame N starts at N / fps seconds;
+// SMPTE labels count at the nominal (rounded) rate, so 23.976 uses 24 labels per second.
+
+use serde::Deserialize;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TimecodeFormat {
+  Clock,
+  Smpte,
+  SmpteDropFrame,
+  Frames,
+  Seconds,
+}
+
+// Tolerance when mapping a timestamp to the frame that contains it (in frames).
+const FRAME_EPSILON: f64 = 1e-3;
+
+fn frame_number_digits(t: &str) -> Option<&str> {
+  t.strip_prefix('#').or_else(|| t.strip_suffix(['f', 'F']))
+}
+
+fn is_smpte(t: &str) -> bool {
+  t.contains(';') || t.matches(':').count() == 3
+}
+
+/// True if parsing `input` requires the frame rate (SMPTE or frame number).
+pub fn needs_frame_rate(input: &str) -> bool {
+  let t = input.trim();
+  is_smpte(t) || frame_number_digits(t).is_some()
+}
+
+/// Parse any supported timecode form into seconds.
+pub fn parse(input: &str, fps: Option<f64>) -> Result<f64, String> {
+  let t = input.trim();
+  if t.is_empty() {
+    return Err("Time is empty".to_string());
+  }
+
+  if let Some(digits) = frame_number_digits(t) {
+    let fps = require_fps(fps, &format!("Frame number '{t}'"))?;
+    let frame = parse_field(digits.trim(), "frame number")?;
+    return Ok(frame as f64 / fps);
+  }
+  if is_smpte(t) {
+    return parse_smpte(t, require_fps(fps, &format!("Timecode '{t}'"))?);
+  }
+  if t.contains(':') {
+    return parse_clock(t);
+  }
+
+  let number = t.strip_suffix(['s', 'S']).unwrap_or(t).trim();
+  if number.is_empty() || !number.chars().all(|c| c.is_ascii_digit() || c == '.') {
+    return Err(format!(
+      "Unrecognized time '{t}'. Use hh:mm:ss.mmm, hh:mm:ss:ff, hh:mm:ss;ff, a frame number like 1234f, or seconds"
+    ));
+  }
+  number.parse::<f64>().map_err(|_| format!("Invalid seconds '{t}'"))
+}
+
+/// Parse clock time: `h:mm:ss[.fff]` or `mm:ss[.fff]`.
+pub fn parse_clock(input: &str) -> Result<f64, String> {
+  let parts: Vec<&str> = input.trim().split(':').collect();
+  let (h, m, s) = match parts.as_slice() {
+    [h, m, s] => (*h, *m, *s),
+    [m, s] => ("0", *m, *s),
+    _ => return Err("Time must be in format hh:mm:ss or hh:mm:ss.milliseconds".to_string()),
+  };
+
+  let hours = parse_field(h, "hours")?;
+  if m.len() > 2 {
+    return Err("Invalid minutes (at most 2 digits)".to_string());
+  }
+  let minutes = parse_field(m, "minutes")?;
+
+  if s.is_empty() || !s.chars().all(|c| c.is_ascii_digit() || c == '.') {
+    return Err("Invalid seconds".to_string());
+  }
+  let seconds: f64 = s.parse().map_err(|_| "Invalid seconds".to_string())?;
+
+  if minutes >= 60 || seconds >= 60.0 {
+    return Err("Minutes and seconds must be < 60".to_string());
+  }
+
+  Ok(hours as f64 * 3600.0 + minutes as f64 * 60.0 + seconds)
+}
+
+/// Format seconds as hh:mm:ss.mmm (the inverse of `parse_clock`).
+pub fn format_clock(seconds: f64) -> String {
+  let total_ms = (seconds.max(0.0) * 1000.0).round() as u64;
+  let hours = total_ms / 3_600_000;
+  let minutes = (total_ms / 60_000) % 60;
+  let secs = (total_ms / 1000) % 60;
+  let millis = total_ms % 1000;
+  format!("{hours:02}:{minutes:02}:{secs:02}.{millis:03}")
+}
+
+/// Format seconds in the requested form.
+pub fn format(seconds: f64, format: TimecodeFormat, fps: Option<f64>) -> Result<String, String> {
+  let seconds = seconds.max(0.0);
+  match format {
+    TimecodeFormat::Clock => Ok(format_clock(seconds)),
+    TimecodeFormat::Seconds => Ok(format!("{seconds:.3}")),
+    TimecodeFormat::Frames => {
+      let fps = require_fps(fps, "Frame numbers")?;
+      Ok(format!("{}f", frame_at(seconds, fps)))
+    }
+    TimecodeFormat::Smpte => {
+      let fps = require_fps(fps, "SMPTE timecode")?;
+      Ok(format_smpte_labels(frame_at(seconds, fps), nominal_rate(fps), ':'))
+    }
+    TimecodeFormat::SmpteDropFrame => {
+      let fps = require_fps(fps, "Drop-frame timecode")?;
+      let nominal = drop_frame_nominal_rate(fps)?;
+      let drop = nominal / 15;
+      let frames_per_minute = nominal * 60 - drop;
+      let frames_per_ten_minutes = nominal * 600 - drop * 9;
+
+      // Add back the labels skipped so far, then label as if non-drop.
+      let frame = frame_at(seconds, fps);
+      let tens = frame / frames_per_ten_minutes;
+      let rem = frame % frames_per_ten_minutes;
+      let mut label = frame + drop * 9 * tens;
+      if rem > drop {
+        label += drop * ((rem - drop) / frames_per_minute);
+      }
+      Ok(format_smpte_labels(label, nominal, ';'))
+    }
+  }
+}
+
+/// Index of the frame that contains `seconds`.
+pub fn frame_at(seconds: f64, fps: f64) -> u64 {
+  (seconds.max(0.0) * fps + FRAME_EPSILON).floor() as u64
+}
+
+fn require_fps(fps: Option<f64>, what: &str) -> Result<f64, String> {
+  match fps {
+    Some(fps) if fps.is_finite() && fps > 0.0 => Ok(fps),
+    _ => Err(format!(
+      "{what} needs the video frame rate, which could not be determined for this file"
+    )),
+  }
+}
+
+fn parse_field(text: &str, what: &str) -> Result<u64, String> {
+  if text.is_empty() || !text.chars().all(|c| c.is_ascii_digit()) {
+    return Err(format!("Invalid {what}"));
+  }
+  text.parse().map_err(|_| format!("Invalid {what}"))
+}
+
+fn nominal_rate(fps: f64) -> u64 {
+  (fps.round() as u64).max(1)
+}
+
+// Drop-frame only exists for NTSC rates (30000/1001 and 60000/1001).
+fn drop_frame_nominal_rate(fps: f64) -> Result<u64, String> {
+  let nominal = nominal_rate(fps);
+  let ntsc = nominal as f64 * 1000.0 / 1001.0;
+  if (nominal == 30 || nominal == 60) && (fps - ntsc).abs() < 0.01 {
+    Ok(nominal)
+  } else {
+    Err(format!("Drop-frame timecode requires 29.97 or 59.94 fps (file is {fps:.3} fps)"))
+  }
+}
+
+fn parse_smpte(t: &str, fps: f64) -> Result<f64, String> {
+  let drop_frame = t.contains(';');
+  let parts: Vec<&str> = t.split([':', ';']).collect();
+  let [h, m, s, f] = parts.as_slice() else {
+    return Err("SMPTE timecode must be hh:mm:ss:ff (or hh:mm:ss;ff for drop-frame)".to_string());
+  };
+
+  let hours = parse_field(h, "hours")?;
+  let minutes = parse_field(m, "minutes")?;
+  let seconds = parse_field(s, "seconds")?;
+  let frames = parse_field(f, "frames")?;
+  if minutes >= 60 || seconds >= 60 {
+    return Err("Minutes and seconds must be < 60".to_string());
+  }
+
+  let nominal = if drop_frame { drop_frame_nominal_rate(fps)? } else { nominal_rate(fps) };
+  if frames >= nominal {
+    return Err(format!("Frame field must be < {nominal} at {fps:.3} fps"));
+  }
+
+  let mut frame = (hours * 3600 + minutes * 60 + seconds) * nominal + frames;
+  if drop_frame {
+    let drop = nominal / 15;
+    if seconds == 0 && minutes % 10 != 0 && frames < drop {
+      return Err(format!("{t} does not exist in drop-frame timecode"));
+    }
+    let total_minutes = hours * 60 + minutes;
+    frame -= drop * (total_minutes - total_minutes / 10);
+  }
+
+  Ok(frame as f64 / fps)
+}
+
+fn format_smpte_labels(label: u64, nominal: u64, frame_separator: char) -> String {
+  let frames = label % nominal;
+  let total_seconds = label / nominal;
+  let hours = total_seconds / 3600;
+  let minutes = (total_seconds / 60) % 60;
+  let seconds = total_seconds % 60;
+  format!("{hours:02}:{minutes:02}:{seconds:02}{frame_separator}{frames:02}")
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const NTSC_30: f64 = 30000.0 / 1001.0;
+  const NTSC_60: f64 = 60000.0 / 1001.0;
+
+  fn df_label(frame: u64, fps: f64) -> String {
+    format(frame as f64 / fps, TimecodeFormat::SmpteDropFrame, Some(fps)).unwrap()
+  }
+
+  fn df_frame(label: &str, fps: f64) -> u64 {
+    frame_at(parse(label, Some(fps)).unwrap(), fps)
+  }
+
+  #[test]
+  fn drop_frame_labels_skip_at_each_minute_but_not_every_tenth() {
+    for (fps, last, first_after_drop, ten_minutes, hour) in [
+      (NTSC_30, "00:00:59;29", "00:01:00;02", 17_982, 107_892),
+      (NTSC_60, "00:00:59;59", "00:01:00;04", 35_964, 215_784),
+    ] {
+      let per_minute = nominal_rate(fps) * 60;
+      assert_eq!(df_label(per_minute - 1, fps), last);
+      assert_eq!(df_label(per_minute, fps), first_after_drop);
+      assert_eq!(df_frame(first_after_drop, fps), per_minute);
+      assert_eq!(df_label(ten_minutes, fps), "00:10:00;00");
+      assert_eq!(df_frame("00:10:00;00", fps), ten_minutes);
+      assert_eq!(df_frame("01:00:00;00", fps), hour);
+    }
+    assert!(parse("00:01:00;00", Some(NTSC_30)).unwrap_err().contains("does not exist"));
+    assert!(parse("00:01:00;03", Some(NTSC_60)).unwrap_err().contains("does not exist"));
+  }
+
+  #[test]
+  fn drop_frame_round_trips_across_minute_and_ten_minute_boundaries() {
+    for fps in [NTSC_30, NTSC_60] {
+      let per_minute = nominal_rate(fps) * 60 - nominal_rate(fps) / 15;
+      let ten_minutes = per_minute * 10 + nominal_rate(fps) / 15;
+      let boundaries = [nominal_rate(fps) * 60, ten_minutes, ten_minutes + per_minute, ten_minutes * 6];
+      for boundary in boundaries {
+        for frame in boundary - 10..boundary + 10 {
+          let label = df_label(frame, fps);
+          assert_eq!(df_frame(&label, fps), frame, "{label} at {fps:.2} fps");
+        }
+      }
+    }
+  }
+
+  #[test]
+  fn drop_frame_needs_an_ntsc_rate() {
+    assert!(parse("00:01:00;02", Some(25.0)).unwrap_err().contains("29.97 or 59.94"));
+    assert!(format(60.0, TimecodeFormat::SmpteDropFrame, Some(24.0)).is_err());
+    assert_eq!(format(60.06, TimecodeFormat::Smpte, Some(NTSC_30)).unwrap(), "00:01:00:00");
+  }
+}
@@ -1114,6 +1114,11 @@ function App() {
 
       setSubsStatus('loading')
 
+      // Background pre-analysis (keyframes, thumbnails, waveform); replaces the previous file's run.
+      invoke('analyze_file', { inputPath: path, ffmpegBinDir }).catch((e) => {
+        logDebug(`Background analysis not started: ${String(e)}`, 'info')
+      })
+
       logUser('Probing duration…', 'info')
       const durationResult = await invoke('probe_duration', {
         inputPath: path,
@@ -1219,7 +1224,7 @@ function App() {
             : ''
 
         pushStatus(
-          `⏱ Validation: ${t.validation_ms.toFixed(1)}ms | Resolve: ${t.resolve_binaries_ms.toFixed(1)}ms | FFprobe: ${t.ffprobe_execution_ms.toFixed(1)}ms | Parse: ${t.json_parsing_ms.toFixed(1)}ms | Total: ${t.total_ms.toFixed(1)}ms${extra}`,
+          `⏱ Validation: ${t.validation_ms.toFixed(1)}ms | Resolve: ${t.resolve_binaries_ms.toFixed(1)}ms | FFprobe: ${t.ffprobe_execution_ms.toFixed(1)}ms | Parse: ${t.json_parsing_ms.toFixed(1)}ms | Frames: ${(t.frame_sampling_ms ?? 0).toFixed(1)}ms | Total: ${t.total_ms.toFixed(1)}ms${extra}`,
           'info'
         )
       }
@@ -1392,13 +1397,15 @@ function App() {
     try {
       const cutStart = performance.now()
       const result = await invoke('trim_media', {
-        inputPath,
-        inTime,
-        outTime,
-        mode,
-        audioStreamIndex: selectedAudioIndex,
-        subtitleStreamIndex: selectedSubtitleIndex,
-        ffmpegBinDir,
+        request: {
+          input_path: inputPath,
+          in_time: inTime,
+          out_time: outTime,
+          mode,
+          audio_stream_index: selectedAudioIndex,
+          subtitle_stream_index: selectedSubtitleIndex,
+          ffmpeg_bin_dir: ffmpegBinDir,
+        },
       })
       const cutSec = ((performance.now() - cutStart) / 1000).toFixed(3)
 